@@ -0,0 +1,46 @@
+//! Samples the voltage on PA0 at exactly 1 kHz: every update event of TIM3 starts a conversion,
+//! whose result is moved into a buffer by the DMA
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+#[macro_use(singleton)]
+extern crate cortex_m;
+extern crate stm32f103xx_hal as hal;
+
+use cortex_m::asm;
+use hal::adc::{Adc, ExternalTrigger};
+use hal::prelude::*;
+use hal::stm32f103xx;
+use hal::timer::{MasterMode, Timer};
+
+fn main() {
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let channels = dp.DMA1.split(&mut rcc.ahb);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let _pa0 = gpioa.pa0.into_analog(&mut gpioa.crl);
+
+    let adc = Adc::adc1(dp.ADC1, clocks, &mut rcc.apb2);
+    let buffer = singleton!(: [u16; 256] = [0; 256]).unwrap();
+
+    // PA0 is channel 0
+    let transfer =
+        adc.read_scan_dma_triggered(&[0], ExternalTrigger::Tim3Trgo, channels.1, buffer);
+
+    let mut timer = Timer::tim3(dp.TIM3, 1.khz(), clocks, &mut rcc.apb1);
+    timer.set_master_mode(MasterMode::Update);
+
+    let (_buffer, _c1, adc) = transfer.wait();
+    let _adc = adc.stop();
+
+    // 256 ms worth of samples
+    asm::bkpt();
+}
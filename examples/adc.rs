@@ -0,0 +1,42 @@
+//! Reads the voltage of a potentiometer connected to PA0
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate cortex_m_semihosting as semihosting;
+extern crate stm32f103xx_hal as hal;
+
+use core::fmt::Write;
+
+use hal::adc::Adc;
+use hal::delay::Delay;
+use hal::prelude::*;
+use hal::stm32f103xx;
+use semihosting::hio;
+
+fn main() {
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    let mut pot = gpioa.pa0.into_analog(&mut gpioa.crl);
+
+    let mut adc = Adc::adc1(dp.ADC1, clocks, &mut rcc.apb2);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let mut hstdout = hio::hstdout().unwrap();
+    loop {
+        let value = adc.read(&mut pot);
+
+        writeln!(hstdout, "{}", value).unwrap();
+
+        delay.delay_ms(500_u16);
+    }
+}
@@ -0,0 +1,33 @@
+//! Counts the number of resets in a backup data register
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate cortex_m_semihosting as semihosting;
+extern crate stm32f103xx_hal as hal;
+
+use core::fmt::Write;
+
+use cortex_m::asm;
+use hal::prelude::*;
+use hal::stm32f103xx;
+use semihosting::hio;
+
+fn main() {
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut rcc = dp.RCC.constrain();
+
+    let mut pwr = dp.PWR.constrain(&mut rcc.apb1);
+    let mut backup_domain = dp.BKP.constrain(&mut rcc.apb1, &mut pwr);
+
+    let count = backup_domain.read_data_register(0).wrapping_add(1);
+    backup_domain.write_data_register(0, count).unwrap();
+
+    let mut hstdout = hio::hstdout().unwrap();
+    writeln!(hstdout, "reboots: {}", count).unwrap();
+
+    asm::bkpt();
+}
@@ -34,8 +34,8 @@ fn main() {
     let mut timer = Timer::syst(cp.SYST, 1.hz(), clocks);
     loop {
         block!(timer.wait()).unwrap();
-        led.set_high();
+        led.set_high().unwrap();
         block!(timer.wait()).unwrap();
-        led.set_low();
+        led.set_low().unwrap();
     }
 }
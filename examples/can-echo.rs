@@ -0,0 +1,51 @@
+//! Echoes every CAN frame received back onto the bus, at 500 kbit/s
+//!
+//! Connect a CAN transceiver to PA12 (TX) and PA11 (RX)
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+#[macro_use(block)]
+extern crate nb;
+extern crate stm32f103xx_hal as hal;
+
+use hal::can::{Can, Fifo};
+use hal::prelude::*;
+use hal::stm32f103xx;
+
+fn main() {
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr
+        .use_hse(8.mhz())
+        .sysclk(72.mhz())
+        .pclk1(36.mhz())
+        .freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+
+    let tx = gpioa.pa12.into_alternate_push_pull(&mut gpioa.crh);
+    let rx = gpioa.pa11;
+
+    let mut can = Can::can1(
+        dp.CAN,
+        (tx, rx),
+        &mut afio.mapr,
+        500_000.bps(),
+        clocks,
+        &mut rcc.apb1,
+    );
+
+    loop {
+        if let Ok(frame) = block!(can.receive(Fifo::Fifo0)) {
+            block!(can.transmit(&frame)).ok();
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Outputs a sawtooth wave on PA4 and a constant voltage on PA5
+//!
+//! NOTE requires a high density device (e.g. STM32F103RC)
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate stm32f103xx_hal as hal;
+
+use hal::dac::Alignment;
+use hal::prelude::*;
+use hal::stm32f103xx;
+
+fn main() {
+    let p = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut rcc = p.RCC.constrain();
+    let mut gpioa = p.GPIOA.split(&mut rcc.apb2);
+
+    let pa4 = gpioa.pa4.into_analog(&mut gpioa.crl);
+    let pa5 = gpioa.pa5.into_analog(&mut gpioa.crl);
+
+    let (mut c1, mut c2) = p.DAC.dac((pa4, pa5), &mut rcc.apb1);
+
+    c1.enable();
+    c2.enable();
+
+    // half of VREF+
+    c2.set_value_aligned(0x80, Alignment::Right8);
+
+    let mut value = 0;
+    loop {
+        c1.set_value(value);
+        value = (value + 1) % 4096;
+    }
+}
@@ -0,0 +1,31 @@
+//! Disables the JTAG port to use PA15, PB3 and PB4 as outputs
+//!
+//! The chip can still be debugged through SWD
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate stm32f103xx_hal as hal;
+
+use hal::prelude::*;
+use hal::stm32f103xx;
+
+fn main() {
+    let p = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut rcc = p.RCC.constrain();
+    let mut afio = p.AFIO.constrain(&mut rcc.apb2);
+    let mut gpioa = p.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = p.GPIOB.split(&mut rcc.apb2);
+
+    let (pa15, pb3, pb4) = afio.mapr.disable_jtag(gpioa.pa15, gpiob.pb3, gpiob.pb4);
+
+    let mut pa15 = pa15.into_push_pull_output(&mut gpioa.crh);
+    let mut pb3 = pb3.into_push_pull_output(&mut gpiob.crl);
+    let mut pb4 = pb4.into_push_pull_output(&mut gpiob.crl);
+
+    pa15.set_high().unwrap();
+    pb3.set_high().unwrap();
+    pb4.set_high().unwrap();
+}
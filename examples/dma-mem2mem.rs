@@ -0,0 +1,29 @@
+//! Copies a 2 KB buffer using a memory to memory DMA transfer
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+#[macro_use(singleton)]
+extern crate cortex_m;
+extern crate stm32f103xx_hal as hal;
+
+use cortex_m::asm;
+use hal::prelude::*;
+use hal::stm32f103xx;
+
+fn main() {
+    let p = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut rcc = p.RCC.constrain();
+    let channels = p.DMA1.split(&mut rcc.ahb);
+
+    let src = singleton!(: [u8; 2048] = [0xAA; 2048]).unwrap();
+    let dst = singleton!(: [u8; 2048] = [0; 2048]).unwrap();
+
+    let (dst, _c, src) = channels.1.mem2mem(src, dst).wait();
+
+    assert!(dst.iter().zip(src.iter()).all(|(d, s)| d == s));
+
+    asm::bkpt();
+}
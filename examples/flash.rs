@@ -0,0 +1,61 @@
+//! Persists a small settings struct to the last page of the flash memory
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate stm32f103xx_hal as hal;
+
+use cortex_m::asm;
+use hal::prelude::*;
+use hal::stm32f103xx;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Settings {
+    brightness: u16,
+    threshold: u16,
+}
+
+impl Settings {
+    fn to_bytes(&self) -> [u8; 4] {
+        [
+            self.brightness as u8,
+            (self.brightness >> 8) as u8,
+            self.threshold as u8,
+            (self.threshold >> 8) as u8,
+        ]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Settings {
+            brightness: u16::from(bytes[0]) | u16::from(bytes[1]) << 8,
+            threshold: u16::from(bytes[2]) | u16::from(bytes[3]) << 8,
+        }
+    }
+}
+
+fn main() {
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+
+    let settings = Settings {
+        brightness: 200,
+        threshold: 1024,
+    };
+
+    let offset = flash.size() - flash.page_size();
+
+    {
+        let mut writer = flash.unlock().unwrap();
+        writer.erase_page(offset).unwrap();
+        writer.write(offset, &settings.to_bytes()).unwrap();
+    }
+
+    let stored = Settings::from_bytes(flash.read(offset, 4).unwrap());
+
+    assert_eq!(stored, settings);
+
+    asm::bkpt();
+}
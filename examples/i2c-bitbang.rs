@@ -0,0 +1,41 @@
+//! Reads the WHO_AM_I register of an I2C device over a software I2C bus on PA0 (SDA) and PA1
+//! (SCL)
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate stm32f103xx_hal as hal;
+
+use cortex_m::asm;
+use hal::bitbang::BitBangI2c;
+use hal::delay::Delay;
+use hal::prelude::*;
+use hal::stm32f103xx;
+
+const ADDRESS: u8 = 0x68;
+const WHO_AM_I: u8 = 0x75;
+
+fn main() {
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+
+    let sda = gpioa.pa0.into_open_drain_output(&mut gpioa.crl);
+    let scl = gpioa.pa1.into_open_drain_output(&mut gpioa.crl);
+    let delay = Delay::new(cp.SYST, clocks);
+
+    let mut i2c = BitBangI2c::new(sda, scl, delay, 100.khz());
+
+    let mut buffer = [0];
+    i2c.write_read(ADDRESS, &[WHO_AM_I], &mut buffer).unwrap();
+
+    asm::bkpt();
+}
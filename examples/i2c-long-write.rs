@@ -0,0 +1,60 @@
+//! Writes 1024 bytes to an MB85RC256V FRAM in a single I2C transfer and reads them back
+//!
+//! Unlike an EEPROM, which wraps around at the end of its (64 byte) page, the FRAM accepts
+//! sequential writes of any length
+//!
+//! Connect SCL to PB6 and SDA to PB7; both lines need external pull-up resistors
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate stm32f103xx_hal as hal;
+
+use cortex_m::asm;
+use hal::i2c::I2c;
+use hal::prelude::*;
+use hal::stm32f103xx;
+
+const ADDRESS: u8 = 0x50;
+const LEN: usize = 1024;
+
+fn main() {
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb6.into_alternate_open_drain(&mut gpiob.crl);
+    let sda = gpiob.pb7.into_alternate_open_drain(&mut gpiob.crl);
+
+    let mut i2c = I2c::i2c1(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        400.khz(),
+        clocks,
+        &mut rcc.apb1,
+    );
+
+    // memory address (big endian) followed by the data
+    let mut buffer = [0; 2 + LEN];
+    for (i, byte) in buffer[2..].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    i2c.write(ADDRESS, &buffer).unwrap();
+
+    let mut data = [0; LEN];
+    i2c.write_read(ADDRESS, &[0, 0], &mut data).unwrap();
+
+    assert!(data[..] == buffer[2..]);
+
+    asm::bkpt();
+}
@@ -0,0 +1,131 @@
+//! Emulates a 256 byte I2C EEPROM (24C02 style) at address 0x50
+//!
+//! The master writes the memory address and then either writes the data, which is stored
+//! starting at that address, or reads it back after a repeated START. The address auto-increments
+//! and wraps around.
+//!
+//! Connect SCL to PB6 and SDA to PB7; both lines need external pull-up resistors
+
+#![feature(proc_macro)]
+#![deny(unsafe_code)]
+// #![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate cortex_m_rtfm as rtfm;
+extern crate stm32f103xx_hal as hal;
+
+use hal::gpio::gpiob::{PB6, PB7};
+use hal::gpio::{Alternate, OpenDrain};
+use hal::i2c::{Direction, I2c, I2cSlave, OwnAddress, SlaveEvent};
+use hal::prelude::*;
+use hal::stm32f103xx;
+use hal::stm32f103xx::I2C1;
+use rtfm::{app, Threshold};
+
+const ADDRESS: u8 = 0x50;
+
+pub struct Eeprom {
+    memory: [u8; 256],
+    pointer: u8,
+    // the next byte written by the master is the memory address
+    expect_pointer: bool,
+}
+
+app! {
+    device: stm32f103xx,
+
+    resources: {
+        static EEPROM: Eeprom = Eeprom {
+            memory: [0xff; 256],
+            pointer: 0,
+            expect_pointer: false,
+        };
+        static I2C: I2cSlave<I2C1, (PB6<Alternate<OpenDrain>>, PB7<Alternate<OpenDrain>>)>;
+    },
+
+    tasks: {
+        I2C1_EV: {
+            path: event,
+            resources: [EEPROM, I2C],
+        },
+
+        I2C1_ER: {
+            path: error,
+            resources: [EEPROM, I2C],
+        },
+    }
+}
+
+fn init(p: init::Peripherals, _r: init::Resources) -> init::LateResources {
+    let mut flash = p.device.FLASH.constrain();
+    let mut rcc = p.device.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = p.device.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = p.device.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb6.into_alternate_open_drain(&mut gpiob.crl);
+    let sda = gpiob.pb7.into_alternate_open_drain(&mut gpiob.crl);
+
+    let mut i2c = I2c::i2c1(
+        p.device.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        100.khz(),
+        clocks,
+        &mut rcc.apb1,
+    );
+    i2c.set_own_address(OwnAddress::SevenBit(ADDRESS));
+
+    let mut i2c = i2c.into_slave();
+    i2c.listen();
+
+    init::LateResources { I2C: i2c }
+}
+
+fn idle() -> ! {
+    loop {
+        rtfm::wfi();
+    }
+}
+
+fn event(_t: &mut Threshold, mut r: I2C1_EV::Resources) {
+    if let Some(event) = r.I2C.handle_event() {
+        handle(event, &mut r.I2C, &mut r.EEPROM);
+    }
+}
+
+fn error(_t: &mut Threshold, mut r: I2C1_ER::Resources) {
+    if let Some(event) = r.I2C.handle_error() {
+        handle(event, &mut r.I2C, &mut r.EEPROM);
+    }
+}
+
+fn handle(
+    event: SlaveEvent,
+    i2c: &mut I2cSlave<I2C1, (PB6<Alternate<OpenDrain>>, PB7<Alternate<OpenDrain>>)>,
+    eeprom: &mut Eeprom,
+) {
+    match event {
+        SlaveEvent::AddressMatched(Direction::Write) => eeprom.expect_pointer = true,
+        SlaveEvent::AddressMatched(Direction::Read) => {}
+        SlaveEvent::ByteReceived(byte) => if eeprom.expect_pointer {
+            eeprom.pointer = byte;
+            eeprom.expect_pointer = false;
+        } else {
+            eeprom.memory[usize::from(eeprom.pointer)] = byte;
+            eeprom.pointer = eeprom.pointer.wrapping_add(1);
+        },
+        SlaveEvent::ByteRequested => {
+            i2c.send(eeprom.memory[usize::from(eeprom.pointer)]);
+            eeprom.pointer = eeprom.pointer.wrapping_add(1);
+        }
+        // NOTE the master NACKs the last byte it wants; the byte after it has already been
+        // requested so step back
+        SlaveEvent::Nack => eeprom.pointer = eeprom.pointer.wrapping_sub(1),
+        SlaveEvent::Stop | SlaveEvent::Error(_) => {}
+    }
+}
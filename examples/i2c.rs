@@ -0,0 +1,50 @@
+//! Reads the WHO_AM_I register of an MPU6050 over I2C
+//!
+//! Connect SCL to PB6 and SDA to PB7; both lines need external pull-up resistors
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate stm32f103xx_hal as hal;
+
+use cortex_m::asm;
+use hal::i2c::I2c;
+use hal::prelude::*;
+use hal::stm32f103xx;
+
+const ADDRESS: u8 = 0x68;
+const WHO_AM_I: u8 = 0x75;
+
+fn main() {
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb6.into_alternate_open_drain(&mut gpiob.crl);
+    let sda = gpiob.pb7.into_alternate_open_drain(&mut gpiob.crl);
+
+    let mut i2c = I2c::i2c1(
+        dp.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        100.khz(),
+        clocks,
+        &mut rcc.apb1,
+    );
+
+    let mut buffer = [0];
+    i2c.write_read(ADDRESS, &[WHO_AM_I], &mut buffer).unwrap();
+
+    assert_eq!(buffer[0], ADDRESS);
+
+    asm::bkpt();
+}
@@ -0,0 +1,49 @@
+//! Measures how long a busy loop and a cycle counter based delay take using the DWT cycle counter
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate cortex_m_semihosting as semihosting;
+extern crate stm32f103xx_hal as hal;
+
+use core::fmt::Write;
+
+use cortex_m::asm;
+use hal::prelude::*;
+use hal::stm32f103xx;
+use hal::time::MonoTimer;
+use semihosting::hio;
+
+fn main() {
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let timer = MonoTimer::new(cp.DWT, cp.DCB, clocks);
+
+    let instant = timer.now();
+    for _ in 0..1_000 {
+        asm::nop();
+    }
+    let cycles = instant.elapsed();
+
+    let mut hstdout = hio::hstdout().unwrap();
+    writeln!(
+        hstdout,
+        "{} cycles ({} us)",
+        cycles,
+        cycles / (timer.frequency().0 / 1_000_000)
+    ).unwrap();
+
+    let instant = timer.now();
+    timer.delay_us(100);
+    writeln!(hstdout, "delay_us(100) took {} cycles", instant.elapsed()).unwrap();
+
+    asm::bkpt();
+}
@@ -0,0 +1,39 @@
+//! Outputs a 10 us pulse on PA0 every second
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+#[macro_use(block)]
+extern crate nb;
+extern crate stm32f103xx_hal as hal;
+
+use hal::delay::Delay;
+use hal::prelude::*;
+use hal::stm32f103xx;
+use hal::timer::OnePulse;
+
+fn main() {
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+
+    let pa0 = gpioa.pa0.into_alternate_push_pull(&mut gpioa.crl);
+
+    let mut pulse = OnePulse::tim2(dp.TIM2, pa0, 10.us(), clocks, &mut rcc.apb1);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    loop {
+        pulse.trigger();
+        block!(pulse.wait()).unwrap();
+
+        delay.delay_ms(1_000_u16);
+    }
+}
@@ -0,0 +1,74 @@
+//! Reads the temperature of up to 4 DS18B20 sensors connected to a 1-Wire bus on PA0
+//!
+//! The bus needs an external pull-up resistor (4.7 kOhm); the sensors can't be parasite powered
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate stm32f103xx_hal as hal;
+
+use cortex_m::asm;
+use hal::bitbang::{self, OneWire, RomSearch};
+use hal::delay::Delay;
+use hal::prelude::*;
+use hal::stm32f103xx;
+
+const CONVERT_T: u8 = 0x44;
+const READ_SCRATCHPAD: u8 = 0xbe;
+
+fn main() {
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+
+    let pin = gpioa.pa0.into_open_drain_output(&mut gpioa.crl);
+    let mut delay = Delay::new(cp.SYST, clocks);
+
+    let mut bus = OneWire::new(pin);
+
+    let mut roms = [[0; 8]; 4];
+    let mut count = 0;
+    let mut search = RomSearch::new();
+    while count < roms.len() {
+        match bus.search(&mut search, &mut delay) {
+            Some(rom) => {
+                if bitbang::crc8(&rom) == 0 {
+                    roms[count] = rom;
+                    count += 1;
+                }
+            }
+            None => break,
+        }
+    }
+
+    // all the sensors start a conversion at the same time
+    bus.skip_rom(&mut delay);
+    bus.write_byte(CONVERT_T, &mut delay);
+    delay.delay_ms(750_u16);
+
+    // temperatures in 1/16 degrees Celsius
+    let mut temperatures = [0_i16; 4];
+    for (rom, temperature) in roms[..count].iter().zip(temperatures.iter_mut()) {
+        bus.select(rom, &mut delay);
+        bus.write_byte(READ_SCRATCHPAD, &mut delay);
+
+        let mut scratchpad = [0; 9];
+        for byte in scratchpad.iter_mut() {
+            *byte = bus.read_byte(&mut delay);
+        }
+
+        if bitbang::crc8(&scratchpad) == 0 {
+            *temperature = (u16::from(scratchpad[1]) << 8 | u16::from(scratchpad[0])) as i16;
+        }
+    }
+
+    asm::bkpt();
+}
@@ -0,0 +1,73 @@
+//! Drives three half-bridges with complementary PWM outputs and 500 ns of dead time
+//!
+//! High side: PA8, PA9, PA10; low side: PB13, PB14, PB15. Pulling PB12 (over-current fault) low
+//! turns all the transistors off
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate stm32f103xx_hal as hal;
+
+use cortex_m::asm;
+use hal::prelude::*;
+use hal::pwm::{Break, BreakPolarity, IdleState};
+use hal::stm32f103xx;
+
+fn main() {
+    let p = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut flash = p.FLASH.constrain();
+    let mut rcc = p.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = p.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpioa = p.GPIOA.split(&mut rcc.apb2);
+    let mut gpiob = p.GPIOB.split(&mut rcc.apb2);
+
+    let c1 = gpioa.pa8.into_alternate_push_pull(&mut gpioa.crh);
+    let c1n = gpiob.pb13.into_alternate_push_pull(&mut gpiob.crh);
+    let c2 = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+    let c2n = gpiob.pb14.into_alternate_push_pull(&mut gpiob.crh);
+    let c3 = gpioa.pa10.into_alternate_push_pull(&mut gpioa.crh);
+    let c3n = gpiob.pb15.into_alternate_push_pull(&mut gpiob.crh);
+
+    let (mut u, mut v, mut w) = p.TIM1.pwm(
+        ((c1, c1n), (c2, c2n), (c3, c3n)),
+        &mut afio.mapr,
+        20.khz(),
+        clocks,
+        &mut rcc.apb2,
+    );
+
+    u.set_dead_time(500, clocks);
+
+    // keep both transistors of each half-bridge off while the outputs are disabled
+    u.set_idle_state(IdleState::Low, IdleState::Low);
+    v.set_idle_state(IdleState::Low, IdleState::Low);
+    w.set_idle_state(IdleState::Low, IdleState::Low);
+
+    let mut fault = Break::tim1(gpiob.pb12, BreakPolarity::ActiveLow);
+
+    let max = u.get_max_duty();
+
+    u.set_duty(max / 4);
+    v.set_duty(max / 2);
+    w.set_duty(3 * max / 4);
+
+    u.enable();
+    v.enable();
+    w.enable();
+
+    loop {
+        if fault.is_pending() {
+            // the outputs have been forced low
+            asm::bkpt();
+
+            fault.rearm();
+        }
+    }
+}
@@ -52,9 +52,9 @@ fn idle() -> ! {
 }
 
 fn sys_tick(_t: &mut Threshold, mut r: SYS_TICK::Resources) {
-    if r.LED.is_low() {
-        r.LED.set_high()
+    if r.LED.is_set_low() {
+        r.LED.set_high().unwrap()
     } else {
-        r.LED.set_low()
+        r.LED.set_low().unwrap()
     }
 }
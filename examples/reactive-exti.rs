@@ -0,0 +1,70 @@
+//! Toggles an LED every time the button connected to PA0 is pressed
+
+#![feature(proc_macro)]
+#![deny(unsafe_code)]
+// #![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate cortex_m_rtfm as rtfm;
+extern crate stm32f103xx_hal as hal;
+
+use hal::gpio::gpioa::PA0;
+use hal::gpio::gpioc::PC13;
+use hal::gpio::{Edge, Floating, Input, Output, PushPull};
+use hal::prelude::*;
+use hal::stm32f103xx;
+use rtfm::{app, Threshold};
+
+app! {
+    device: stm32f103xx,
+
+    resources: {
+        static BUTTON: PA0<Input<Floating>>;
+        static LED: PC13<Output<PushPull>>;
+    },
+
+    tasks: {
+        EXTI0: {
+            path: exti0,
+            resources: [BUTTON, LED],
+        },
+    }
+}
+
+fn init(mut p: init::Peripherals) -> init::LateResources {
+    let mut rcc = p.device.RCC.constrain();
+
+    let mut afio = p.device.AFIO.constrain(&mut rcc.apb2);
+
+    let gpioa = p.device.GPIOA.split(&mut rcc.apb2);
+    let mut gpioc = p.device.GPIOC.split(&mut rcc.apb2);
+
+    let led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+
+    let mut button = gpioa.pa0;
+    button.make_interrupt_source(&mut afio);
+    button.trigger_on_edge(&mut p.device.EXTI, Edge::Falling);
+    button.enable_interrupt(&mut p.device.EXTI);
+
+    init::LateResources {
+        BUTTON: button,
+        LED: led,
+    }
+}
+
+fn idle() -> ! {
+    loop {
+        rtfm::wfi();
+    }
+}
+
+fn exti0(_t: &mut Threshold, mut r: EXTI0::Resources) {
+    r.BUTTON.clear_interrupt_pending_bit();
+
+    if r.LED.is_set_low() {
+        r.LED.set_high().unwrap()
+    } else {
+        r.LED.set_low().unwrap()
+    }
+}
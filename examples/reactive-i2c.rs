@@ -0,0 +1,104 @@
+//! Reads the WHO_AM_I register of an MPU6050 using interrupt driven I2C transfers
+//!
+//! Connect SCL to PB6 and SDA to PB7; both lines need external pull-up resistors
+
+#![feature(proc_macro)]
+#![deny(unsafe_code)]
+// #![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate cortex_m_rtfm as rtfm;
+extern crate stm32f103xx_hal as hal;
+
+use cortex_m::asm;
+use hal::gpio::gpiob::{PB6, PB7};
+use hal::gpio::{Alternate, OpenDrain};
+use hal::i2c::{Completion, I2c, IrqI2c};
+use hal::prelude::*;
+use hal::stm32f103xx;
+use hal::stm32f103xx::I2C1;
+use rtfm::{app, Threshold};
+
+const ADDRESS: u8 = 0x68;
+
+app! {
+    device: stm32f103xx,
+
+    resources: {
+        static WHO_AM_I: [u8; 1] = [0x75];
+        static BUFFER: [u8; 1] = [0];
+        static I2C: IrqI2c<I2C1, (PB6<Alternate<OpenDrain>>, PB7<Alternate<OpenDrain>>)>;
+    },
+
+    init: {
+        resources: [WHO_AM_I, BUFFER],
+    },
+
+    tasks: {
+        I2C1_EV: {
+            path: event,
+            resources: [I2C],
+        },
+
+        I2C1_ER: {
+            path: error,
+            resources: [I2C],
+        },
+    }
+}
+
+fn init(p: init::Peripherals, r: init::Resources) -> init::LateResources {
+    let mut flash = p.device.FLASH.constrain();
+    let mut rcc = p.device.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut afio = p.device.AFIO.constrain(&mut rcc.apb2);
+
+    let mut gpiob = p.device.GPIOB.split(&mut rcc.apb2);
+
+    let scl = gpiob.pb6.into_alternate_open_drain(&mut gpiob.crl);
+    let sda = gpiob.pb7.into_alternate_open_drain(&mut gpiob.crl);
+
+    let mut i2c = I2c::i2c1(
+        p.device.I2C1,
+        (scl, sda),
+        &mut afio.mapr,
+        100.khz(),
+        clocks,
+        &mut rcc.apb1,
+    ).into_irq();
+
+    // returns immediately; the result is reported by the interrupt handlers
+    i2c.start_write_read(ADDRESS, r.WHO_AM_I, r.BUFFER).ok().unwrap();
+
+    init::LateResources { I2C: i2c }
+}
+
+fn idle() -> ! {
+    loop {
+        rtfm::wfi();
+    }
+}
+
+fn event(_t: &mut Threshold, mut r: I2C1_EV::Resources) {
+    if let Some(completion) = r.I2C.handle_event() {
+        done(completion);
+    }
+}
+
+fn error(_t: &mut Threshold, mut r: I2C1_ER::Resources) {
+    if let Some(completion) = r.I2C.handle_error() {
+        done(completion);
+    }
+}
+
+fn done(completion: Completion) {
+    match completion {
+        Completion::Read(buffer) => assert_eq!(buffer[0], ADDRESS),
+        _ => asm::bkpt(),
+    }
+
+    asm::bkpt();
+}
@@ -0,0 +1,41 @@
+//! Prints the number of seconds counted by the RTC, which keeps running across resets
+//!
+//! Requires a 32.768 kHz crystal connected to PC14 / PC15
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate cortex_m_semihosting as semihosting;
+extern crate stm32f103xx_hal as hal;
+
+use core::fmt::Write;
+
+use hal::prelude::*;
+use hal::rtc::{ClockSource, Rtc};
+use hal::stm32f103xx;
+use semihosting::hio;
+
+fn main() {
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut rcc = dp.RCC.constrain();
+
+    let mut pwr = dp.PWR.constrain(&mut rcc.apb1);
+    let mut backup_domain = dp.BKP.constrain(&mut rcc.apb1, &mut pwr);
+
+    let rtc = Rtc::new(dp.RTC, &mut backup_domain, ClockSource::Lse);
+
+    let mut hstdout = hio::hstdout().unwrap();
+
+    let mut last = rtc.get_cnt();
+    loop {
+        let now = rtc.get_cnt();
+
+        if now != last {
+            writeln!(hstdout, "{}", now).unwrap();
+            last = now;
+        }
+    }
+}
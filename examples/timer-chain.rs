@@ -0,0 +1,35 @@
+//! Chains TIM2 and TIM3: TIM3 counts the overflows of TIM2, which runs at 1 kHz, so together they
+//! form a free running millisecond counter
+
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![no_std]
+
+extern crate cortex_m;
+extern crate stm32f103xx_hal as hal;
+
+use cortex_m::asm;
+use hal::prelude::*;
+use hal::stm32f103xx;
+use hal::timer::{MasterMode, SlaveMode, Timer};
+
+fn main() {
+    let dp = stm32f103xx::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    let mut master = Timer::tim2(dp.TIM2, 1.khz(), clocks, &mut rcc.apb1);
+    master.set_master_mode(MasterMode::Update);
+
+    let mut slave = Timer::tim3(dp.TIM3, 1.hz(), clocks, &mut rcc.apb1);
+    slave.set_slave_mode(&master, SlaveMode::ExternalClock);
+
+    let start = slave.count();
+    while slave.count().wrapping_sub(start) < 1_000 {}
+
+    // one second has elapsed
+    asm::bkpt();
+}
@@ -12,7 +12,6 @@ main() {
         serial
         pwm
         qei
-        mpu9250
     )
 
     rm -rf src/examples
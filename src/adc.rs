@@ -0,0 +1,761 @@
+//! Analog to digital converter
+
+use core::marker::Unsize;
+use core::sync::atomic::{self, Ordering};
+
+use cast::u16;
+use cortex_m::asm;
+use hal::adc::OneShot;
+use nb;
+use stm32f103xx::{ADC1, ADC2};
+use void::Void;
+
+use dma::{dma1, Transfer, W};
+use gpio::gpioa::{PA0, PA1, PA2, PA3, PA4, PA5, PA6, PA7};
+use gpio::gpiob::{PB0, PB1};
+use gpio::Analog;
+use rcc::{APB2, Clocks, RccPeripheral};
+use time::Hertz;
+
+/// Analog input channel of `ADC`; `channel` returns the channel number
+pub use hal::adc::Channel;
+
+macro_rules! channels {
+    ($($PXi:ident: $channel:expr,)+) => {
+        $(
+            impl Channel<ADC1> for $PXi<Analog> {
+                type ID = u8;
+
+                fn channel() -> u8 {
+                    $channel
+                }
+            }
+
+            impl Channel<ADC2> for $PXi<Analog> {
+                type ID = u8;
+
+                fn channel() -> u8 {
+                    $channel
+                }
+            }
+        )+
+    };
+}
+
+channels! {
+    PA0: 0,
+    PA1: 1,
+    PA2: 2,
+    PA3: 3,
+    PA4: 4,
+    PA5: 5,
+    PA6: 6,
+    PA7: 7,
+    PB0: 8,
+    PB1: 9,
+}
+
+/// Sampling time, in ADC clock cycles
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SampleTime {
+    /// 1.5 cycles
+    T_1_5,
+    /// 7.5 cycles
+    T_7_5,
+    /// 13.5 cycles
+    T_13_5,
+    /// 28.5 cycles
+    T_28_5,
+    /// 41.5 cycles
+    T_41_5,
+    /// 55.5 cycles
+    T_55_5,
+    /// 71.5 cycles
+    T_71_5,
+    /// 239.5 cycles
+    T_239_5,
+}
+
+impl SampleTime {
+    fn bits(&self) -> u32 {
+        match *self {
+            SampleTime::T_1_5 => 0b000,
+            SampleTime::T_7_5 => 0b001,
+            SampleTime::T_13_5 => 0b010,
+            SampleTime::T_28_5 => 0b011,
+            SampleTime::T_41_5 => 0b100,
+            SampleTime::T_55_5 => 0b101,
+            SampleTime::T_71_5 => 0b110,
+            SampleTime::T_239_5 => 0b111,
+        }
+    }
+
+    /// Sampling time in half ADC clock cycles
+    fn half_cycles(&self) -> u32 {
+        match *self {
+            SampleTime::T_1_5 => 3,
+            SampleTime::T_7_5 => 15,
+            SampleTime::T_13_5 => 27,
+            SampleTime::T_28_5 => 57,
+            SampleTime::T_41_5 => 83,
+            SampleTime::T_55_5 => 111,
+            SampleTime::T_71_5 => 143,
+            SampleTime::T_239_5 => 479,
+        }
+    }
+}
+
+/// Interrupt events
+pub enum Event {
+    /// A conversion of the channel monitored by the analog watchdog fell outside the thresholds
+    AnalogWatchdog,
+    /// The conversion of the injected group has completed
+    InjectedEndOfConversion,
+}
+
+/// Event that starts the conversion of the injected group
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InjectedTrigger {
+    /// TIM1 trigger output (TRGO)
+    Tim1Trgo,
+    /// TIM1 capture / compare 4
+    Tim1Cc4,
+    /// TIM2 trigger output (TRGO)
+    Tim2Trgo,
+    /// TIM2 capture / compare 1
+    Tim2Cc1,
+    /// TIM3 capture / compare 4
+    Tim3Cc4,
+    /// TIM4 trigger output (TRGO)
+    Tim4Trgo,
+    /// EXTI line 15
+    Exti15,
+    /// `Adc::start_injected`
+    Software,
+}
+
+/// Event that starts the conversion of the regular group
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExternalTrigger {
+    /// TIM1 capture / compare 1
+    Tim1Cc1,
+    /// TIM1 capture / compare 2
+    Tim1Cc2,
+    /// TIM1 capture / compare 3
+    Tim1Cc3,
+    /// TIM2 capture / compare 2
+    Tim2Cc2,
+    /// TIM3 trigger output (TRGO)
+    Tim3Trgo,
+    /// TIM4 capture / compare 4
+    Tim4Cc4,
+    /// EXTI line 11
+    Exti11,
+}
+
+impl ExternalTrigger {
+    fn bits(&self) -> u8 {
+        match *self {
+            ExternalTrigger::Tim1Cc1 => 0b000,
+            ExternalTrigger::Tim1Cc2 => 0b001,
+            ExternalTrigger::Tim1Cc3 => 0b010,
+            ExternalTrigger::Tim2Cc2 => 0b011,
+            ExternalTrigger::Tim3Trgo => 0b100,
+            ExternalTrigger::Tim4Cc4 => 0b101,
+            ExternalTrigger::Exti11 => 0b110,
+        }
+    }
+}
+
+impl InjectedTrigger {
+    fn bits(&self) -> u8 {
+        match *self {
+            InjectedTrigger::Tim1Trgo => 0b000,
+            InjectedTrigger::Tim1Cc4 => 0b001,
+            InjectedTrigger::Tim2Trgo => 0b010,
+            InjectedTrigger::Tim2Cc1 => 0b011,
+            InjectedTrigger::Tim3Cc4 => 0b100,
+            InjectedTrigger::Tim4Trgo => 0b101,
+            InjectedTrigger::Exti15 => 0b110,
+            InjectedTrigger::Software => 0b111,
+        }
+    }
+}
+
+/// ADC abstraction
+pub struct Adc<ADC> {
+    adc: ADC,
+    clocks: Clocks,
+    sample_time: SampleTime,
+}
+
+/// An ADC that's feeding a DMA transfer with the results of a scan
+pub struct AdcDma<ADC> {
+    adc: Adc<ADC>,
+}
+
+/// ADC1 and ADC2 operating in regular simultaneous mode
+///
+/// Both ADCs sample their channel at the same instant; ADC1 is the master that starts the
+/// conversions
+pub struct DualAdc {
+    adc1: Adc<ADC1>,
+    adc2: Adc<ADC2>,
+}
+
+macro_rules! hal {
+    ($($ADCX:ident: ($adcX:ident),)+) => {
+        $(
+            impl Adc<$ADCX> {
+                /// Powers up and calibrates the ADC
+                ///
+                /// The ADC clock (`clocks.adcclk()`) is derived from PCLK2 when the clocks are
+                /// frozen
+                pub fn $adcX(adc: $ADCX, clocks: Clocks, apb2: &mut APB2) -> Self {
+                    // enable and reset peripheral to a clean slate state
+                    $ADCX::enable(apb2);
+                    $ADCX::reset(apb2);
+
+                    let mut adc = Adc {
+                        adc,
+                        clocks,
+                        sample_time: SampleTime::T_28_5,
+                    };
+
+                    adc.power_up();
+                    adc.calibrate();
+
+                    adc
+                }
+
+                /// Returns the frequency of the ADC clock
+                pub fn clock(&self) -> Hertz {
+                    self.clocks.adcclk()
+                }
+
+                /// Returns the duration of a single conversion, in nanoseconds
+                ///
+                /// A conversion takes the sampling time plus 12.5 ADC clock cycles
+                pub fn conversion_time_ns(&self) -> u32 {
+                    let half_cycles = u64::from(self.sample_time.half_cycles() + 25);
+
+                    (half_cycles * 1_000_000_000 / (2 * u64::from(self.clocks.adcclk().0))) as u32
+                }
+
+                /// Sets the sampling time used by the following conversions
+                pub fn set_sample_time(&mut self, sample_time: SampleTime) {
+                    self.sample_time = sample_time;
+                }
+
+                /// Converts the voltage on `pin`; the result is 12 bits wide and right aligned
+                pub fn read<PIN>(&mut self, _pin: &mut PIN) -> u16
+                where
+                    PIN: Channel<$ADCX, ID = u8>,
+                {
+                    self.convert(PIN::channel())
+                }
+
+                /// Makes the analog watchdog monitor the conversions of `pin`
+                ///
+                /// The watchdog flag is raised when a conversion result is below `low` or above
+                /// `high` (12-bit, right aligned values). NOTE the watchdog only checks the
+                /// conversions that actually take place (e.g. `read`); it doesn't sample the pin on
+                /// its own
+                pub fn enable_watchdog<PIN>(&mut self, _pin: &PIN, low: u16, high: u16)
+                where
+                    PIN: Channel<$ADCX, ID = u8>,
+                {
+                    assert!(low <= high && high < 1 << 12);
+
+                    self.adc.ltr.write(|w| unsafe { w.lt().bits(low) });
+                    self.adc.htr.write(|w| unsafe { w.ht().bits(high) });
+                    self.adc.cr1.modify(|_, w| unsafe {
+                        w.awdch()
+                            .bits(PIN::channel())
+                            .awdsgl()
+                            .set_bit()
+                            .awden()
+                            .set_bit()
+                    });
+                }
+
+                /// Stops the analog watchdog
+                pub fn disable_watchdog(&mut self) {
+                    self.adc.cr1.modify(|_, w| w.awden().clear_bit());
+                }
+
+                /// Returns `true` if a monitored conversion fell outside the watchdog thresholds
+                pub fn is_watchdog_triggered(&self) -> bool {
+                    self.adc.sr.read().awd().bit_is_set()
+                }
+
+                /// Clears the analog watchdog flag
+                pub fn clear_watchdog(&mut self) {
+                    self.adc.sr.modify(|_, w| w.awd().clear_bit());
+                }
+
+                /// Configures the injected group to convert `channels`, in order, every time
+                /// `trigger` occurs
+                ///
+                /// `channels` are channel numbers (see `Channel::channel`); up to 4 channels can
+                /// be injected. An injected conversion preempts the ongoing regular one, which is
+                /// resumed afterwards. The results are read with `read_injected`
+                pub fn configure_injected(&mut self, channels: &[u8], trigger: InjectedTrigger) {
+                    assert!(!channels.is_empty() && channels.len() <= 4);
+
+                    // NOTE a sequence shorter than 4 conversions starts at JSQ(4 - JL) so the
+                    // channels are placed in the upper slots of JSQR
+                    let sample_time = self.sample_time;
+                    let first = 4 - channels.len();
+                    let mut jsqr = ((channels.len() - 1) as u32) << 20;
+                    for (i, &channel) in channels.iter().enumerate() {
+                        assert!(channel < 18);
+
+                        self.set_channel_sample_time(channel, sample_time);
+                        jsqr |= u32::from(channel) << (5 * (first + i));
+                    }
+
+                    if channels.iter().any(|&channel| channel >= 16) {
+                        self.adc.cr2.modify(|_, w| w.tsvrefe().set_bit());
+                        self.delay_us(10);
+                    }
+
+                    // NOTE(unsafe) the channel numbers have been checked above
+                    self.adc.jsqr.write(|w| unsafe { w.bits(jsqr) });
+                    self.adc.cr2.modify(|_, w| unsafe {
+                        w.jextsel().bits(trigger.bits()).jexttrig().set_bit()
+                    });
+                }
+
+                /// Starts the conversion of the injected group
+                ///
+                /// NOTE the group must have been configured with `InjectedTrigger::Software`
+                pub fn start_injected(&mut self) {
+                    self.adc.cr2.modify(|_, w| w.jswstart().set_bit());
+                }
+
+                /// Returns `true` if the conversion of the injected group has completed
+                pub fn is_injected_done(&self) -> bool {
+                    self.adc.sr.read().jeoc().bit_is_set()
+                }
+
+                /// Clears the injected end of conversion flag
+                pub fn clear_injected_done(&mut self) {
+                    self.adc.sr.modify(|_, w| w.jeoc().clear_bit().jstrt().clear_bit());
+                }
+
+                /// Returns the result of the `index`-th conversion (starting at 0) of the
+                /// injected group
+                pub fn read_injected(&self, index: usize) -> u16 {
+                    match index {
+                        0 => self.adc.jdr1.read().jdata().bits(),
+                        1 => self.adc.jdr2.read().jdata().bits(),
+                        2 => self.adc.jdr3.read().jdata().bits(),
+                        3 => self.adc.jdr4.read().jdata().bits(),
+                        _ => panic!("invalid injected conversion index"),
+                    }
+                }
+
+                /// Starts listening for an interrupt `event` (ADC1 and ADC2 share the `ADC1_2`
+                /// interrupt)
+                pub fn listen(&mut self, event: Event) {
+                    match event {
+                        Event::AnalogWatchdog => self.adc.cr1.modify(|_, w| w.awdie().set_bit()),
+                        Event::InjectedEndOfConversion => {
+                            self.adc.cr1.modify(|_, w| w.jeocie().set_bit())
+                        }
+                    }
+                }
+
+                /// Stops listening for an interrupt `event`
+                pub fn unlisten(&mut self, event: Event) {
+                    match event {
+                        Event::AnalogWatchdog => {
+                            self.adc.cr1.modify(|_, w| w.awdie().clear_bit())
+                        }
+                        Event::InjectedEndOfConversion => {
+                            self.adc.cr1.modify(|_, w| w.jeocie().clear_bit())
+                        }
+                    }
+                }
+
+                /// Powers down the ADC and releases the peripheral
+                pub fn release(self, apb2: &mut APB2) -> $ADCX {
+                    self.adc.cr2.modify(|_, w| w.adon().clear_bit());
+                    $ADCX::disable(apb2);
+
+                    self.adc
+                }
+
+                fn power_up(&mut self) {
+                    self.adc.cr2.modify(|_, w| w.adon().set_bit());
+
+                    // NOTE wait for the ADC to stabilize (tSTAB = 1 us)
+                    self.delay_us(1);
+                }
+
+                fn calibrate(&mut self) {
+                    // NOTE the ADC has been on for more than the 2 ADC clock cycles that are
+                    // required before starting a calibration
+                    self.adc.cr2.modify(|_, w| w.rstcal().set_bit());
+                    while self.adc.cr2.read().rstcal().bit_is_set() {}
+
+                    self.adc.cr2.modify(|_, w| w.cal().set_bit());
+                    while self.adc.cr2.read().cal().bit_is_set() {}
+                }
+
+                fn set_channel_sample_time(&mut self, channel: u8, sample_time: SampleTime) {
+                    let bits = sample_time.bits();
+
+                    // NOTE(unsafe) 3 bits per channel; all the bit patterns are valid
+                    if channel < 10 {
+                        let offset = 3 * u32::from(channel);
+                        self.adc.smpr2.modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b111 << offset)) | (bits << offset))
+                        });
+                    } else {
+                        let offset = 3 * u32::from(channel - 10);
+                        self.adc.smpr1.modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b111 << offset)) | (bits << offset))
+                        });
+                    }
+                }
+
+                fn convert(&mut self, channel: u8) -> u16 {
+                    let sample_time = self.sample_time;
+                    self.set_channel_sample_time(channel, sample_time);
+
+                    // a regular sequence of a single conversion
+                    self.adc.sqr1.write(|w| unsafe { w.l().bits(0b0000) });
+                    self.adc.sqr3.write(|w| unsafe { w.sq1().bits(channel) });
+
+                    // NOTE setting ADON while it's already set starts a conversion
+                    self.adc.cr2.modify(|_, w| w.adon().set_bit());
+                    while self.adc.sr.read().eoc().bit_is_clear() {}
+
+                    // NOTE reading DR clears EOC
+                    self.adc.dr.read().data().bits()
+                }
+
+                fn delay_us(&self, us: u32) {
+                    let cycles = us * (self.clocks.sysclk().0 / 1_000_000);
+
+                    // NOTE each iteration takes at least one core clock cycle
+                    for _ in 0..cycles {
+                        asm::nop();
+                    }
+                }
+            }
+
+            impl<PIN> OneShot<$ADCX, u16, PIN> for Adc<$ADCX>
+            where
+                PIN: Channel<$ADCX, ID = u8>,
+            {
+                type Error = Void;
+
+                /// Converts the voltage on `pin`; this blocks until the conversion is done
+                fn read(&mut self, _pin: &mut PIN) -> nb::Result<u16, Void> {
+                    Ok(self.convert(PIN::channel()))
+                }
+            }
+        )+
+    }
+}
+
+/// Supply / reference voltage assumed when converting readings into voltages, in millivolts
+const VDDA_MV: i32 = 3_300;
+
+/// Typical value of the internal reference voltage, in millivolts
+///
+/// NOTE the datasheet specifies 1.16 - 1.24 V; there's no factory calibration on the F1
+const VREFINT_MV: u32 = 1_200;
+
+impl Adc<ADC1> {
+    /// Reads the internal temperature sensor and returns the temperature in degrees Celsius
+    ///
+    /// NOTE the F1 doesn't store calibration values for the sensor so this uses the typical
+    /// values from the datasheet (V25 = 1.43 V, Avg_Slope = 4.3 mV/C); the offset varies from chip
+    /// to chip by up to 45 C so this is mostly useful to track temperature *changes*. The
+    /// conversion assumes VDDA = 3.3 V
+    pub fn read_temp(&mut self) -> i32 {
+        const V25_UV: i32 = 1_430_000;
+        const AVG_SLOPE_UV: i32 = 4_300;
+
+        let raw = self.convert_internal(16);
+
+        // NOTE(i64) the product overflows an `i32` for readings above ~650
+        let vsense_uv = (i64::from(raw) * i64::from(VDDA_MV) * 1_000 / 4_096) as i32;
+
+        (V25_UV - vsense_uv) / AVG_SLOPE_UV + 25
+    }
+
+    /// Converts the internal reference voltage (VREFINT, 1.20 V typical) and returns the raw
+    /// reading
+    pub fn read_vref(&mut self) -> u16 {
+        self.convert_internal(17)
+    }
+
+    /// Returns the supply / reference voltage (VDDA) in millivolts, estimated from VREFINT
+    pub fn read_vdda(&mut self) -> u16 {
+        let vref = u32::from(self.read_vref());
+
+        (VREFINT_MV * 4_096 / vref) as u16
+    }
+
+    /// Converts the voltage on `pin` and returns it in millivolts
+    ///
+    /// The reading is scaled against VREFINT so the result doesn't depend on the actual VDDA
+    pub fn read_voltage<PIN>(&mut self, pin: &mut PIN) -> u16
+    where
+        PIN: Channel<ADC1, ID = u8>,
+    {
+        let vref = u32::from(self.read_vref());
+        let raw = u32::from(self.read(pin));
+
+        (raw * VREFINT_MV / vref) as u16
+    }
+
+    /// Converts the sequence of `channels` and writes the results, in order, into `buffer`
+    /// using a DMA transfer
+    ///
+    /// `channels` are channel numbers (see `Channel::channel`); channels 16 and 17 are the
+    /// internal ones. If `buffer` is longer than `channels` the sequence is converted repeatedly
+    /// until `buffer` is full; its length must be a multiple of the length of `channels`. Once the
+    /// DMA transfer is done the ADC must be released with
+    /// [`AdcDma.stop`](struct.AdcDma.html#method.stop)
+    pub fn read_scan_dma<B>(
+        self,
+        channels: &[u8],
+        chan: dma1::C1,
+        buffer: &'static mut B,
+    ) -> Transfer<W, &'static mut B, dma1::C1, AdcDma<ADC1>>
+    where
+        B: Unsize<[u16]>,
+    {
+        self.scan_dma(channels, None, chan, buffer)
+    }
+
+    /// Like `read_scan_dma` but the sequence of `channels` is converted once every time `trigger`
+    /// occurs, e.g. on every update event of a timer set up with `MasterMode::Update`
+    ///
+    /// This samples the channels at the rate of the trigger without any CPU involvement
+    pub fn read_scan_dma_triggered<B>(
+        self,
+        channels: &[u8],
+        trigger: ExternalTrigger,
+        chan: dma1::C1,
+        buffer: &'static mut B,
+    ) -> Transfer<W, &'static mut B, dma1::C1, AdcDma<ADC1>>
+    where
+        B: Unsize<[u16]>,
+    {
+        self.scan_dma(channels, Some(trigger), chan, buffer)
+    }
+
+    fn scan_dma<B>(
+        mut self,
+        channels: &[u8],
+        trigger: Option<ExternalTrigger>,
+        mut chan: dma1::C1,
+        buffer: &'static mut B,
+    ) -> Transfer<W, &'static mut B, dma1::C1, AdcDma<ADC1>>
+    where
+        B: Unsize<[u16]>,
+    {
+        assert!(!channels.is_empty() && channels.len() <= 16);
+
+        // regular sequence
+        let sample_time = self.sample_time;
+        let mut sqr1 = ((channels.len() - 1) as u32) << 20;
+        let mut sqr2 = 0;
+        let mut sqr3 = 0;
+        for (i, &channel) in channels.iter().enumerate() {
+            assert!(channel < 18);
+
+            self.set_channel_sample_time(channel, sample_time);
+
+            let channel = u32::from(channel);
+            match i {
+                0...5 => sqr3 |= channel << (5 * i),
+                6...11 => sqr2 |= channel << (5 * (i - 6)),
+                _ => sqr1 |= channel << (5 * (i - 12)),
+            }
+        }
+
+        if channels.iter().any(|&channel| channel >= 16) {
+            self.adc.cr2.modify(|_, w| w.tsvrefe().set_bit());
+            self.delay_us(10);
+        }
+
+        // NOTE(unsafe) the channel numbers have been checked above
+        self.adc.sqr1.write(|w| unsafe { w.bits(sqr1) });
+        self.adc.sqr2.write(|w| unsafe { w.bits(sqr2) });
+        self.adc.sqr3.write(|w| unsafe { w.bits(sqr3) });
+
+        let len = {
+            let buffer: &[u16] = buffer;
+            assert!(buffer.len() % channels.len() == 0);
+
+            chan.cmar().write(|w| unsafe {
+                w.ma().bits(buffer.as_ptr() as usize as u32)
+            });
+            chan.cndtr().write(|w| unsafe{
+                w.ndt().bits(u16(buffer.len()).unwrap())
+            });
+            chan.cpar().write(|w| unsafe {
+                w.pa().bits(&(*ADC1::ptr()).dr as *const _ as usize as u32)
+            });
+
+            // NOTE(compiler_fence) operations on `buffer` should not be reordered after
+            // the next statement, which starts the DMA transfer
+            atomic::compiler_fence(Ordering::SeqCst);
+
+            chan.ccr().modify(|_, w| {
+                w.mem2mem()
+                    .clear_bit()
+                    .pl()
+                    .medium()
+                    .msize()
+                    .bit16()
+                    .psize()
+                    .bit16()
+                    .minc()
+                    .set_bit()
+                    .pinc()
+                    .clear_bit()
+                    .circ()
+                    .clear_bit()
+                    .dir()
+                    .clear_bit()
+                    .en()
+                    .set_bit()
+            });
+
+            buffer.len()
+        };
+
+        self.adc.cr1.modify(|_, w| w.scan().set_bit());
+
+        if let Some(trigger) = trigger {
+            self.adc.cr2.modify(|_, w| unsafe {
+                w.dma()
+                    .set_bit()
+                    .extsel()
+                    .bits(trigger.bits())
+                    .exttrig()
+                    .set_bit()
+            });
+        } else {
+            self.adc
+                .cr2
+                .modify(|_, w| w.dma().set_bit().cont().bit(len > channels.len()));
+
+            // NOTE setting ADON while it's already set starts the conversions
+            self.adc.cr2.modify(|_, w| w.adon().set_bit());
+        }
+
+        Transfer::w(buffer, chan, AdcDma { adc: self })
+    }
+
+    /// Pairs this ADC with `adc2` to sample two channels simultaneously
+    pub fn into_dual(self, adc2: Adc<ADC2>) -> DualAdc {
+        // NOTE the external trigger must be enabled on both ADCs to prevent spurious slave
+        // conversions but only the master ever gets a (software) start
+        self.adc
+            .cr2
+            .modify(|_, w| unsafe { w.extsel().bits(0b111).exttrig().set_bit() });
+        adc2.adc
+            .cr2
+            .modify(|_, w| unsafe { w.extsel().bits(0b111).exttrig().set_bit() });
+
+        // regular simultaneous mode
+        self.adc.cr1.modify(|_, w| unsafe { w.dualmod().bits(0b0110) });
+
+        DualAdc { adc1: self, adc2 }
+    }
+
+    /// Converts internal channel 16 (temperature sensor) or 17 (VREFINT)
+    fn convert_internal(&mut self, channel: u8) -> u16 {
+        if self.adc.cr2.read().tsvrefe().bit_is_clear() {
+            self.adc.cr2.modify(|_, w| w.tsvrefe().set_bit());
+
+            // NOTE wait for the internal channels to start up (tSTART = 10 us max)
+            self.delay_us(10);
+        }
+
+        // NOTE the sensor requires a sampling time of at least 17.1 us; the ADC clock is at most
+        // 14 MHz so 239.5 cycles always meet that
+        let sample_time = self.sample_time;
+        self.sample_time = SampleTime::T_239_5;
+        let raw = self.convert(channel);
+        self.sample_time = sample_time;
+
+        raw
+    }
+}
+
+impl DualAdc {
+    /// Converts the voltages on `pin1` (ADC1) and `pin2` (ADC2) at the same time; the results are
+    /// 12 bits wide and right aligned
+    ///
+    /// NOTE both conversions use the sampling time of ADC1; the two pins must be different
+    pub fn read<PIN1, PIN2>(&mut self, _pin1: &mut PIN1, _pin2: &mut PIN2) -> (u16, u16)
+    where
+        PIN1: Channel<ADC1, ID = u8>,
+        PIN2: Channel<ADC2, ID = u8>,
+    {
+        let sample_time = self.adc1.sample_time;
+        self.adc1.set_channel_sample_time(PIN1::channel(), sample_time);
+        self.adc2.set_channel_sample_time(PIN2::channel(), sample_time);
+
+        self.adc1.adc.sqr1.write(|w| unsafe { w.l().bits(0b0000) });
+        self.adc1.adc.sqr3.write(|w| unsafe { w.sq1().bits(PIN1::channel()) });
+        self.adc2.adc.sqr1.write(|w| unsafe { w.l().bits(0b0000) });
+        self.adc2.adc.sqr3.write(|w| unsafe { w.sq1().bits(PIN2::channel()) });
+
+        self.adc1.adc.cr2.modify(|_, w| w.swstart().set_bit());
+        while self.adc1.adc.sr.read().eoc().bit_is_clear() {}
+
+        // NOTE in dual mode the upper half of ADC1's DR holds the ADC2 result; reading it clears
+        // the EOC flag of ADC1 only
+        let dr = self.adc1.adc.dr.read();
+        self.adc2.adc.sr.modify(|_, w| w.eoc().clear_bit());
+
+        (dr.data().bits(), dr.adc2data().bits())
+    }
+
+    /// Goes back to independent mode and releases both ADCs
+    pub fn split(self) -> (Adc<ADC1>, Adc<ADC2>) {
+        self.adc1.adc.cr1.modify(|_, w| unsafe { w.dualmod().bits(0b0000) });
+
+        self.adc1.adc.cr2.modify(|_, w| w.exttrig().clear_bit());
+        self.adc2.adc.cr2.modify(|_, w| w.exttrig().clear_bit());
+
+        (self.adc1, self.adc2)
+    }
+}
+
+impl AdcDma<ADC1> {
+    /// Stops the conversions and releases the ADC
+    pub fn stop(self) -> Adc<ADC1> {
+        let adc = self.adc;
+
+        adc.adc.cr2.modify(|_, w| {
+            w.cont()
+                .clear_bit()
+                .dma()
+                .clear_bit()
+                .exttrig()
+                .clear_bit()
+        });
+        adc.adc.cr1.modify(|_, w| w.scan().clear_bit());
+
+        adc
+    }
+}
+
+hal! {
+    ADC1: (adc1),
+    ADC2: (adc2),
+}
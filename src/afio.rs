@@ -1,6 +1,19 @@
+//! Alternate Function I/O
+//!
+//! `MAPR` is borrowed by the peripheral constructors to remap their pins; it also controls the
+//! debug port, see `MAPR::disable_jtag`.
+
+use core::marker::PhantomData;
+
 use stm32f103xx::{afio, AFIO};
 
-use rcc::APB2;
+use gpio::gpioa::PA15;
+use gpio::gpiob::{PB3, PB4};
+use gpio::{Debugger, Floating, Input};
+use rcc::{APB2, RccPeripheral};
+
+/// PA15, PB3 and PB4 once they have been freed from the JTAG port
+pub type JtagPins = (PA15<Input<Floating>>, PB3<Input<Floating>>, PB4<Input<Floating>>);
 
 pub trait AfioExt {
     fn constrain(self, apb2: &mut APB2) -> Parts;
@@ -8,26 +21,108 @@ pub trait AfioExt {
 
 impl AfioExt for AFIO {
     fn constrain(self, apb2: &mut APB2) -> Parts {
-        apb2.enr().modify(|_, w| w.afioen().enabled());
-        apb2.rstr().modify(|_, w| w.afiorst().set_bit());
-        apb2.rstr().modify(|_, w| w.afiorst().clear_bit());
+        AFIO::enable(apb2);
+        AFIO::reset(apb2);
 
         Parts {
-            mapr: MAPR { _0: () },
+            mapr: MAPR { jtag_enabled: true },
+            exticr1: EXTICR1 { _0: () },
+            exticr2: EXTICR2 { _0: () },
+            exticr3: EXTICR3 { _0: () },
+            exticr4: EXTICR4 { _0: () },
         }
     }
 }
 
 pub struct Parts {
     pub mapr: MAPR,
+    pub exticr1: EXTICR1,
+    pub exticr2: EXTICR2,
+    pub exticr3: EXTICR3,
+    pub exticr4: EXTICR4,
 }
 
+/// Opaque MAPR register
 pub struct MAPR {
-    _0: (),
+    jtag_enabled: bool,
 }
 
 impl MAPR {
-    pub fn mapr(&mut self) -> &afio::MAPR {
+    pub(crate) fn mapr(&mut self) -> &afio::MAPR {
         unsafe { &(*AFIO::ptr()).mapr }
     }
+
+    /// Modifies the MAPR register while preserving the debug port configuration
+    ///
+    /// NOTE the SWJ_CFG bits are write only (they read back as zero) so a plain `modify` would
+    /// re-enable the JTAG port
+    pub fn modify_mapr<F>(&mut self, f: F)
+    where
+        F: for<'w> FnOnce(&afio::mapr::R, &'w mut afio::mapr::W) -> &'w mut afio::mapr::W,
+    {
+        let swj_cfg = if self.jtag_enabled { 0b000 } else { 0b010 };
+
+        self.mapr()
+            .modify(|r, w| unsafe { f(r, w).swj_cfg().bits(swj_cfg) });
+    }
+
+    /// Disables the JTAG debug port, keeping SWD enabled
+    ///
+    /// This frees PA15, PB3 and PB4 (JTDI, JTDO and NJTRST), which are handed back as floating
+    /// inputs, to be used as general purpose I/O
+    pub fn disable_jtag(
+        &mut self,
+        _pa15: PA15<Debugger>,
+        _pb3: PB3<Debugger>,
+        _pb4: PB4<Debugger>,
+    ) -> JtagPins {
+        self.jtag_enabled = false;
+        self.modify_mapr(|_, w| w);
+
+        (
+            PA15 { _mode: PhantomData },
+            PB3 { _mode: PhantomData },
+            PB4 { _mode: PhantomData },
+        )
+    }
+}
+
+pub struct EXTICR1 {
+    _0: (),
+}
+
+impl EXTICR1 {
+    pub(crate) fn exticr(&mut self) -> &afio::EXTICR1 {
+        unsafe { &(*AFIO::ptr()).exticr1 }
+    }
+}
+
+pub struct EXTICR2 {
+    _0: (),
+}
+
+impl EXTICR2 {
+    pub(crate) fn exticr(&mut self) -> &afio::EXTICR2 {
+        unsafe { &(*AFIO::ptr()).exticr2 }
+    }
+}
+
+pub struct EXTICR3 {
+    _0: (),
+}
+
+impl EXTICR3 {
+    pub(crate) fn exticr(&mut self) -> &afio::EXTICR3 {
+        unsafe { &(*AFIO::ptr()).exticr3 }
+    }
+}
+
+pub struct EXTICR4 {
+    _0: (),
+}
+
+impl EXTICR4 {
+    pub(crate) fn exticr(&mut self) -> &afio::EXTICR4 {
+        unsafe { &(*AFIO::ptr()).exticr4 }
+    }
 }
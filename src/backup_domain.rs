@@ -0,0 +1,95 @@
+//! Backup domain
+//!
+//! The backup data registers keep their contents across resets and, as long as VBAT is powered,
+//! across power loss as well.
+
+use stm32f103xx::{rcc, BKP, PWR, RCC};
+
+use pwr::Pwr;
+use rcc::{APB1, RccPeripheral};
+
+/// Number of backup data registers (medium density devices)
+pub const DATA_REGISTERS: usize = 10;
+
+/// Backup domain error
+#[derive(Debug)]
+pub enum Error {
+    /// The backup domain write protection (PWR CR DBP) is enabled
+    WriteProtected,
+    #[doc(hidden)] _Extensible,
+}
+
+/// Extension trait that constrains the `BKP` peripheral
+pub trait BkpExt {
+    /// Enables the BKP clock and disables the backup domain write protection
+    fn constrain(self, apb1: &mut APB1, pwr: &mut Pwr) -> BackupDomain;
+}
+
+impl BkpExt for BKP {
+    fn constrain(self, apb1: &mut APB1, pwr: &mut Pwr) -> BackupDomain {
+        // NOTE the BKP peripheral is not reset here as that would wipe the data registers
+        BKP::enable(apb1);
+
+        pwr.cr().modify(|_, w| w.dbp().set_bit());
+
+        BackupDomain { bkp: self }
+    }
+}
+
+/// Backup domain with write access enabled
+pub struct BackupDomain {
+    bkp: BKP,
+}
+
+impl BackupDomain {
+    /// Reads backup data register `n`
+    ///
+    /// # Panics
+    ///
+    /// If `n` is not less than `DATA_REGISTERS`
+    pub fn read_data_register(&self, n: usize) -> u16 {
+        assert!(n < DATA_REGISTERS);
+
+        // NOTE(unsafe) DR1 .. DR10 are laid out contiguously, one 32-bit word each; `n` is in range
+        unsafe { (*self.data_register(n)).read().d1().bits() }
+    }
+
+    /// Writes `value` to backup data register `n`
+    ///
+    /// # Panics
+    ///
+    /// If `n` is not less than `DATA_REGISTERS`
+    pub fn write_data_register(&mut self, n: usize, value: u16) -> Result<(), Error> {
+        assert!(n < DATA_REGISTERS);
+
+        if !self.is_writable() {
+            return Err(Error::WriteProtected);
+        }
+
+        // NOTE(unsafe) see `read_data_register`
+        unsafe { (*self.data_register(n)).write(|w| w.d1().bits(value)) }
+
+        Ok(())
+    }
+
+    /// Releases the BKP peripheral
+    ///
+    /// NOTE write protection and the clocks are left as they are
+    pub fn release(self) -> BKP {
+        self.bkp
+    }
+
+    pub(crate) fn bdcr(&mut self) -> &rcc::BDCR {
+        // NOTE(unsafe) the BDCR register is only accessed through the backup domain
+        unsafe { &(*RCC::ptr()).bdcr }
+    }
+
+    fn is_writable(&self) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*PWR::ptr()).cr.read().dbp().bit_is_set() }
+    }
+
+    fn data_register(&self, n: usize) -> *const ::stm32f103xx::bkp::DR1 {
+        (&self.bkp.dr1 as *const _ as *const u32).wrapping_offset(n as isize) as *const _
+    }
+}
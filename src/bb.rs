@@ -1,15 +1,21 @@
 //! Bit banding
+//!
+//! Each bit of the peripheral region is aliased to a word of the bit band region; writing that word
+//! sets or clears the bit in a single bus transaction, without a read-modify-write of the register
 
 use core::ptr;
 
+/// Clears `bit` of `register` atomically
 pub fn clear<T>(register: *const T, bit: u8) {
     write(register, bit, false);
 }
 
+/// Sets `bit` of `register` atomically
 pub fn set<T>(register: *const T, bit: u8) {
     write(register, bit, true);
 }
 
+/// Writes `set` into `bit` of `register` atomically
 pub fn write<T>(register: *const T, bit: u8, set: bool) {
     let addr = register as usize;
 
@@ -0,0 +1,509 @@
+//! Bit-banged buses on general purpose I/O pins
+//!
+//! These drivers toggle the pins from software, timed by a `DelayUs` provider, so they work on
+//! any pin but keep the CPU busy for the whole transfer. The pins must be configured as open
+//! drain outputs (`Output<OpenDrain>`) and the bus lines need external pull-up resistors.
+//!
+//! - `BitBangI2c`: I2C master on two pins
+//! - `OneWire`: 1-Wire master on a single pin
+
+use core::cmp;
+use core::convert::Infallible;
+
+use hal::blocking::delay::DelayUs;
+use hal::blocking::i2c::{Read, Write, WriteRead};
+use hal::digital::v2::{InputPin, OutputPin};
+
+pub use i2c::Error;
+use time::Hertz;
+
+/// Number of half SCL periods a slave may stretch the clock before giving up with
+/// `Error::Timeout`
+const STRETCH_TIMEOUT: u32 = 1_000;
+
+/// Unwraps the result of an operation on a pin of this crate, which can't fail
+fn infallible<T>(result: Result<T, Infallible>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => match e {},
+    }
+}
+
+/// I2C master driven from software
+///
+/// Slaves that stretch the clock are supported; there's no multi-master arbitration
+pub struct BitBangI2c<SDA, SCL, DELAY> {
+    sda: SDA,
+    scl: SCL,
+    delay: DELAY,
+    half_period_us: u32,
+}
+
+impl<SDA, SCL, DELAY> BitBangI2c<SDA, SCL, DELAY>
+where
+    SDA: OutputPin<Error = Infallible> + InputPin<Error = Infallible>,
+    SCL: OutputPin<Error = Infallible> + InputPin<Error = Infallible>,
+    DELAY: DelayUs<u32>,
+{
+    /// Creates an I2C master that clocks the bus at (up to) `freq`
+    ///
+    /// NOTE the delays have a resolution of 1 us so the clock frequency is at most 500 kHz; the
+    /// time spent toggling the pins makes the actual frequency somewhat lower than requested
+    pub fn new<F>(mut sda: SDA, mut scl: SCL, delay: DELAY, freq: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        let freq = freq.into().0;
+        assert!(freq > 0);
+
+        // release the bus
+        infallible(sda.set_high());
+        infallible(scl.set_high());
+
+        BitBangI2c {
+            sda,
+            scl,
+            delay,
+            half_period_us: cmp::max(500_000 / freq, 1),
+        }
+    }
+
+    /// Releases the pins and the delay provider
+    pub fn free(self) -> (SDA, SCL, DELAY) {
+        (self.sda, self.scl, self.delay)
+    }
+
+    fn wait(&mut self) {
+        let us = self.half_period_us;
+        self.delay.delay_us(us);
+    }
+
+    /// Releases SCL and waits until the slave stops stretching the clock
+    fn scl_high(&mut self) -> Result<(), Error> {
+        infallible(self.scl.set_high());
+
+        let mut timeout = STRETCH_TIMEOUT;
+        while infallible(self.scl.is_low()) {
+            if timeout == 0 {
+                return Err(Error::Timeout);
+            }
+
+            timeout -= 1;
+            self.wait();
+        }
+
+        Ok(())
+    }
+
+    /// Generates a (repeated) START condition
+    fn start(&mut self) -> Result<(), Error> {
+        infallible(self.sda.set_high());
+        self.wait();
+        self.scl_high()?;
+
+        // a slave is holding SDA low
+        if infallible(self.sda.is_low()) {
+            return Err(Error::Bus);
+        }
+
+        self.wait();
+        infallible(self.sda.set_low());
+        self.wait();
+        infallible(self.scl.set_low());
+
+        Ok(())
+    }
+
+    /// Generates a STOP condition
+    fn stop(&mut self) -> Result<(), Error> {
+        infallible(self.sda.set_low());
+        self.wait();
+        self.scl_high()?;
+        self.wait();
+        infallible(self.sda.set_high());
+        self.wait();
+
+        Ok(())
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<(), Error> {
+        if bit {
+            infallible(self.sda.set_high());
+        } else {
+            infallible(self.sda.set_low());
+        }
+
+        self.wait();
+        self.scl_high()?;
+        self.wait();
+        infallible(self.scl.set_low());
+
+        Ok(())
+    }
+
+    fn read_bit(&mut self) -> Result<bool, Error> {
+        infallible(self.sda.set_high());
+
+        self.wait();
+        self.scl_high()?;
+        self.wait();
+        let bit = infallible(self.sda.is_high());
+        infallible(self.scl.set_low());
+
+        Ok(bit)
+    }
+
+    /// Sends `byte` (MSB first) and checks that the slave acknowledged it
+    fn write_byte(&mut self, byte: u8) -> Result<(), Error> {
+        for i in (0..8).rev() {
+            self.write_bit(byte & (1 << i) != 0)?;
+        }
+
+        // NOTE the slave acknowledges by pulling SDA low
+        if self.read_bit()? {
+            Err(Error::Acknowledge)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Receives a byte (MSB first) and then acknowledges it if `ack` is `true`
+    fn read_byte(&mut self, ack: bool) -> Result<u8, Error> {
+        let mut byte = 0;
+        for _ in 0..8 {
+            byte = (byte << 1) | self.read_bit()? as u8;
+        }
+
+        self.write_bit(!ack)?;
+
+        Ok(byte)
+    }
+
+    fn send(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+        self.start()?;
+        self.write_byte(addr << 1)?;
+
+        for byte in bytes {
+            self.write_byte(*byte)?;
+        }
+
+        Ok(())
+    }
+
+    fn recv(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        self.start()?;
+        self.write_byte((addr << 1) | 1)?;
+
+        // NOTE the master NACKs the last byte to tell the slave to stop sending
+        let len = buffer.len();
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.read_byte(i + 1 != len)?;
+        }
+
+        Ok(())
+    }
+
+    /// Releases the bus with a STOP condition, even if `ret` is an error, and returns `ret`
+    fn end(&mut self, ret: Result<(), Error>) -> Result<(), Error> {
+        let stop = self.stop();
+
+        ret.and(stop)
+    }
+}
+
+impl<SDA, SCL, DELAY> Write for BitBangI2c<SDA, SCL, DELAY>
+where
+    SDA: OutputPin<Error = Infallible> + InputPin<Error = Infallible>,
+    SCL: OutputPin<Error = Infallible> + InputPin<Error = Infallible>,
+    DELAY: DelayUs<u32>,
+{
+    type Error = Error;
+
+    fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+        let ret = self.send(addr, bytes);
+        self.end(ret)
+    }
+}
+
+impl<SDA, SCL, DELAY> Read for BitBangI2c<SDA, SCL, DELAY>
+where
+    SDA: OutputPin<Error = Infallible> + InputPin<Error = Infallible>,
+    SCL: OutputPin<Error = Infallible> + InputPin<Error = Infallible>,
+    DELAY: DelayUs<u32>,
+{
+    type Error = Error;
+
+    fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        let ret = self.recv(addr, buffer);
+        self.end(ret)
+    }
+}
+
+impl<SDA, SCL, DELAY> WriteRead for BitBangI2c<SDA, SCL, DELAY>
+where
+    SDA: OutputPin<Error = Infallible> + InputPin<Error = Infallible>,
+    SCL: OutputPin<Error = Infallible> + InputPin<Error = Infallible>,
+    DELAY: DelayUs<u32>,
+{
+    type Error = Error;
+
+    fn write_read(&mut self, addr: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        let ret = self
+            .send(addr, bytes)
+            .and_then(|_| self.recv(addr, buffer));
+        self.end(ret)
+    }
+}
+
+/// 1-Wire ROM command: address a single device by its ROM code
+pub const MATCH_ROM: u8 = 0x55;
+/// 1-Wire ROM command: list the ROM codes of the devices on the bus
+pub const SEARCH_ROM: u8 = 0xf0;
+/// 1-Wire ROM command: address all the devices on the bus
+pub const SKIP_ROM: u8 = 0xcc;
+
+/// 1-Wire master driven from software
+///
+/// The time slots are a few microseconds long so interrupts that fire in the middle of a
+/// transaction can corrupt it; keep them short or run the transaction in a critical section
+pub struct OneWire<PIN> {
+    pin: PIN,
+}
+
+/// State of a ROM search; see `OneWire::search`
+pub struct RomSearch {
+    rom: [u8; 8],
+    // 1-based index of the ROM bit where the last search took the `0` branch; 0 if none
+    last_discrepancy: u8,
+    done: bool,
+}
+
+impl RomSearch {
+    /// Starts a new search
+    pub fn new() -> Self {
+        RomSearch {
+            rom: [0; 8],
+            last_discrepancy: 0,
+            done: false,
+        }
+    }
+}
+
+impl Default for RomSearch {
+    fn default() -> Self {
+        RomSearch::new()
+    }
+}
+
+impl<PIN> OneWire<PIN>
+where
+    PIN: OutputPin<Error = Infallible> + InputPin<Error = Infallible>,
+{
+    /// Creates a 1-Wire master on `pin` and releases the bus
+    pub fn new(mut pin: PIN) -> Self {
+        infallible(pin.set_high());
+
+        OneWire { pin }
+    }
+
+    /// Releases the pin
+    pub fn free(self) -> PIN {
+        self.pin
+    }
+
+    /// Sends a reset pulse and returns `true` if at least one device answered with a presence
+    /// pulse
+    pub fn reset<D>(&mut self, delay: &mut D) -> bool
+    where
+        D: DelayUs<u16>,
+    {
+        infallible(self.pin.set_low());
+        delay.delay_us(480);
+        infallible(self.pin.set_high());
+        delay.delay_us(70);
+        let present = infallible(self.pin.is_low());
+        delay.delay_us(410);
+
+        present
+    }
+
+    /// Writes a single bit
+    pub fn write_bit<D>(&mut self, bit: bool, delay: &mut D)
+    where
+        D: DelayUs<u16>,
+    {
+        infallible(self.pin.set_low());
+        if bit {
+            delay.delay_us(6);
+            infallible(self.pin.set_high());
+            delay.delay_us(64);
+        } else {
+            delay.delay_us(60);
+            infallible(self.pin.set_high());
+            delay.delay_us(10);
+        }
+    }
+
+    /// Reads a single bit
+    pub fn read_bit<D>(&mut self, delay: &mut D) -> bool
+    where
+        D: DelayUs<u16>,
+    {
+        infallible(self.pin.set_low());
+        delay.delay_us(6);
+        infallible(self.pin.set_high());
+        // NOTE the slave output is only valid for 15 us after the start of the slot
+        delay.delay_us(9);
+        let bit = infallible(self.pin.is_high());
+        delay.delay_us(55);
+
+        bit
+    }
+
+    /// Writes a byte, LSB first
+    pub fn write_byte<D>(&mut self, byte: u8, delay: &mut D)
+    where
+        D: DelayUs<u16>,
+    {
+        for i in 0..8 {
+            self.write_bit(byte & (1 << i) != 0, delay);
+        }
+    }
+
+    /// Reads a byte, LSB first
+    pub fn read_byte<D>(&mut self, delay: &mut D) -> u8
+    where
+        D: DelayUs<u16>,
+    {
+        let mut byte = 0;
+        for i in 0..8 {
+            if self.read_bit(delay) {
+                byte |= 1 << i;
+            }
+        }
+
+        byte
+    }
+
+    /// Resets the bus and addresses the device with ROM code `rom`
+    ///
+    /// Returns `false` if no device is present
+    pub fn select<D>(&mut self, rom: &[u8; 8], delay: &mut D) -> bool
+    where
+        D: DelayUs<u16>,
+    {
+        if !self.reset(delay) {
+            return false;
+        }
+
+        self.write_byte(MATCH_ROM, delay);
+        for byte in rom {
+            self.write_byte(*byte, delay);
+        }
+
+        true
+    }
+
+    /// Resets the bus and addresses all the devices on it
+    ///
+    /// Returns `false` if no device is present
+    pub fn skip_rom<D>(&mut self, delay: &mut D) -> bool
+    where
+        D: DelayUs<u16>,
+    {
+        if !self.reset(delay) {
+            return false;
+        }
+
+        self.write_byte(SKIP_ROM, delay);
+
+        true
+    }
+
+    /// Finds the ROM code of the next device on the bus
+    ///
+    /// Call this repeatedly with the same `search` to enumerate all the devices; it returns
+    /// `None` once all of them have been found. The ROM codes are returned as read from the bus:
+    /// family code first and CRC last (see `crc8`).
+    pub fn search<D>(&mut self, search: &mut RomSearch, delay: &mut D) -> Option<[u8; 8]>
+    where
+        D: DelayUs<u16>,
+    {
+        if search.done || !self.reset(delay) {
+            return None;
+        }
+
+        self.write_byte(SEARCH_ROM, delay);
+
+        let mut last_zero = 0;
+        for n in 1..65 {
+            let byte = usize::from((n - 1) / 8);
+            let mask = 1 << ((n - 1) % 8);
+
+            // every device sends its bit and then the complement of it
+            let bit = self.read_bit(delay);
+            let complement = self.read_bit(delay);
+
+            let dir = match (bit, complement) {
+                // no device answered
+                (true, true) => {
+                    *search = RomSearch::new();
+                    return None;
+                }
+                // all the remaining devices agree on this bit
+                (bit, complement) if bit != complement => bit,
+                // discrepancy: before the last discrepancy follow the path taken last time; at the
+                // last discrepancy take the `1` branch (the `0` one was explored last time); after
+                // it, a new discrepancy takes the `0` branch first and is remembered so that the
+                // next search comes back for its `1` branch
+                _ => {
+                    let dir = if n < search.last_discrepancy {
+                        search.rom[byte] & mask != 0
+                    } else {
+                        n == search.last_discrepancy
+                    };
+
+                    if !dir {
+                        last_zero = n;
+                    }
+
+                    dir
+                }
+            };
+
+            if dir {
+                search.rom[byte] |= mask;
+            } else {
+                search.rom[byte] &= !mask;
+            }
+
+            // only the devices whose bit matches `dir` stay in the search
+            self.write_bit(dir, delay);
+        }
+
+        search.last_discrepancy = last_zero;
+        search.done = last_zero == 0;
+
+        Some(search.rom)
+    }
+}
+
+/// Computes the Dallas/Maxim CRC-8 of `bytes`
+///
+/// The CRC of a ROM code, or of a scratchpad that includes its CRC byte, is `0`
+pub fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0;
+    for byte in bytes {
+        let mut byte = *byte;
+        for _ in 0..8 {
+            let mix = (crc ^ byte) & 1;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8c;
+            }
+            byte >>= 1;
+        }
+    }
+
+    crc
+}
@@ -0,0 +1,448 @@
+//! Controller Area Network (bxCAN)
+//!
+//! NOTE the CAN peripheral shares its 512 byte SRAM with the USB peripheral so the two can't be used
+//! at the same time
+
+use core::{cmp, ptr};
+
+use nb;
+use stm32f103xx::CAN;
+
+use afio::MAPR;
+use gpio::gpioa::{PA11, PA12};
+use gpio::gpiob::{PB8, PB9};
+use gpio::{Alternate, Floating, Input, PushPull};
+use rcc::{APB1, Clocks, RccPeripheral};
+use time::Bps;
+
+/// CAN error
+#[derive(Debug)]
+pub enum Error {
+    /// A frame was lost because the receive FIFO was full
+    Overrun,
+    #[doc(hidden)] _Extensible,
+}
+
+/// Frame identifier
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Id {
+    /// 11-bit standard identifier
+    Standard(u16),
+    /// 29-bit extended identifier
+    Extended(u32),
+}
+
+impl Id {
+    /// Returns the identifier laid out as in the TIxR / RIxR registers (IDE and RTR excluded)
+    fn bits(&self) -> u32 {
+        match *self {
+            Id::Standard(id) => u32::from(id & 0x7FF) << 21,
+            Id::Extended(id) => (id & 0x1FFF_FFFF) << 3 | IDE,
+        }
+    }
+
+    /// Returns the identifier laid out as in a 32-bit filter register, for data frames
+    pub fn filter32(&self) -> u32 {
+        self.bits()
+    }
+
+    /// Returns the identifier laid out as in a 16-bit filter register, for data frames
+    ///
+    /// NOTE only the 3 most significant bits of the extended part of an extended identifier fit
+    pub fn filter16(&self) -> u16 {
+        match *self {
+            Id::Standard(id) => (id & 0x7FF) << 5,
+            Id::Extended(id) => {
+                ((id >> 18) as u16 & 0x7FF) << 5 | 1 << 3 | ((id >> 15) as u16 & 0b111)
+            }
+        }
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        if bits & IDE != 0 {
+            Id::Extended(bits >> 3)
+        } else {
+            Id::Standard((bits >> 21) as u16)
+        }
+    }
+}
+
+// TIxR / RIxR bits
+const IDE: u32 = 1 << 2;
+const RTR: u32 = 1 << 1;
+const TXRQ: u32 = 1 << 0;
+
+/// CAN frame
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    id: Id,
+    remote: bool,
+    dlc: u8,
+    data: [u8; 8],
+}
+
+impl Frame {
+    /// Creates a data frame
+    ///
+    /// # Panics
+    ///
+    /// If `data` is longer than 8 bytes
+    pub fn new(id: Id, data: &[u8]) -> Self {
+        assert!(data.len() <= 8);
+
+        let mut frame = Frame {
+            id,
+            remote: false,
+            dlc: data.len() as u8,
+            data: [0; 8],
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        frame
+    }
+
+    /// Creates a remote frame requesting `dlc` bytes
+    ///
+    /// # Panics
+    ///
+    /// If `dlc` is larger than 8
+    pub fn new_remote(id: Id, dlc: u8) -> Self {
+        assert!(dlc <= 8);
+
+        Frame {
+            id,
+            remote: true,
+            dlc,
+            data: [0; 8],
+        }
+    }
+
+    /// Returns the identifier of this frame
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Returns `true` if this is a remote frame
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    /// Returns the data length code
+    pub fn dlc(&self) -> u8 {
+        self.dlc
+    }
+
+    /// Returns the payload of this frame; empty for remote frames
+    pub fn data(&self) -> &[u8] {
+        if self.remote {
+            &[]
+        } else {
+            &self.data[..self.dlc as usize]
+        }
+    }
+}
+
+/// Receive FIFO
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Fifo {
+    /// FIFO 0
+    Fifo0,
+    /// FIFO 1
+    Fifo1,
+}
+
+/// Number of filter banks
+pub const FILTER_BANKS: u8 = 14;
+
+/// Filter bank mode and scale
+///
+/// The meaning of the `id` and `mask` arguments of `Can::set_filter` depends on the mode; all
+/// values are laid out as in the filter registers (see `Id::filter32` and `Id::filter16`)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FilterMode {
+    /// One 32-bit identifier (`id`) and mask (`mask`); 1 bits in the mask must match
+    Mask32,
+    /// Two 32-bit identifiers: `id` and `mask`
+    List32,
+    /// Two 16-bit identifiers (the halves of `id`) and their masks (the halves of `mask`)
+    Mask16,
+    /// Four 16-bit identifiers: the halves of `id` and `mask`
+    List16,
+}
+
+pub trait Pins<CAN> {
+    const REMAP: u8;
+}
+
+impl Pins<CAN> for (PA12<Alternate<PushPull>>, PA11<Input<Floating>>) {
+    const REMAP: u8 = 0b00;
+}
+
+impl Pins<CAN> for (PB9<Alternate<PushPull>>, PB8<Input<Floating>>) {
+    const REMAP: u8 = 0b10;
+}
+
+/// CAN peripheral
+pub struct Can<CAN, PINS> {
+    can: CAN,
+    pins: PINS,
+}
+
+impl<PINS> Can<CAN, PINS> {
+    /// Configures the CAN peripheral to run at `bitrate` and joins the bus
+    ///
+    /// All incoming frames are accepted into FIFO 0 until filters are configured.
+    ///
+    /// NOTE this blocks until 11 consecutive recessive bits have been seen on the bus, i.e. a
+    /// transceiver must be connected
+    ///
+    /// # Panics
+    ///
+    /// If `bitrate` can't be derived exactly from PCLK1
+    pub fn can1(
+        can: CAN,
+        pins: PINS,
+        mapr: &mut MAPR,
+        bitrate: Bps,
+        clocks: Clocks,
+        apb: &mut APB1,
+    ) -> Self
+    where
+        PINS: Pins<CAN>,
+    {
+        mapr.modify_mapr(|_, w| unsafe { w.can_remap().bits(PINS::REMAP) });
+
+        // enable and reset CAN
+        CAN::enable(apb);
+        CAN::reset(apb);
+
+        let btr = bit_timing(clocks.pclk1().0, bitrate.0);
+
+        // leave sleep mode and request initialization
+        can.can_mcr
+            .modify(|_, w| w.sleep().clear_bit().inrq().set_bit());
+        while {
+            let msr = can.can_msr.read();
+            msr.inak().bit_is_clear() || msr.slak().bit_is_set()
+        } {}
+
+        can.can_btr.write(|w| unsafe { w.bits(btr) });
+
+        // automatically recover from the bus-off state
+        can.can_mcr.modify(|_, w| w.abom().set_bit());
+
+        // accept everything into FIFO 0: bank 0, 32-bit mask mode, all bits "don't care"
+        can.can_fmr.modify(|_, w| w.finit().set_bit());
+        can.can_fm1r.modify(|r, w| unsafe { w.bits(r.bits() & !1) });
+        can.can_fs1r.modify(|_, w| w.fsc0().set_bit());
+        can.can_ffa1r.modify(|_, w| w.ffa0().clear_bit());
+        can.f0r1.write(|w| unsafe { w.bits(0) });
+        can.f0r2.write(|w| unsafe { w.bits(0) });
+        can.can_fa1r.modify(|_, w| w.fact0().set_bit());
+        can.can_fmr.modify(|_, w| w.finit().clear_bit());
+
+        // switch to normal mode
+        can.can_mcr.modify(|_, w| w.inrq().clear_bit());
+        while can.can_msr.read().inak().bit_is_set() {}
+
+        Can { can, pins }
+    }
+
+    /// Configures filter `bank` and routes the frames it accepts to `fifo`
+    ///
+    /// NOTE bank 0 is initially configured to accept all the frames; reconfigure or disable it to
+    /// only receive the frames accepted by the other banks
+    ///
+    /// # Panics
+    ///
+    /// If `bank` is not less than `FILTER_BANKS`
+    pub fn set_filter(&mut self, bank: u8, mode: FilterMode, id: u32, mask: u32, fifo: Fifo) {
+        assert!(bank < FILTER_BANKS);
+
+        let bit = 1 << bank;
+        let (list, scale32, fr1, fr2) = match mode {
+            FilterMode::Mask32 => (false, true, id, mask),
+            FilterMode::List32 => (true, true, id, mask),
+            FilterMode::Mask16 => (
+                false,
+                false,
+                (mask & 0xFFFF) << 16 | (id & 0xFFFF),
+                (mask & 0xFFFF_0000) | (id >> 16),
+            ),
+            FilterMode::List16 => (true, false, id, mask),
+        };
+
+        let can = &self.can;
+        let set = |r: u32, value: bool| if value { r | bit } else { r & !bit };
+
+        // NOTE(unsafe) there are only 14 banks and `bank` has been checked
+        unsafe {
+            can.can_fmr.modify(|_, w| w.finit().set_bit());
+            can.can_fa1r.modify(|r, w| w.bits(set(r.bits(), false)));
+
+            can.can_fm1r.modify(|r, w| w.bits(set(r.bits(), list)));
+            can.can_fs1r.modify(|r, w| w.bits(set(r.bits(), scale32)));
+            can.can_ffa1r
+                .modify(|r, w| w.bits(set(r.bits(), fifo == Fifo::Fifo1)));
+
+            let fr = &can.f0r1 as *const _ as *mut u32;
+            ptr::write_volatile(fr.offset(2 * bank as isize), fr1);
+            ptr::write_volatile(fr.offset(2 * bank as isize + 1), fr2);
+
+            can.can_fa1r.modify(|r, w| w.bits(set(r.bits(), true)));
+            can.can_fmr.modify(|_, w| w.finit().clear_bit());
+        }
+    }
+
+    /// Disables filter `bank`
+    ///
+    /// # Panics
+    ///
+    /// If `bank` is not less than `FILTER_BANKS`
+    pub fn disable_filter(&mut self, bank: u8) {
+        assert!(bank < FILTER_BANKS);
+
+        self.can
+            .can_fa1r
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << bank)) });
+    }
+
+    /// Queues `frame` for transmission in one of the three transmit mailboxes
+    ///
+    /// Returns `WouldBlock` if all the mailboxes are busy
+    pub fn transmit(&mut self, frame: &Frame) -> nb::Result<(), !> {
+        let tsr = self.can.can_tsr.read();
+
+        let tir = frame.id.bits() | if frame.remote { RTR } else { 0 } | TXRQ;
+        let tdtr = u32::from(frame.dlc);
+        let tdlr = u32::from(frame.data[0])
+            | u32::from(frame.data[1]) << 8
+            | u32::from(frame.data[2]) << 16
+            | u32::from(frame.data[3]) << 24;
+        let tdhr = u32::from(frame.data[4])
+            | u32::from(frame.data[5]) << 8
+            | u32::from(frame.data[6]) << 16
+            | u32::from(frame.data[7]) << 24;
+
+        macro_rules! mailbox {
+            ($tir:ident, $tdtr:ident, $tdlr:ident, $tdhr:ident) => {{
+                unsafe {
+                    self.can.$tdtr.write(|w| w.bits(tdtr));
+                    self.can.$tdlr.write(|w| w.bits(tdlr));
+                    self.can.$tdhr.write(|w| w.bits(tdhr));
+                    // NOTE setting TXRQ must be the last write
+                    self.can.$tir.write(|w| w.bits(tir));
+                }
+                Ok(())
+            }};
+        }
+
+        if tsr.tme0().bit_is_set() {
+            mailbox!(can_ti0r, can_tdt0r, can_tdl0r, can_tdh0r)
+        } else if tsr.tme1().bit_is_set() {
+            mailbox!(can_ti1r, can_tdt1r, can_tdl1r, can_tdh1r)
+        } else if tsr.tme2().bit_is_set() {
+            mailbox!(can_ti2r, can_tdt2r, can_tdl2r, can_tdh2r)
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Returns `true` if all the transmit mailboxes are empty
+    pub fn is_transmit_idle(&self) -> bool {
+        let tsr = self.can.can_tsr.read();
+
+        tsr.tme0().bit_is_set() && tsr.tme1().bit_is_set() && tsr.tme2().bit_is_set()
+    }
+
+    /// Takes the oldest frame out of the receive `fifo`
+    ///
+    /// Returns `Error::Overrun`, once, if a frame was lost; the frames still in the FIFO can be
+    /// read afterwards
+    pub fn receive(&mut self, fifo: Fifo) -> nb::Result<Frame, Error> {
+        macro_rules! fifo {
+            ($rfr:ident, $fovr:ident, $fmp:ident, $rfom:ident,
+             $rir:ident, $rdtr:ident, $rdlr:ident, $rdhr:ident) => {{
+                let rfr = self.can.$rfr.read();
+
+                if rfr.$fovr().bit_is_set() {
+                    // NOTE this flag is cleared by writing 1 to it
+                    self.can.$rfr.write(|w| w.$fovr().set_bit());
+                    return Err(nb::Error::Other(Error::Overrun));
+                }
+
+                if rfr.$fmp().bits() == 0 {
+                    return Err(nb::Error::WouldBlock);
+                }
+
+                let rir = self.can.$rir.read().bits();
+                let rdtr = self.can.$rdtr.read().bits();
+                let rdlr = self.can.$rdlr.read().bits();
+                let rdhr = self.can.$rdhr.read().bits();
+
+                // release the output mailbox
+                self.can.$rfr.write(|w| w.$rfom().set_bit());
+
+                (rir, rdtr, rdlr, rdhr)
+            }};
+        }
+
+        let (rir, rdtr, rdlr, rdhr) = match fifo {
+            Fifo::Fifo0 => fifo!(
+                can_rf0r, fovr0, fmp0, rfom0, can_ri0r, can_rdt0r, can_rdl0r, can_rdh0r
+            ),
+            Fifo::Fifo1 => fifo!(
+                can_rf1r, fovr1, fmp1, rfom1, can_ri1r, can_rdt1r, can_rdl1r, can_rdh1r
+            ),
+        };
+
+        Ok(Frame {
+            id: Id::from_bits(rir),
+            remote: rir & RTR != 0,
+            dlc: (rdtr & 0xF) as u8,
+            data: [
+                rdlr as u8,
+                (rdlr >> 8) as u8,
+                (rdlr >> 16) as u8,
+                (rdlr >> 24) as u8,
+                rdhr as u8,
+                (rdhr >> 8) as u8,
+                (rdhr >> 16) as u8,
+                (rdhr >> 24) as u8,
+            ],
+        })
+    }
+
+    /// Releases the CAN peripheral and associated pins
+    pub fn free(self) -> (CAN, PINS) {
+        (self.can, self.pins)
+    }
+}
+
+/// Computes the BTR value for the given bitrate
+///
+/// Picks the smallest prescaler that yields 8 - 25 time quanta per bit, with the sample point as
+/// close as possible to 87.5%
+fn bit_timing(pclk1: u32, bitrate: u32) -> u32 {
+    for brp in 1..1025 {
+        if pclk1 % (bitrate * brp) != 0 {
+            continue;
+        }
+
+        let tq = pclk1 / (bitrate * brp);
+        if tq < 8 || tq > 25 {
+            continue;
+        }
+
+        // NOTE one quantum is always spent in the synchronization segment
+        let ts2 = cmp::min(cmp::max((tq + 4) / 8, 1), 8);
+        let ts1 = tq - 1 - ts2;
+        if ts1 > 16 {
+            continue;
+        }
+
+        // SJW = 1 tq
+        return (ts2 - 1) << 20 | (ts1 - 1) << 16 | (brp - 1);
+    }
+
+    panic!("unreachable CAN bitrate");
+}
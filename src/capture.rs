@@ -0,0 +1,335 @@
+//! Input capture
+
+use cast::{u16, u32};
+use hal;
+use nb;
+use stm32f103xx::{TIM2, TIM3, TIM4};
+
+use afio::MAPR;
+use bb;
+use gpio::gpioa::{PA0, PA1, PA2, PA3, PA6, PA7};
+use gpio::gpiob::{PB0, PB1, PB6, PB7, PB8, PB9};
+use gpio::{Floating, Input};
+use rcc::{APB1, Clocks, RccPeripheral};
+use time::Hertz;
+
+/// Input capture error
+#[derive(Debug)]
+pub enum Error {
+    /// A new value was captured before the previous one was read
+    Overcapture,
+    #[doc(hidden)] _Extensible,
+}
+
+/// Capture channel
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Channel {
+    /// Channel 1
+    _1,
+    /// Channel 2
+    _2,
+    /// Channel 3
+    _3,
+    /// Channel 4
+    _4,
+}
+
+/// Edge that triggers a capture
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Edge {
+    /// Low to high transition
+    Rising,
+    /// High to low transition
+    Falling,
+    /// Both transitions
+    ///
+    /// NOTE the F1 timers can only capture on one edge so the polarity is flipped in software
+    /// after every capture
+    RisingFalling,
+}
+
+pub trait Pins<TIM> {
+    const REMAP: u8;
+    const C1: bool;
+    const C2: bool;
+    const C3: bool;
+    const C4: bool;
+}
+
+impl Pins<TIM2>
+    for (
+        PA0<Input<Floating>>,
+        PA1<Input<Floating>>,
+        PA2<Input<Floating>>,
+        PA3<Input<Floating>>,
+    ) {
+    const REMAP: u8 = 0b00;
+    const C1: bool = true;
+    const C2: bool = true;
+    const C3: bool = true;
+    const C4: bool = true;
+}
+
+impl Pins<TIM2> for PA0<Input<Floating>> {
+    const REMAP: u8 = 0b00;
+    const C1: bool = true;
+    const C2: bool = false;
+    const C3: bool = false;
+    const C4: bool = false;
+}
+
+impl Pins<TIM3>
+    for (
+        PA6<Input<Floating>>,
+        PA7<Input<Floating>>,
+        PB0<Input<Floating>>,
+        PB1<Input<Floating>>,
+    ) {
+    const REMAP: u8 = 0b00;
+    const C1: bool = true;
+    const C2: bool = true;
+    const C3: bool = true;
+    const C4: bool = true;
+}
+
+impl Pins<TIM3> for PA6<Input<Floating>> {
+    const REMAP: u8 = 0b00;
+    const C1: bool = true;
+    const C2: bool = false;
+    const C3: bool = false;
+    const C4: bool = false;
+}
+
+impl Pins<TIM4>
+    for (
+        PB6<Input<Floating>>,
+        PB7<Input<Floating>>,
+        PB8<Input<Floating>>,
+        PB9<Input<Floating>>,
+    ) {
+    const REMAP: u8 = 0b0;
+    const C1: bool = true;
+    const C2: bool = true;
+    const C3: bool = true;
+    const C4: bool = true;
+}
+
+impl Pins<TIM4> for PB6<Input<Floating>> {
+    const REMAP: u8 = 0b0;
+    const C1: bool = true;
+    const C2: bool = false;
+    const C3: bool = false;
+    const C4: bool = false;
+}
+
+/// Input capture on the channels of a general purpose timer
+///
+/// The captured values are counter values; the counter ticks at the configured resolution and
+/// wraps around at `u16::MAX`
+pub struct InputCapture<TIM, PINS> {
+    tim: TIM,
+    pins: PINS,
+    clocks: Clocks,
+    both_edges: [bool; 4],
+}
+
+impl<PINS> InputCapture<TIM2, PINS> {
+    pub fn tim2<T>(
+        tim: TIM2,
+        pins: PINS,
+        mapr: &mut MAPR,
+        resolution: T,
+        clocks: Clocks,
+        apb: &mut APB1,
+    ) -> Self
+    where
+        PINS: Pins<TIM2>,
+        T: Into<Hertz>,
+    {
+        mapr.modify_mapr(|_, w| unsafe { w.tim2_remap().bits(PINS::REMAP) });
+
+        InputCapture::_tim2(tim, pins, resolution.into(), clocks, apb)
+    }
+}
+
+impl<PINS> InputCapture<TIM3, PINS> {
+    pub fn tim3<T>(
+        tim: TIM3,
+        pins: PINS,
+        mapr: &mut MAPR,
+        resolution: T,
+        clocks: Clocks,
+        apb: &mut APB1,
+    ) -> Self
+    where
+        PINS: Pins<TIM3>,
+        T: Into<Hertz>,
+    {
+        mapr.modify_mapr(|_, w| unsafe { w.tim3_remap().bits(PINS::REMAP) });
+
+        InputCapture::_tim3(tim, pins, resolution.into(), clocks, apb)
+    }
+}
+
+impl<PINS> InputCapture<TIM4, PINS> {
+    pub fn tim4<T>(
+        tim: TIM4,
+        pins: PINS,
+        mapr: &mut MAPR,
+        resolution: T,
+        clocks: Clocks,
+        apb: &mut APB1,
+    ) -> Self
+    where
+        PINS: Pins<TIM4>,
+        T: Into<Hertz>,
+    {
+        mapr.modify_mapr(|_, w| w.tim4_remap().bit(PINS::REMAP == 1));
+
+        InputCapture::_tim4(tim, pins, resolution.into(), clocks, apb)
+    }
+}
+
+macro_rules! hal {
+    ($($TIMX:ident: ($timX:ident),)+) => {
+        $(
+            impl<PINS> InputCapture<$TIMX, PINS>
+            where
+                PINS: Pins<$TIMX>,
+            {
+                fn $timX(
+                    tim: $TIMX,
+                    pins: PINS,
+                    resolution: Hertz,
+                    clocks: Clocks,
+                    apb: &mut APB1,
+                ) -> Self {
+                    // enable and reset peripheral to a clean slate state
+                    $TIMX::enable(apb);
+                    $TIMX::reset(apb);
+
+                    // CCxS = 0b01: map ICx onto TIx, i.e. configure the channel as a capture
+                    // NOTE(unsafe) the captures are still disabled (CCxE = 0)
+                    let cc1s = if PINS::C1 { 0b01 } else { 0b00 };
+                    let cc2s = if PINS::C2 { 0b01 } else { 0b00 };
+                    let cc3s = if PINS::C3 { 0b01 } else { 0b00 };
+                    let cc4s = if PINS::C4 { 0b01 } else { 0b00 };
+                    tim.ccmr1_output
+                        .write(|w| unsafe { w.bits((cc1s << 0) | (cc2s << 8)) });
+                    tim.ccmr2_output
+                        .write(|w| unsafe { w.bits((cc3s << 0) | (cc4s << 8)) });
+
+                    tim.arr.write(|w| w.arr().bits(u16::max_value()));
+
+                    let mut capture = InputCapture {
+                        tim,
+                        pins,
+                        clocks,
+                        both_edges: [false; 4],
+                    };
+                    hal::Capture::set_resolution(&mut capture, resolution);
+
+                    capture.tim.cr1.write(|w| w.cen().enabled());
+
+                    capture
+                }
+
+                /// Selects the edge that triggers a capture on `channel`
+                pub fn set_edge(&mut self, channel: Channel, edge: Edge) {
+                    let i = channel as usize;
+
+                    self.both_edges[i] = edge == Edge::RisingFalling;
+
+                    bb::write(&self.tim.ccer, 4 * i as u8 + 1, edge == Edge::Falling);
+                }
+
+                pub fn release(self) -> ($TIMX, PINS) {
+                    (self.tim, self.pins)
+                }
+            }
+
+            impl<PINS> hal::Capture for InputCapture<$TIMX, PINS>
+            where
+                PINS: Pins<$TIMX>,
+            {
+                type Error = Error;
+                type Channel = Channel;
+                type Time = Hertz;
+                type Capture = u16;
+
+                fn capture(&mut self, channel: Channel) -> nb::Result<u16, Error> {
+                    let i = channel as usize;
+                    let sr = self.tim.sr.read().bits();
+
+                    if sr & (1 << (9 + i)) != 0 {
+                        // NOTE(unsafe) the status flags are cleared by writing zero; writing one
+                        // has no effect
+                        self.tim.sr.write(|w| unsafe { w.bits(!(1 << (9 + i))) });
+
+                        return Err(nb::Error::Other(Error::Overcapture));
+                    }
+
+                    if sr & (1 << (1 + i)) == 0 {
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    // NOTE reading CCRx clears the CCxIF flag
+                    let value = match channel {
+                        Channel::_1 => self.tim.ccr1.read().ccr1().bits(),
+                        Channel::_2 => self.tim.ccr2.read().ccr2().bits(),
+                        Channel::_3 => self.tim.ccr3.read().ccr3().bits(),
+                        Channel::_4 => self.tim.ccr4.read().ccr4().bits(),
+                    };
+
+                    if self.both_edges[i] {
+                        let ccxp = 4 * i as u8 + 1;
+                        let falling = self.tim.ccer.read().bits() & (1 << ccxp) != 0;
+
+                        bb::write(&self.tim.ccer, ccxp, !falling);
+                    }
+
+                    Ok(value)
+                }
+
+                fn disable(&mut self, channel: Channel) {
+                    bb::clear(&self.tim.ccer, 4 * channel as u8);
+                }
+
+                fn enable(&mut self, channel: Channel) {
+                    let available = match channel {
+                        Channel::_1 => PINS::C1,
+                        Channel::_2 => PINS::C2,
+                        Channel::_3 => PINS::C3,
+                        Channel::_4 => PINS::C4,
+                    };
+                    assert!(available, "no pin for this channel");
+
+                    bb::set(&self.tim.ccer, 4 * channel as u8);
+                }
+
+                fn get_resolution(&self) -> Hertz {
+                    let psc = self.tim.psc.read().psc().bits();
+
+                    Hertz(self.clocks.pclk1_tim().0 / (u32(psc) + 1))
+                }
+
+                fn set_resolution<R>(&mut self, resolution: R)
+                where
+                    R: Into<Hertz>,
+                {
+                    let psc = self.clocks.pclk1_tim().0 / resolution.into().0 - 1;
+                    self.tim.psc.write(|w| w.psc().bits(u16(psc).unwrap()));
+
+                    // NOTE PSC is buffered; generate an update event to load it
+                    self.tim.egr.write(|w| w.ug().set_bit());
+                }
+            }
+        )+
+    }
+}
+
+hal! {
+    TIM2: (_tim2),
+    TIM3: (_tim3),
+    TIM4: (_tim4),
+}
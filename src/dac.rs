@@ -0,0 +1,136 @@
+//! Digital to analog converter
+//!
+//! NOTE only the high density devices (e.g. STM32F103RC / VC / ZC) have a DAC
+
+use core::marker::PhantomData;
+use core::mem;
+
+use stm32f103xx::DAC;
+
+use bb;
+use gpio::gpioa::{PA4, PA5};
+use gpio::Analog;
+use rcc::{APB1, RccPeripheral};
+
+/// Output pins of the DAC
+pub trait Pins<DAC> {
+    type Channels;
+}
+
+impl Pins<DAC> for PA4<Analog> {
+    type Channels = Dac<C1>;
+}
+
+impl Pins<DAC> for PA5<Analog> {
+    type Channels = Dac<C2>;
+}
+
+impl Pins<DAC> for (PA4<Analog>, PA5<Analog>) {
+    type Channels = (Dac<C1>, Dac<C2>);
+}
+
+/// Format of the values passed to `Dac::set_value_aligned`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alignment {
+    /// 12-bit value in the bits 0:11
+    Right12,
+    /// 12-bit value in the bits 4:15
+    Left12,
+    /// 8-bit value in the bits 0:7; the DAC output covers the full range at a lower resolution
+    Right8,
+}
+
+/// Extension trait to configure the DAC
+pub trait DacExt: Sized {
+    /// Enables the DAC and returns one `Dac` per output pin
+    ///
+    /// The channels start disabled with an output of 0 V
+    fn dac<PINS>(self, pins: PINS, apb: &mut APB1) -> PINS::Channels
+    where
+        PINS: Pins<Self>;
+}
+
+impl DacExt for DAC {
+    fn dac<PINS>(self, _pins: PINS, apb: &mut APB1) -> PINS::Channels
+    where
+        PINS: Pins<Self>,
+    {
+        DAC::enable(apb);
+        DAC::reset(apb);
+
+        unsafe { mem::uninitialized() }
+    }
+}
+
+/// DAC channel `CHANNEL`
+pub struct Dac<CHANNEL> {
+    _channel: PhantomData<CHANNEL>,
+}
+
+/// Channel 1 (PA4)
+pub struct C1;
+/// Channel 2 (PA5)
+pub struct C2;
+
+macro_rules! hal {
+    ($($CX:ident: (
+        $enX:expr,
+        $dhr12rX:ident,
+        $dhr12lX:ident,
+        $dhr8rX:ident,
+        $daccXdhr:ident,
+        $dorX:ident,
+        $daccXdor:ident
+    ),)+) => {
+        $(
+            impl Dac<$CX> {
+                /// Enables the channel output
+                pub fn enable(&mut self) {
+                    // NOTE(bb) CR is shared by both channels
+                    unsafe { bb::set(&(*DAC::ptr()).cr, $enX) }
+                }
+
+                /// Disables the channel output
+                pub fn disable(&mut self) {
+                    unsafe { bb::clear(&(*DAC::ptr()).cr, $enX) }
+                }
+
+                /// Sets the output to `value` (12-bit, right aligned)
+                ///
+                /// The output voltage is `VREF+ * value / 4096`
+                pub fn set_value(&mut self, value: u16) {
+                    self.set_value_aligned(value, Alignment::Right12)
+                }
+
+                /// Sets the output to `value`, which is interpreted according to `alignment`
+                pub fn set_value_aligned(&mut self, value: u16, alignment: Alignment) {
+                    let dac = unsafe { &*DAC::ptr() };
+
+                    match alignment {
+                        Alignment::Right12 => {
+                            assert!(value < 1 << 12);
+                            dac.$dhr12rX.write(|w| unsafe { w.$daccXdhr().bits(value) })
+                        }
+                        Alignment::Left12 => {
+                            dac.$dhr12lX.write(|w| unsafe { w.$daccXdhr().bits(value >> 4) })
+                        }
+                        Alignment::Right8 => {
+                            assert!(value < 1 << 8);
+                            dac.$dhr8rX.write(|w| unsafe { w.$daccXdhr().bits(value as u8) })
+                        }
+                    }
+                }
+
+                /// Returns the value currently being output (12-bit, right aligned)
+                pub fn get_value(&self) -> u16 {
+                    unsafe { (*DAC::ptr()).$dorX.read().$daccXdor().bits() }
+                }
+            }
+        )+
+    }
+}
+
+hal! {
+    C1: (0, dhr12r1, dhr12l1, dhr8r1, dacc1dhr, dor1, dacc1dor),
+    C2: (16, dhr12r2, dhr12l2, dhr8r2, dacc2dhr, dor2, dacc2dor),
+}
@@ -1,6 +1,6 @@
 //! Delays
 
-use cast::u32;
+use cast::{u32, u64};
 use cortex_m::peripheral::SYST;
 use cortex_m::peripheral::syst::SystClkSource;
 
@@ -25,11 +25,41 @@ impl Delay {
     pub fn free(self) -> SYST {
         self.syst
     }
+
+    // NOTE(u64) `ticks` doesn't fit in a `u32` for delays longer than 59 seconds at 72 MHz
+    fn delay_ticks(&mut self, mut ticks: u64) {
+        // The RVR register is 24 bits wide, as SysTick is based on a 24 bit counter
+        const MAX_RVR: u32 = 0x00FF_FFFF;
+
+        while ticks != 0 {
+            let current_rvr = if ticks < u64(MAX_RVR) {
+                ticks as u32
+            } else {
+                MAX_RVR
+            };
+
+            self.syst.set_reload(current_rvr);
+            self.syst.clear_current();
+            self.syst.enable_counter();
+
+            // Update the tracking variable while we are waiting...
+            ticks -= u64(current_rvr);
+
+            while !self.syst.has_wrapped() {}
+
+            self.syst.disable_counter();
+        }
+    }
+
+    fn ticks_per_us(&self) -> u64 {
+        u64(self.clocks.sysclk().0 / 1_000_000)
+    }
 }
 
 impl DelayMs<u32> for Delay {
     fn delay_ms(&mut self, ms: u32) {
-        self.delay_us(ms * 1_000);
+        let ticks = u64(ms) * 1_000 * self.ticks_per_us();
+        self.delay_ticks(ticks);
     }
 }
 
@@ -47,29 +77,8 @@ impl DelayMs<u8> for Delay {
 
 impl DelayUs<u32> for Delay {
     fn delay_us(&mut self, us: u32) {
-        // The RVR register is 24 bits wide, as SysTick is based on a 24 bit counter
-        const MAX_RVR: u32 = (1 << 24);
-        
-        let mut total_rvr = us * (self.clocks.sysclk().0 / 1_000_000);
-
-        while total_rvr != 0 {
-            let current_rvr = if total_rvr < MAX_RVR {
-                total_rvr
-            } else {
-                MAX_RVR
-            };
-
-            self.syst.set_reload(current_rvr);
-            self.syst.clear_current();
-            self.syst.enable_counter();
-
-            // Update the tracking variable while we are waiting...
-            total_rvr -= current_rvr;
-
-            while !self.syst.has_wrapped() {}
-
-            self.syst.disable_counter();
-        }
+        let ticks = u64(us) * self.ticks_per_us();
+        self.delay_ticks(ticks);
     }
 }
 
@@ -16,6 +16,15 @@ pub enum Event {
     TransferComplete,
 }
 
+/// Direction of a transfer
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// From the peripheral address to the memory address
+    PeripheralToMemory,
+    /// From the memory address to the peripheral address
+    MemoryToPeripheral,
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum Half {
     First,
@@ -41,6 +50,26 @@ impl<BUFFER, CHANNEL> CircBuffer<BUFFER, CHANNEL> {
     }
 }
 
+/// A circular transfer that keeps overwriting `buffer` as if it was a ring buffer
+pub struct RingBuffer<BUFFER, CHANNEL>
+where
+    BUFFER: 'static,
+{
+    buffer: &'static mut BUFFER,
+    channel: CHANNEL,
+    len: usize,
+}
+
+impl<BUFFER, CHANNEL> RingBuffer<BUFFER, CHANNEL> {
+    pub(crate) fn new(buffer: &'static mut BUFFER, channel: CHANNEL, len: usize) -> Self {
+        RingBuffer {
+            buffer,
+            channel,
+            len,
+        }
+    }
+}
+
 pub trait Static<B> {
     fn borrow(&self) -> &B;
 }
@@ -131,7 +160,7 @@ macro_rules! dma {
 
                 use stm32f103xx::{$DMAX, dma1};
 
-                use dma::{CircBuffer, DmaExt, Error, Event, Half, Transfer, W};
+                use dma::{CircBuffer, Direction, DmaExt, Error, Event, Half, RingBuffer, Transfer, W};
                 use rcc::AHB;
 
                 pub struct Channels((), $(pub $CX),+);
@@ -185,6 +214,68 @@ macro_rules! dma {
                             unsafe { &(*$DMAX::ptr()).$cmarX }
                         }
 
+                        /// Sets the peripheral address and whether it's incremented after each
+                        /// element
+                        ///
+                        /// # Safety
+                        ///
+                        /// Once started the DMA will access `address` behind the back of the
+                        /// compiler; it must point to a valid peripheral register or buffer
+                        pub unsafe fn set_peripheral_address(&mut self, address: u32, inc: bool) {
+                            self.cpar().write(|w| w.pa().bits(address));
+                            self.ccr().modify(|_, w| w.pinc().bit(inc));
+                        }
+
+                        /// Sets the memory address and whether it's incremented after each element
+                        ///
+                        /// # Safety
+                        ///
+                        /// Once started the DMA will access `address` behind the back of the
+                        /// compiler; it must point to a buffer that outlives the transfer
+                        pub unsafe fn set_memory_address(&mut self, address: u32, inc: bool) {
+                            self.cmar().write(|w| w.ma().bits(address));
+                            self.ccr().modify(|_, w| w.minc().bit(inc));
+                        }
+
+                        /// Sets the number of elements to transfer
+                        pub fn set_transfer_length(&mut self, len: usize) {
+                            assert!(len <= u16::max_value() as usize);
+
+                            self.cndtr().write(|w| unsafe { w.ndt().bits(len as u16) });
+                        }
+
+                        /// Sets the direction of the transfer
+                        pub fn set_direction(&mut self, direction: Direction) {
+                            self.ccr().modify(|_, w| {
+                                w.dir().bit(direction == Direction::MemoryToPeripheral)
+                            });
+                        }
+
+                        /// Sets whether the transfer restarts from the beginning once it's done
+                        pub fn set_circular(&mut self, circ: bool) {
+                            self.ccr().modify(|_, w| w.circ().bit(circ));
+                        }
+
+                        /// Starts the transfer
+                        pub fn start(&mut self) {
+                            self.ccr().modify(|_, w| w.en().set_bit());
+                        }
+
+                        /// Returns `true` if the channel is enabled and there are elements left
+                        /// to transfer
+                        pub fn in_progress(&self) -> bool {
+                            // NOTE(unsafe) atomic read with no side effects
+                            let enabled = unsafe { (*$DMAX::ptr()).$ccrX.read().en().bit_is_set() };
+
+                            enabled && self.isr().$tcifX().bit_is_clear()
+                        }
+
+                        /// Disables the channel and clears its interrupt flags
+                        pub fn stop(&mut self) {
+                            self.ifcr().write(|w| w.$cgifX().set_bit());
+                            self.ccr().modify(|_, w| w.en().clear_bit());
+                        }
+
                         pub(crate) fn get_cndtr(&self) -> u32 {
                             // NOTE(unsafe) atomic read with no side effects
                             unsafe { (*$DMAX::ptr()).$cndtrX.read().bits() }
@@ -192,6 +283,82 @@ macro_rules! dma {
 
                     }
 
+                    impl $CX {
+                        /// Copies `src` into `dst` using a memory to memory transfer
+                        ///
+                        /// Words or half words are moved at a time when the alignment and length
+                        /// of both buffers allow it
+                        ///
+                        /// # Panics
+                        ///
+                        /// If the buffers are empty or have different lengths
+                        pub fn mem2mem<S, D>(
+                            mut self,
+                            src: &'static S,
+                            dst: &'static mut D,
+                        ) -> Transfer<W, &'static mut D, $CX, &'static S>
+                        where
+                            S: Unsize<[u8]>,
+                            D: Unsize<[u8]>,
+                        {
+                            {
+                                let src: &[u8] = src;
+                                let dst: &mut [u8] = dst;
+                                assert_eq!(src.len(), dst.len());
+                                // NOTE the transfer complete flag never gets set if there's
+                                // nothing to transfer
+                                assert!(src.len() > 0);
+
+                                let src_addr = src.as_ptr() as usize as u32;
+                                let dst_addr = dst.as_ptr() as usize as u32;
+                                let len = src.len() as u32;
+
+                                let size = if (src_addr | dst_addr | len) % 4 == 0 {
+                                    4
+                                } else if (src_addr | dst_addr | len) % 2 == 0 {
+                                    2
+                                } else {
+                                    1
+                                };
+
+                                // NOTE in memory to memory mode the DMA reads from the
+                                // "peripheral" address when DIR = 0
+                                self.cpar().write(|w| unsafe { w.pa().bits(src_addr) });
+                                self.cmar().write(|w| unsafe { w.ma().bits(dst_addr) });
+                                self.set_transfer_length((len / size) as usize);
+
+                                // NOTE(compiler_fence) operations on the buffers should not be
+                                // reordered after the next statement, which starts the DMA transfer
+                                atomic::compiler_fence(Ordering::SeqCst);
+
+                                self.ccr().modify(|_, w| {
+                                    let w = match size {
+                                        4 => w.msize().bit32().psize().bit32(),
+                                        2 => w.msize().bit16().psize().bit16(),
+                                        _ => w.msize().bit8().psize().bit8(),
+                                    };
+
+                                    w.mem2mem()
+                                        .set_bit()
+                                        .pl()
+                                        .medium()
+                                        .minc()
+                                        .set_bit()
+                                        .pinc()
+                                        .set_bit()
+                                        .circ()
+                                        .clear_bit()
+                                        .dir()
+                                        .clear_bit()
+                                        .en()
+                                        .set_bit()
+                                });
+                            }
+
+                            Transfer::w(dst, self, src)
+                        }
+                    }
+
                     impl<B> CircBuffer<B, $CX> {
                         /// Peeks into the readable half of the buffer
                         pub fn peek<R, F>(&mut self, f: F) -> Result<R, Error>
@@ -258,6 +425,39 @@ macro_rules! dma {
                         }
                     }
 
+                    impl<BUFFER> RingBuffer<BUFFER, $CX> {
+                        /// Returns the index of the element the DMA will write next
+                        pub fn write_index(&self) -> usize {
+                            let pending = self.channel.get_cndtr() as usize;
+
+                            // NOTE the counter is reloaded with `len` when it reaches zero
+                            (self.len - pending) % self.len
+                        }
+
+                        /// Returns the whole buffer
+                        ///
+                        /// NOTE the DMA keeps writing into the buffer; data that's not drained
+                        /// before the DMA wraps around gets overwritten
+                        pub fn buffer<T>(&self) -> &[T]
+                        where
+                            BUFFER: Unsize<[T]>,
+                        {
+                            let slice: &[T] = self.buffer;
+                            slice
+                        }
+
+                        /// Stops the transfer and releases the buffer and the channel
+                        pub fn stop(mut self) -> (&'static mut BUFFER, $CX) {
+                            self.channel.ccr().modify(|_, w| w.en().clear_bit());
+
+                            // NOTE(compiler_fence) operations on `buffer` should not be reordered
+                            // before the previous statement, which stops the DMA transfer
+                            atomic::compiler_fence(Ordering::SeqCst);
+
+                            (self.buffer, self.channel)
+                        }
+                    }
+
                     impl<BUFFER, PAYLOAD, MODE> Transfer<MODE, BUFFER, $CX, PAYLOAD> {
                         pub fn is_done(&self) -> bool {
                             self.channel.isr().$tcifX().bit_is_set()
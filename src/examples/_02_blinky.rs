@@ -32,9 +32,9 @@
 //!     let mut timer = Timer::tim2(p.TIM2, 1.hz(), clocks, &mut rcc.apb1);
 //!     loop {
 //!         block!(timer.wait()).unwrap();
-//!         led.set_high();
+//!         led.set_high().unwrap();
 //!         block!(timer.wait()).unwrap();
-//!         led.set_low();
+//!         led.set_low().unwrap();
 //!     }
 //! }
 //! ```
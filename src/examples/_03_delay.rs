@@ -26,9 +26,9 @@
 //!     let mut delay = Delay::new(cp.SYST, clocks);
 //! 
 //!     loop {
-//!         led.set_high();
+//!         led.set_high().unwrap();
 //!         delay.delay_ms(1_000_u16);
-//!         led.set_low();
+//!         led.set_low().unwrap();
 //!         delay.delay_ms(1_000_u16);
 //!     }
 //! }
@@ -9,4 +9,3 @@ pub mod _03_delay;
 pub mod _04_serial;
 pub mod _05_pwm;
 pub mod _06_qei;
-pub mod _07_mpu9250;
@@ -1,7 +1,47 @@
 //! Flash memory
+//!
+//! Besides the ACR latency configuration this module provides a small API to erase and program
+//! the internal flash. The F103 can only program one half-word at a time and a location must be
+//! erased (read back as `0xFFFF`) before it can be programmed again.
+
+use core::{ptr, slice};
 
 use stm32f103xx::{flash, FLASH};
 
+/// Start address of the main flash memory
+pub const FLASH_START: u32 = 0x0800_0000;
+
+// Size of an erasable page, in bytes, of the low / medium density and of the high density devices
+const PAGE_SIZE_1K: u32 = 1024;
+const PAGE_SIZE_2K: u32 = 2048;
+
+// Largest flash memory of the low / medium density devices
+const MEDIUM_DENSITY_MAX_SIZE: u32 = 128 * 1024;
+
+// Flash size register (in KiB)
+const FLASH_SIZE_REGISTER: u32 = 0x1FFF_F7E0;
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+
+/// Flash error
+#[derive(Debug)]
+pub enum Error {
+    /// The address lies outside of the flash memory
+    AddressOutOfRange,
+    /// The address (or length) is not half-word or page aligned
+    Misaligned,
+    /// The flash controller could not be unlocked
+    Locked,
+    /// The target location is write protected
+    WriteProtection,
+    /// The target location was not erased before programming
+    Programming,
+    /// The content read back doesn't match what was written
+    Verify,
+    #[doc(hidden)] _Extensible,
+}
+
 /// Extension trait to constrain the FLASH peripheral
 pub trait FlashExt {
     /// Constrains the FLASH peripheral to play nicely with the other abstractions
@@ -12,6 +52,10 @@ impl FlashExt for FLASH {
     fn constrain(self) -> Parts {
         Parts {
             acr: ACR { _0: () },
+            ar: AR { _0: () },
+            cr: CR { _0: () },
+            keyr: KEYR { _0: () },
+            sr: SR { _0: () },
         }
     }
 }
@@ -20,6 +64,212 @@ impl FlashExt for FLASH {
 pub struct Parts {
     /// Opaque ACR register
     pub acr: ACR,
+    /// Opaque AR register
+    pub ar: AR,
+    /// Opaque CR register
+    pub cr: CR,
+    /// Opaque KEYR register
+    pub keyr: KEYR,
+    /// Opaque SR register
+    pub sr: SR,
+}
+
+impl Parts {
+    /// Returns the size of the flash memory, in bytes
+    pub fn size(&self) -> u32 {
+        // NOTE(unsafe) read only factory programmed value
+        u32::from(unsafe { ptr::read_volatile(FLASH_SIZE_REGISTER as *const u16) }) * 1024
+    }
+
+    /// Returns the size of an erasable page, in bytes
+    ///
+    /// This is 1 KiB on the low and medium density devices and 2 KiB on the high density ones
+    pub fn page_size(&self) -> u32 {
+        page_size(self.size())
+    }
+
+    /// Reads `len` bytes starting at `offset` bytes from the start of the flash memory
+    ///
+    /// The returned slice borrows `self` so the flash can't be erased or programmed (see
+    /// `unlock`) while the slice is alive
+    pub fn read(&self, offset: u32, len: usize) -> Result<&[u8], Error> {
+        if out_of_range(offset, len, self.size()) {
+            return Err(Error::AddressOutOfRange);
+        }
+
+        // NOTE(unsafe) the flash memory is always mapped and only modified through `unlock`,
+        // which requires a `&mut self`
+        Ok(unsafe { slice::from_raw_parts((FLASH_START + offset) as *const u8, len) })
+    }
+
+    /// Unlocks the flash controller for erasing and programming
+    ///
+    /// The controller is locked again when the returned `UnlockedFlash` is dropped
+    pub fn unlock<'a>(&'a mut self) -> Result<UnlockedFlash<'a>, Error> {
+        let size = self.size();
+
+        if self.cr.cr().read().lock().bit_is_set() {
+            self.keyr.keyr().write(|w| unsafe { w.key().bits(KEY1) });
+            self.keyr.keyr().write(|w| unsafe { w.key().bits(KEY2) });
+
+            if self.cr.cr().read().lock().bit_is_set() {
+                return Err(Error::Locked);
+            }
+        }
+
+        Ok(UnlockedFlash {
+            ar: &mut self.ar,
+            cr: &mut self.cr,
+            sr: &mut self.sr,
+            size,
+            page_size: page_size(size),
+        })
+    }
+}
+
+fn page_size(size: u32) -> u32 {
+    if size > MEDIUM_DENSITY_MAX_SIZE {
+        PAGE_SIZE_2K
+    } else {
+        PAGE_SIZE_1K
+    }
+}
+
+/// Returns `true` if the `len` bytes starting at `offset` don't fit in `size` bytes
+fn out_of_range(offset: u32, len: usize, size: u32) -> bool {
+    // NOTE(checked_add) a wrapping sum would pass the check
+    offset
+        .checked_add(len as u32)
+        .map_or(true, |end| end > size)
+}
+
+/// Unlocked flash controller
+pub struct UnlockedFlash<'a> {
+    ar: &'a mut AR,
+    cr: &'a mut CR,
+    sr: &'a mut SR,
+    size: u32,
+    page_size: u32,
+}
+
+impl<'a> UnlockedFlash<'a> {
+    /// Erases the page that starts `offset` bytes from the start of the flash memory
+    ///
+    /// `offset` must be a multiple of the page size (see `Parts::page_size`)
+    pub fn erase_page(&mut self, offset: u32) -> Result<(), Error> {
+        if offset >= self.size {
+            return Err(Error::AddressOutOfRange);
+        }
+
+        if offset % self.page_size != 0 {
+            return Err(Error::Misaligned);
+        }
+
+        self.wait_busy();
+
+        self.cr.cr().modify(|_, w| w.per().set_bit());
+        self.ar
+            .ar()
+            .write(|w| unsafe { w.far().bits(FLASH_START + offset) });
+        self.cr.cr().modify(|_, w| w.strt().set_bit());
+
+        self.wait_busy();
+        self.cr.cr().modify(|_, w| w.per().clear_bit());
+
+        self.status()?;
+
+        let start = (FLASH_START + offset) as *const u16;
+        for i in 0..(self.page_size / 2) as usize {
+            // NOTE(unsafe) read within the erased page
+            if unsafe { ptr::read_volatile(start.offset(i as isize)) } != 0xFFFF {
+                return Err(Error::Verify);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Programs the half-word located `offset` bytes from the start of the flash memory
+    ///
+    /// The location must have been erased beforehand
+    pub fn program_half_word(&mut self, offset: u32, half_word: u16) -> Result<(), Error> {
+        if out_of_range(offset, 2, self.size) {
+            return Err(Error::AddressOutOfRange);
+        }
+
+        if offset % 2 != 0 {
+            return Err(Error::Misaligned);
+        }
+
+        let address = (FLASH_START + offset) as *mut u16;
+
+        self.wait_busy();
+
+        self.cr.cr().modify(|_, w| w.pg().set_bit());
+        // NOTE(unsafe) the controller is in programming mode and the address is in range
+        unsafe { ptr::write_volatile(address, half_word) }
+
+        self.wait_busy();
+        self.cr.cr().modify(|_, w| w.pg().clear_bit());
+
+        self.status()?;
+
+        // NOTE(unsafe) read back the location we just programmed
+        if unsafe { ptr::read_volatile(address) } != half_word {
+            return Err(Error::Verify);
+        }
+
+        Ok(())
+    }
+
+    /// Programs `data` starting `offset` bytes from the start of the flash memory
+    ///
+    /// `offset` and the length of `data` must both be even; bytes are programmed in little endian
+    /// half-words
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Error> {
+        if offset % 2 != 0 || data.len() % 2 != 0 {
+            return Err(Error::Misaligned);
+        }
+
+        if out_of_range(offset, data.len(), self.size) {
+            return Err(Error::AddressOutOfRange);
+        }
+
+        for (i, chunk) in data.chunks(2).enumerate() {
+            let half_word = u16::from(chunk[0]) | u16::from(chunk[1]) << 8;
+            self.program_half_word(offset + 2 * i as u32, half_word)?;
+        }
+
+        Ok(())
+    }
+
+    fn wait_busy(&mut self) {
+        while self.sr.sr().read().bsy().bit_is_set() {}
+    }
+
+    fn status(&mut self) -> Result<(), Error> {
+        let sr = self.sr.sr().read();
+
+        // NOTE these flags are cleared by writing a 1 to them
+        if sr.wrprterr().bit_is_set() {
+            self.sr.sr().write(|w| w.wrprterr().set_bit());
+            Err(Error::WriteProtection)
+        } else if sr.pgerr().bit_is_set() {
+            self.sr.sr().write(|w| w.pgerr().set_bit());
+            Err(Error::Programming)
+        } else {
+            if sr.eop().bit_is_set() {
+                self.sr.sr().write(|w| w.eop().set_bit());
+            }
+            Ok(())
+        }
+    }
+}
+
+impl<'a> Drop for UnlockedFlash<'a> {
+    fn drop(&mut self) {
+        self.cr.cr().modify(|_, w| w.lock().set_bit());
+    }
 }
 
 /// Opaque ACR register
@@ -32,4 +282,122 @@ impl ACR {
         // NOTE(unsafe) this proxy grants exclusive access to this register
         unsafe { &(*FLASH::ptr()).acr }
     }
+
+    /// Returns the currently configured number of wait states
+    pub fn latency(&mut self) -> Latency {
+        match self.acr().read().latency().bits() {
+            0b000 => Latency::Ws0,
+            0b001 => Latency::Ws1,
+            _ => Latency::Ws2,
+        }
+    }
+
+    /// Sets the number of flash wait states
+    ///
+    /// NOTE when raising SYSCLK the latency must be increased *before* switching the clock; when
+    /// lowering it, decrease the latency *after* the switch. `CFGR::freeze` takes care of this
+    pub fn set_latency(&mut self, latency: Latency) {
+        self.acr()
+            .modify(|_, w| unsafe { w.latency().bits(latency.bits()) });
+    }
+
+    /// Enables the prefetch buffer
+    ///
+    /// NOTE the prefetch buffer can only be switched on / off while SYSCLK is below 24 MHz and the
+    /// AHB prescaler is 1
+    pub fn enable_prefetch(&mut self) {
+        self.acr().modify(|_, w| w.prftbe().enabled());
+    }
+
+    /// Disables the prefetch buffer
+    ///
+    /// See `enable_prefetch` for restrictions
+    pub fn disable_prefetch(&mut self) {
+        self.acr().modify(|_, w| w.prftbe().disabled());
+    }
+
+    /// Returns `true` if the prefetch buffer is enabled
+    pub fn is_prefetch_enabled(&mut self) -> bool {
+        self.acr().read().prftbs().bit_is_set()
+    }
+}
+
+/// Flash wait states
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Latency {
+    /// Zero wait states, for 0 < SYSCLK <= 24 MHz
+    Ws0,
+    /// One wait state, for 24 MHz < SYSCLK <= 48 MHz
+    Ws1,
+    /// Two wait states, for 48 MHz < SYSCLK <= 72 MHz
+    Ws2,
+}
+
+impl Latency {
+    /// Returns the minimum number of wait states required to run at `sysclk` Hz
+    pub fn for_sysclk(sysclk: u32) -> Self {
+        if sysclk <= 24_000_000 {
+            Latency::Ws0
+        } else if sysclk <= 48_000_000 {
+            Latency::Ws1
+        } else {
+            Latency::Ws2
+        }
+    }
+
+    fn bits(&self) -> u8 {
+        match *self {
+            Latency::Ws0 => 0b000,
+            Latency::Ws1 => 0b001,
+            Latency::Ws2 => 0b010,
+        }
+    }
+}
+
+/// Opaque AR register
+pub struct AR {
+    _0: (),
+}
+
+impl AR {
+    pub(crate) fn ar(&mut self) -> &flash::AR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*FLASH::ptr()).ar }
+    }
+}
+
+/// Opaque CR register
+pub struct CR {
+    _0: (),
+}
+
+impl CR {
+    pub(crate) fn cr(&mut self) -> &flash::CR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*FLASH::ptr()).cr }
+    }
+}
+
+/// Opaque KEYR register
+pub struct KEYR {
+    _0: (),
+}
+
+impl KEYR {
+    pub(crate) fn keyr(&mut self) -> &flash::KEYR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*FLASH::ptr()).keyr }
+    }
+}
+
+/// Opaque SR register
+pub struct SR {
+    _0: (),
+}
+
+impl SR {
+    pub(crate) fn sr(&mut self) -> &flash::SR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*FLASH::ptr()).sr }
+    }
 }
@@ -1,12 +1,38 @@
 //! General Purpose Input / Output
+//!
+//! # Interrupt safety
+//!
+//! `set_high`, `set_low` and `set_state` are single writes to the stateless BSRR register, and
+//! reading a pin only reads IDR / ODR, so these operations never race with operations on other
+//! pins of the same port, even when the pins are used from different interrupt priorities.
+//!
+//! `toggle` reads ODR before writing BSRR; it won't corrupt the state of other pins but the
+//! toggle itself is not atomic with respect to another context that drives the *same* pin.
+//!
+//! Operations that read-modify-write shared registers (the `into_*` methods modify CRL / CRH)
+//! require a mutable reference to the opaque register proxy, so they can't race either.
+//!
+//! # Digital traits
+//!
+//! The pins implement the fallible `embedded-hal` `digital::v2` traits; their `Error` is
+//! `Infallible` so `pin.set_high().unwrap()` can never panic. Drivers that still expect the
+//! deprecated infallible traits can be given a pin wrapped in `OldOutputPin` / `OldInputPin`.
+//!
+//! Open drain outputs also implement `InputPin`, which reads the actual level of the line; this
+//! is what bit-banged buses like 1-Wire use to sense the other devices.
 
 // TODO the pins here currently correspond to the LQFP-48 package. There should be Cargo features
 // that let you select different microcontroller packages
 
 use core::marker::PhantomData;
 
+use hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin, ToggleableOutputPin};
+#[cfg(feature = "eh1")]
+use hal1;
 use rcc::APB2;
 
+pub use hal::digital::v1_compat::{OldInputPin, OldOutputPin};
+
 /// Extension trait to split a GPIO peripheral in independent pins and registers
 pub trait GpioExt {
     /// The to split the GPIO into
@@ -38,27 +64,202 @@ pub struct PushPull;
 /// Open drain output (type state)
 pub struct OpenDrain;
 
-/// Alternate function
+/// Alternate function (type state)
 pub struct Alternate<MODE> {
     _mode: PhantomData<MODE>,
 }
 
+/// Analog mode (type state)
+pub struct Analog;
+
+/// Reserved for the JTAG debug port (type state)
+///
+/// PA15, PB3 and PB4 start in this state; `MAPR::disable_jtag` hands them back as floating inputs
+pub struct Debugger;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Modes a pin can be reconfigured from (type state)
+///
+/// This covers every mode except `Debugger`: a pin reserved for the JTAG port can only be freed
+/// through `MAPR::disable_jtag`
+pub trait Active: sealed::Sealed {}
+
+impl<MODE> sealed::Sealed for Input<MODE> {}
+impl<MODE> Active for Input<MODE> {}
+impl<MODE> sealed::Sealed for Output<MODE> {}
+impl<MODE> Active for Output<MODE> {}
+impl<MODE> sealed::Sealed for Alternate<MODE> {}
+impl<MODE> Active for Alternate<MODE> {}
+impl sealed::Sealed for Analog {}
+impl Active for Analog {}
+
+/// Digital output pin state
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PinState {
+    /// Low pin state
+    Low,
+    /// High pin state
+    High,
+}
+
+impl From<bool> for PinState {
+    fn from(high: bool) -> PinState {
+        if high {
+            PinState::High
+        } else {
+            PinState::Low
+        }
+    }
+}
+
+/// A pin whose configuration has been locked until the next reset
+///
+/// The pin can still be read and driven, through the digital traits, but it can't be
+/// reconfigured
+pub struct Locked<PIN> {
+    pin: PIN,
+}
+
+impl<PIN> InputPin for Locked<PIN>
+where
+    PIN: InputPin,
+{
+    type Error = PIN::Error;
+
+    fn is_high(&self) -> Result<bool, PIN::Error> {
+        self.pin.is_high()
+    }
+
+    fn is_low(&self) -> Result<bool, PIN::Error> {
+        self.pin.is_low()
+    }
+}
+
+impl<PIN> OutputPin for Locked<PIN>
+where
+    PIN: OutputPin,
+{
+    type Error = PIN::Error;
+
+    fn set_high(&mut self) -> Result<(), PIN::Error> {
+        self.pin.set_high()
+    }
+
+    fn set_low(&mut self) -> Result<(), PIN::Error> {
+        self.pin.set_low()
+    }
+}
+
+impl<PIN> StatefulOutputPin for Locked<PIN>
+where
+    PIN: StatefulOutputPin,
+{
+    fn is_set_high(&self) -> Result<bool, PIN::Error> {
+        self.pin.is_set_high()
+    }
+
+    fn is_set_low(&self) -> Result<bool, PIN::Error> {
+        self.pin.is_set_low()
+    }
+}
+
+impl<PIN> ToggleableOutputPin for Locked<PIN>
+where
+    PIN: ToggleableOutputPin,
+{
+    type Error = PIN::Error;
+
+    fn toggle(&mut self) -> Result<(), PIN::Error> {
+        self.pin.toggle()
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl<PIN> hal1::digital::ErrorType for Locked<PIN>
+where
+    PIN: hal1::digital::ErrorType,
+{
+    type Error = PIN::Error;
+}
+
+#[cfg(feature = "eh1")]
+impl<PIN> hal1::digital::InputPin for Locked<PIN>
+where
+    PIN: hal1::digital::InputPin,
+{
+    fn is_high(&mut self) -> Result<bool, PIN::Error> {
+        self.pin.is_high()
+    }
+
+    fn is_low(&mut self) -> Result<bool, PIN::Error> {
+        self.pin.is_low()
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl<PIN> hal1::digital::OutputPin for Locked<PIN>
+where
+    PIN: hal1::digital::OutputPin,
+{
+    fn set_high(&mut self) -> Result<(), PIN::Error> {
+        self.pin.set_high()
+    }
+
+    fn set_low(&mut self) -> Result<(), PIN::Error> {
+        self.pin.set_low()
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl<PIN> hal1::digital::StatefulOutputPin for Locked<PIN>
+where
+    PIN: hal1::digital::StatefulOutputPin,
+{
+    fn is_set_high(&mut self) -> Result<bool, PIN::Error> {
+        self.pin.is_set_high()
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, PIN::Error> {
+        self.pin.is_set_low()
+    }
+}
+
+// Lock key bit of the LCKR registers
+const LCKK: u32 = 1 << 16;
+
+/// Edge that triggers an external interrupt
+pub enum Edge {
+    /// Low to high transition
+    Rising,
+    /// High to low transition
+    Falling,
+    /// Both transitions
+    RisingFalling,
+}
+
 macro_rules! gpio {
-    ($GPIOX:ident, $gpiox:ident, $gpioy:ident, $iopxenr:ident, $iopxrst:ident, $PXx:ident, [
-        $($PXi:ident: ($pxi:ident, $i:expr, $MODE:ty, $CR:ident),)+
+    ($GPIOX:ident, $gpiox:ident, $gpioy:ident, $PXx:ident, $port:expr, [
+        $($PXi:ident: ($pxi:ident, $i:expr, $MODE:ty, $CR:ident, $exticri:ident),)+
     ]) => {
         /// GPIO
         pub mod $gpiox {
+            use core::convert::Infallible;
             use core::marker::PhantomData;
 
-            use hal::digital::OutputPin;
-            use stm32f103xx::{$gpioy, $GPIOX};
+            use hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin, ToggleableOutputPin};
+            #[cfg(feature = "eh1")]
+            use hal1;
+            use stm32f103xx::{$gpioy, $GPIOX, EXTI};
 
-            use rcc::APB2;
+            use afio;
+            use rcc::{APB2, RccPeripheral};
             use super::{
-                Alternate, Floating, GpioExt, Input,
-                // OpenDrain,
-                Output,
+                Active, Alternate, Analog, Edge, Floating, GpioExt, Input, Locked, LCKK,
+                OpenDrain,
+                Output, PinState,
                 // PullDown, PullUp,
                 PushPull,
             };
@@ -69,6 +270,10 @@ macro_rules! gpio {
                 pub crl: CRL,
                 /// Opaque CRH register
                 pub crh: CRH,
+                /// Opaque LCKR register
+                pub lckr: LCKR,
+                /// Whole port access
+                pub port: Port,
                 $(
                     /// Pin
                     pub $pxi: $PXi<$MODE>,
@@ -79,13 +284,14 @@ macro_rules! gpio {
                 type Parts = Parts;
 
                 fn split(self, apb2: &mut APB2) -> Parts {
-                    apb2.enr().modify(|_, w| w.$iopxenr().enabled());
-                    apb2.rstr().modify(|_, w| w.$iopxrst().set_bit());
-                    apb2.rstr().modify(|_, w| w.$iopxrst().clear_bit());
+                    $GPIOX::enable(apb2);
+                    $GPIOX::reset(apb2);
 
                     Parts {
                         crl: CRL { _0: () },
                         crh: CRH { _0: () },
+                        lckr: LCKR { _0: () },
+                        port: Port { _0: () },
                         $(
                             $pxi: $PXi { _mode: PhantomData },
                         )+
@@ -117,40 +323,265 @@ macro_rules! gpio {
                 }
             }
 
+            /// Opaque LCKR register
+            pub struct LCKR {
+                _0: (),
+            }
+
+            impl LCKR {
+                pub(crate) fn lckr(&mut self) -> &$gpioy::LCKR {
+                    unsafe { &(*$GPIOX::ptr()).lckr }
+                }
+
+                /// Locks the configuration of the pins selected by `mask` (bit `N` selects pin `N`)
+                /// until the next reset
+                ///
+                /// The lock key sequence can only be applied once per port so this is the way to
+                /// lock several pins of a port. Returns `false`, and locks nothing, if the port is
+                /// already locked.
+                ///
+                /// NOTE the pins keep their type state; their `into_*` methods no longer have any
+                /// effect on the hardware. Prefer the pin level `lock` when a single pin is locked
+                pub fn lock(&mut self, mask: u16) -> bool {
+                    self.lock_key(u32::from(mask))
+                }
+
+                /// Returns `true` if the configuration of the port has been locked
+                pub fn is_locked(&mut self) -> bool {
+                    self.lckr().read().bits() & LCKK != 0
+                }
+
+                fn lock_key(&mut self, lck: u32) -> bool {
+                    if self.is_locked() {
+                        return false;
+                    }
+
+                    let lckr = self.lckr();
+
+                    // lock key write sequence: write 1, write 0, write 1, read, read
+                    lckr.write(|w| unsafe { w.bits(LCKK | lck) });
+                    lckr.write(|w| unsafe { w.bits(lck) });
+                    lckr.write(|w| unsafe { w.bits(LCKK | lck) });
+                    lckr.read();
+
+                    lckr.read().bits() & (LCKK | lck) == LCKK | lck
+                }
+            }
+
+            /// Whole port access, for reading or driving several pins in a single operation
+            ///
+            /// NOTE only pins configured as outputs are affected by writes
+            pub struct Port {
+                _0: (),
+            }
+
+            impl Port {
+                /// Drives the pins selected by `mask` to the levels of the corresponding bits of
+                /// `value`; the other pins are left untouched
+                ///
+                /// This is a single atomic write to BSRR so all the pins change at the same time
+                pub fn write_bits(&mut self, mask: u16, value: u16) {
+                    let set = u32::from(mask & value);
+                    let reset = u32::from(mask & !value);
+
+                    // NOTE(unsafe) atomic write to a stateless register
+                    unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(reset << 16 | set)) }
+                }
+
+                /// Returns the input levels of all the pins of the port (IDR)
+                pub fn read_bits(&self) -> u16 {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$GPIOX::ptr()).idr.read().bits() as u16 }
+                }
+
+                /// Returns the levels the output pins of the port are being driven to (ODR)
+                pub fn read_output_bits(&self) -> u16 {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$GPIOX::ptr()).odr.read().bits() as u16 }
+                }
+            }
+
             /// Partially erased pin
             pub struct $PXx<MODE> {
                 i: u8,
                 _mode: PhantomData<MODE>,
             }
 
-            impl<MODE> OutputPin for $PXx<Output<MODE>> {
-                fn is_high(&self) -> bool {
-                    !self.is_low()
+            impl<MODE> InputPin for $PXx<Input<MODE>> {
+                type Error = Infallible;
+
+                fn is_high(&self) -> Result<bool, Infallible> {
+                    self.is_low().map(|low| !low)
+                }
+
+                fn is_low(&self) -> Result<bool, Infallible> {
+                    // NOTE(unsafe) atomic read with no side effects
+                    Ok(unsafe { (*$GPIOX::ptr()).idr.read().bits() & (1 << self.i) == 0 })
+                }
+            }
+
+            /// Reads the level of the line, which can be pulled low by another device while the pin is
+            /// released (driven high)
+            impl InputPin for $PXx<Output<OpenDrain>> {
+                type Error = Infallible;
+
+                fn is_high(&self) -> Result<bool, Infallible> {
+                    self.is_low().map(|low| !low)
+                }
+
+                fn is_low(&self) -> Result<bool, Infallible> {
+                    // NOTE(unsafe) atomic read with no side effects
+                    Ok(unsafe { (*$GPIOX::ptr()).idr.read().bits() & (1 << self.i) == 0 })
+                }
+            }
+
+            impl<MODE> $PXx<Output<MODE>> {
+                /// Returns `true` if the pin is being driven high
+                pub fn is_set_high(&self) -> bool {
+                    !self.is_set_low()
                 }
 
-                fn is_low(&self) -> bool {
+                /// Returns `true` if the pin is being driven low
+                pub fn is_set_low(&self) -> bool {
                     // NOTE(unsafe) atomic read with no side effects
                     unsafe { (*$GPIOX::ptr()).odr.read().bits() & (1 << self.i) == 0 }
                 }
 
-                fn set_high(&mut self) {
+                /// Drives the pin high if it's currently low and vice versa
+                pub fn toggle(&mut self) {
+                    if self.is_set_low() {
+                        self.set_state(PinState::High)
+                    } else {
+                        self.set_state(PinState::Low)
+                    }
+                }
+
+                /// Drives the pin high or low depending on `state`
+                pub fn set_state(&mut self, state: PinState) {
+                    let bit = match state {
+                        PinState::High => self.i,
+                        PinState::Low => 16 + self.i,
+                    };
+
+                    // NOTE(unsafe) atomic write to a stateless register
+                    unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(1 << bit)) }
+                }
+            }
+
+            impl<MODE> OutputPin for $PXx<Output<MODE>> {
+                type Error = Infallible;
+
+                fn set_high(&mut self) -> Result<(), Infallible> {
                     // NOTE(unsafe) atomic write to a stateless register
                     unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(1 << self.i)) }
+                    Ok(())
                 }
 
-                fn set_low(&mut self) {
+                fn set_low(&mut self) -> Result<(), Infallible> {
                     // NOTE(unsafe) atomic write to a stateless register
                     unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(1 << (16 + self.i))) }
+                    Ok(())
+                }
+            }
+
+            impl<MODE> StatefulOutputPin for $PXx<Output<MODE>> {
+                fn is_set_high(&self) -> Result<bool, Infallible> {
+                    Ok($PXx::is_set_high(self))
+                }
+
+                fn is_set_low(&self) -> Result<bool, Infallible> {
+                    Ok($PXx::is_set_low(self))
+                }
+            }
+
+            impl<MODE> ToggleableOutputPin for $PXx<Output<MODE>> {
+                type Error = Infallible;
+
+                fn toggle(&mut self) -> Result<(), Infallible> {
+                    $PXx::toggle(self);
+                    Ok(())
+                }
+            }
+
+            #[cfg(feature = "eh1")]
+            impl<MODE> hal1::digital::ErrorType for $PXx<MODE> {
+                type Error = Infallible;
+            }
+
+            #[cfg(feature = "eh1")]
+            impl<MODE> hal1::digital::InputPin for $PXx<Input<MODE>> {
+                fn is_high(&mut self) -> Result<bool, Infallible> {
+                    InputPin::is_high(self)
+                }
+
+                fn is_low(&mut self) -> Result<bool, Infallible> {
+                    InputPin::is_low(self)
+                }
+            }
+
+            #[cfg(feature = "eh1")]
+            impl hal1::digital::InputPin for $PXx<Output<OpenDrain>> {
+                fn is_high(&mut self) -> Result<bool, Infallible> {
+                    InputPin::is_high(self)
+                }
+
+                fn is_low(&mut self) -> Result<bool, Infallible> {
+                    InputPin::is_low(self)
+                }
+            }
+
+            #[cfg(feature = "eh1")]
+            impl<MODE> hal1::digital::OutputPin for $PXx<Output<MODE>> {
+                fn set_high(&mut self) -> Result<(), Infallible> {
+                    OutputPin::set_high(self)
+                }
+
+                fn set_low(&mut self) -> Result<(), Infallible> {
+                    OutputPin::set_low(self)
+                }
+            }
+
+            #[cfg(feature = "eh1")]
+            impl<MODE> hal1::digital::StatefulOutputPin for $PXx<Output<MODE>> {
+                fn is_set_high(&mut self) -> Result<bool, Infallible> {
+                    Ok($PXx::is_set_high(self))
+                }
+
+                fn is_set_low(&mut self) -> Result<bool, Infallible> {
+                    Ok($PXx::is_set_low(self))
+                }
+
+                fn toggle(&mut self) -> Result<(), Infallible> {
+                    $PXx::toggle(self);
+                    Ok(())
                 }
             }
 
             $(
                 /// Pin
                 pub struct $PXi<MODE> {
-                    _mode: PhantomData<MODE>,
+                    pub(crate) _mode: PhantomData<MODE>,
                 }
 
-                impl<MODE> $PXi<MODE> {
+                impl<MODE> $PXi<MODE>
+                where
+                    MODE: Active,
+                {
+                    /// Locks the configuration of the pin until the next reset
+                    ///
+                    /// NOTE the hardware freezes the whole LCKR register of the port once the
+                    /// lock key sequence has been applied, so only *one* pin per port can be
+                    /// locked with this method; if the port has already been locked the pin is
+                    /// given back, unlocked, as the error. Use `LCKR::lock` to lock several pins
+                    /// of a port at once
+                    pub fn lock(self, lckr: &mut LCKR) -> Result<Locked<Self>, Self> {
+                        if lckr.lock_key(1 << $i) {
+                            Ok(Locked { pin: self })
+                        } else {
+                            Err(self)
+                        }
+                    }
+
                     /// Configures the pin to operate as an alternate function push pull output pin
                     pub fn into_alternate_push_pull(
                         self,
@@ -173,6 +604,45 @@ macro_rules! gpio {
                         $PXi { _mode: PhantomData }
                     }
 
+                    /// Configures the pin to operate as an alternate function open drain output pin
+                    pub fn into_alternate_open_drain(
+                        self,
+                        cr: &mut $CR,
+                    ) -> $PXi<Alternate<OpenDrain>> {
+                        let offset = (4 * $i) % 32;
+                        // Alternate function output open drain
+                        let cnf = 0b11;
+                        // Output mode, max speed 50 MHz
+                        let mode = 0b11;
+                        let bits = (cnf << 2) | mode;
+
+                        cr
+                            .cr()
+                            .modify(|r, w| unsafe {
+                                w.bits((r.bits() & !(0b1111 << offset)) | (bits << offset))
+                            });
+
+                        $PXi { _mode: PhantomData }
+                    }
+
+                    /// Configures the pin to operate as an analog input pin
+                    pub fn into_analog(self, cr: &mut $CR) -> $PXi<Analog> {
+                        let offset = (4 * $i) % 32;
+                        // Analog mode
+                        let cnf = 0b00;
+                        // Input mode
+                        let mode = 0b00;
+                        let bits = (cnf << 2) | mode;
+
+                        cr
+                            .cr()
+                            .modify(|r, w| unsafe {
+                                w.bits((r.bits() & !(0b1111 << offset)) | (bits << offset))
+                            });
+
+                        $PXi { _mode: PhantomData }
+                    }
+
                     /// Configures the pin to operate as a floating input pin
                     pub fn into_floating_input(
                         self,
@@ -237,27 +707,26 @@ macro_rules! gpio {
                     //     $PXi { _mode: PhantomData }
                     // }
 
-                    // /// Configures the pin to operate as an open drain output pin
-                    // pub fn into_open_drain_output(
-                    //     self,
-                    //     moder: &mut MODER,
-                    //     otyper: &mut OTYPER,
-                    // ) -> $PXi<Output<OpenDrain>> {
-                    //     let offset = 2 * $i;
-
-                    //     // general purpose output mode
-                    //     let mode = 0b01;
-                    //     moder.moder().modify(|r, w| unsafe {
-                    //         w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
-                    //     });
+                    /// Configures the pin to operate as an open drain output pin
+                    pub fn into_open_drain_output(
+                        self,
+                        cr: &mut $CR,
+                    ) -> $PXi<Output<OpenDrain>> {
+                        let offset = (4 * $i) % 32;
+                        // General purpose output open-drain
+                        let cnf = 0b01;
+                        // Open-Drain Output mode, max speed 50 MHz
+                        let mode = 0b11;
+                        let bits = (cnf << 2) | mode;
 
-                    //     // open drain output
-                    //     otyper
-                    //         .otyper()
-                    //         .modify(|r, w| unsafe { w.bits(r.bits() | (0b1 << $i)) });
+                        cr
+                            .cr()
+                            .modify(|r, w| unsafe {
+                                w.bits((r.bits() & !(0b1111 << offset)) | (bits << offset))
+                            });
 
-                    //     $PXi { _mode: PhantomData }
-                    // }
+                        $PXi { _mode: PhantomData }
+                    }
 
                     /// Configures the pin to operate as an push pull output pin
                     pub fn into_push_pull_output(
@@ -279,39 +748,325 @@ macro_rules! gpio {
 
                         $PXi { _mode: PhantomData }
                     }
+
+                    fn with_mode<M, R, F>(&mut self, cr: &mut $CR, bits: u32, f: F) -> R
+                    where
+                        F: FnOnce(&mut $PXi<M>) -> R,
+                    {
+                        let offset = (4 * $i) % 32;
+
+                        let saved = (cr.cr().read().bits() >> offset) & 0b1111;
+                        // NOTE(unsafe) atomic read with no side effects
+                        let high = unsafe { (*$GPIOX::ptr()).odr.read().bits() & (1 << $i) != 0 };
+
+                        cr
+                            .cr()
+                            .modify(|r, w| unsafe {
+                                w.bits((r.bits() & !(0b1111 << offset)) | (bits << offset))
+                            });
+
+                        let ret = f(&mut $PXi { _mode: PhantomData });
+
+                        // NOTE the output level (or, for an input, the pull direction) is restored
+                        // before the mode to avoid glitches
+                        let bit = if high { $i } else { 16 + $i };
+                        // NOTE(unsafe) atomic write to a stateless register
+                        unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(1 << bit)) }
+
+                        cr
+                            .cr()
+                            .modify(|r, w| unsafe {
+                                w.bits((r.bits() & !(0b1111 << offset)) | (saved << offset))
+                            });
+
+                        ret
+                    }
+                }
+
+                impl<MODE> $PXi<Input<MODE>> {
+                    /// Temporarily configures the pin as a floating input and calls `f` with it;
+                    /// the original configuration is restored once `f` returns
+                    pub fn with_floating_input<R, F>(&mut self, cr: &mut $CR, f: F) -> R
+                    where
+                        F: FnOnce(&mut $PXi<Input<Floating>>) -> R,
+                    {
+                        // Floating input mode
+                        self.with_mode(cr, 0b0100, f)
+                    }
+
+                    /// Temporarily configures the pin as an open drain output and calls `f` with
+                    /// it; the original configuration is restored once `f` returns
+                    pub fn with_open_drain_output<R, F>(&mut self, cr: &mut $CR, f: F) -> R
+                    where
+                        F: FnOnce(&mut $PXi<Output<OpenDrain>>) -> R,
+                    {
+                        // Open-Drain Output mode, max speed 50 MHz
+                        self.with_mode(cr, 0b0111, f)
+                    }
+
+                    /// Temporarily configures the pin as a push pull output and calls `f` with it;
+                    /// the original configuration is restored once `f` returns
+                    pub fn with_push_pull_output<R, F>(&mut self, cr: &mut $CR, f: F) -> R
+                    where
+                        F: FnOnce(&mut $PXi<Output<PushPull>>) -> R,
+                    {
+                        // Output mode, max speed 50 MHz
+                        self.with_mode(cr, 0b0011, f)
+                    }
                 }
 
                 impl<MODE> $PXi<Output<MODE>> {
+                    /// Temporarily configures the pin as a floating input and calls `f` with it;
+                    /// the original configuration is restored once `f` returns
+                    pub fn with_floating_input<R, F>(&mut self, cr: &mut $CR, f: F) -> R
+                    where
+                        F: FnOnce(&mut $PXi<Input<Floating>>) -> R,
+                    {
+                        // Floating input mode
+                        self.with_mode(cr, 0b0100, f)
+                    }
+
+                    /// Temporarily configures the pin as an open drain output and calls `f` with
+                    /// it; the original configuration is restored once `f` returns
+                    pub fn with_open_drain_output<R, F>(&mut self, cr: &mut $CR, f: F) -> R
+                    where
+                        F: FnOnce(&mut $PXi<Output<OpenDrain>>) -> R,
+                    {
+                        // Open-Drain Output mode, max speed 50 MHz
+                        self.with_mode(cr, 0b0111, f)
+                    }
+
+                    /// Temporarily configures the pin as a push pull output and calls `f` with it;
+                    /// the original configuration is restored once `f` returns
+                    pub fn with_push_pull_output<R, F>(&mut self, cr: &mut $CR, f: F) -> R
+                    where
+                        F: FnOnce(&mut $PXi<Output<PushPull>>) -> R,
+                    {
+                        // Output mode, max speed 50 MHz
+                        self.with_mode(cr, 0b0011, f)
+                    }
+                }
+
+                impl<MODE> $PXi<Input<MODE>> {
                     /// Erases the pin number from the type
                     ///
                     /// This is useful when you want to collect the pins into an array where you
                     /// need all the elements to have the same type
-                    pub fn downgrade(self) -> $PXx<Output<MODE>> {
+                    pub fn downgrade(self) -> $PXx<Input<MODE>> {
                         $PXx {
                             i: $i,
                             _mode: self._mode,
                         }
                     }
+
+                    /// Routes this pin to its EXTI line
+                    ///
+                    /// NOTE only one port can be connected to each EXTI line; e.g. connecting PB0
+                    /// disconnects PA0
+                    pub fn make_interrupt_source(&mut self, afio: &mut afio::Parts) {
+                        let offset = 4 * ($i % 4);
+                        afio.$exticri.exticr().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b1111 << offset)) | ($port << offset))
+                        });
+                    }
+
+                    /// Selects the `edge` that triggers the interrupt
+                    pub fn trigger_on_edge(&mut self, exti: &mut EXTI, edge: Edge) {
+                        let (rising, falling) = match edge {
+                            Edge::Rising => (true, false),
+                            Edge::Falling => (false, true),
+                            Edge::RisingFalling => (true, true),
+                        };
+
+                        exti.rtsr.modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(1 << $i)) | ((rising as u32) << $i))
+                        });
+                        exti.ftsr.modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(1 << $i)) | ((falling as u32) << $i))
+                        });
+                    }
+
+                    /// Unmasks the interrupt of this pin's EXTI line
+                    pub fn enable_interrupt(&mut self, exti: &mut EXTI) {
+                        exti.imr.modify(|r, w| unsafe { w.bits(r.bits() | (1 << $i)) });
+                    }
+
+                    /// Masks the interrupt of this pin's EXTI line
+                    pub fn disable_interrupt(&mut self, exti: &mut EXTI) {
+                        exti.imr.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << $i)) });
+                    }
+
+                    /// Clears the pending interrupt flag of this pin's EXTI line
+                    pub fn clear_interrupt_pending_bit(&mut self) {
+                        // NOTE(unsafe) atomic write to a stateless register (write 1 to clear)
+                        unsafe { (*EXTI::ptr()).pr.write(|w| w.bits(1 << $i)) }
+                    }
+
+                    /// Returns `true` if this pin's EXTI line has a pending interrupt
+                    pub fn check_interrupt(&self) -> bool {
+                        // NOTE(unsafe) atomic read with no side effects
+                        unsafe { (*EXTI::ptr()).pr.read().bits() & (1 << $i) != 0 }
+                    }
                 }
 
-                impl<MODE> OutputPin for $PXi<Output<MODE>> {
-                    fn is_high(&self) -> bool {
-                        !self.is_low()
+                impl<MODE> $PXi<Output<MODE>> {
+                    /// Erases the pin number from the type
+                    ///
+                    /// This is useful when you want to collect the pins into an array where you
+                    /// need all the elements to have the same type
+                    pub fn downgrade(self) -> $PXx<Output<MODE>> {
+                        $PXx {
+                            i: $i,
+                            _mode: self._mode,
+                        }
+                    }
+
+                    /// Returns `true` if the pin is being driven high
+                    pub fn is_set_high(&self) -> bool {
+                        !self.is_set_low()
                     }
 
-                    fn is_low(&self) -> bool {
+                    /// Returns `true` if the pin is being driven low
+                    pub fn is_set_low(&self) -> bool {
                         // NOTE(unsafe) atomic read with no side effects
                         unsafe { (*$GPIOX::ptr()).odr.read().bits() & (1 << $i) == 0 }
                     }
 
-                    fn set_high(&mut self) {
+                    /// Drives the pin high if it's currently low and vice versa
+                    pub fn toggle(&mut self) {
+                        if self.is_set_low() {
+                            self.set_state(PinState::High)
+                        } else {
+                            self.set_state(PinState::Low)
+                        }
+                    }
+
+                    /// Drives the pin high or low depending on `state`
+                    pub fn set_state(&mut self, state: PinState) {
+                        let bit = match state {
+                            PinState::High => $i,
+                            PinState::Low => 16 + $i,
+                        };
+
+                        // NOTE(unsafe) atomic write to a stateless register
+                        unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(1 << bit)) }
+                    }
+                }
+
+                impl<MODE> InputPin for $PXi<Input<MODE>> {
+                    type Error = Infallible;
+
+                    fn is_high(&self) -> Result<bool, Infallible> {
+                        self.is_low().map(|low| !low)
+                    }
+
+                    fn is_low(&self) -> Result<bool, Infallible> {
+                        // NOTE(unsafe) atomic read with no side effects
+                        Ok(unsafe { (*$GPIOX::ptr()).idr.read().bits() & (1 << $i) == 0 })
+                    }
+                }
+
+                /// Reads the level of the line, which can be pulled low by another device while the pin is
+                /// released (driven high)
+                impl InputPin for $PXi<Output<OpenDrain>> {
+                    type Error = Infallible;
+
+                    fn is_high(&self) -> Result<bool, Infallible> {
+                        self.is_low().map(|low| !low)
+                    }
+
+                    fn is_low(&self) -> Result<bool, Infallible> {
+                        // NOTE(unsafe) atomic read with no side effects
+                        Ok(unsafe { (*$GPIOX::ptr()).idr.read().bits() & (1 << $i) == 0 })
+                    }
+                }
+
+                impl<MODE> OutputPin for $PXi<Output<MODE>> {
+                    type Error = Infallible;
+
+                    fn set_high(&mut self) -> Result<(), Infallible> {
                         // NOTE(unsafe) atomic write to a stateless register
                         unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(1 << $i)) }
+                        Ok(())
                     }
 
-                    fn set_low(&mut self) {
+                    fn set_low(&mut self) -> Result<(), Infallible> {
                         // NOTE(unsafe) atomic write to a stateless register
                         unsafe { (*$GPIOX::ptr()).bsrr.write(|w| w.bits(1 << (16 + $i))) }
+                        Ok(())
+                    }
+                }
+
+                impl<MODE> StatefulOutputPin for $PXi<Output<MODE>> {
+                    fn is_set_high(&self) -> Result<bool, Infallible> {
+                        Ok($PXi::is_set_high(self))
+                    }
+
+                    fn is_set_low(&self) -> Result<bool, Infallible> {
+                        Ok($PXi::is_set_low(self))
+                    }
+                }
+
+                impl<MODE> ToggleableOutputPin for $PXi<Output<MODE>> {
+                    type Error = Infallible;
+
+                    fn toggle(&mut self) -> Result<(), Infallible> {
+                        $PXi::toggle(self);
+                        Ok(())
+                    }
+                }
+
+                #[cfg(feature = "eh1")]
+                impl<MODE> hal1::digital::ErrorType for $PXi<MODE> {
+                    type Error = Infallible;
+                }
+
+                #[cfg(feature = "eh1")]
+                impl<MODE> hal1::digital::InputPin for $PXi<Input<MODE>> {
+                    fn is_high(&mut self) -> Result<bool, Infallible> {
+                        InputPin::is_high(self)
+                    }
+
+                    fn is_low(&mut self) -> Result<bool, Infallible> {
+                        InputPin::is_low(self)
+                    }
+                }
+
+                #[cfg(feature = "eh1")]
+                impl hal1::digital::InputPin for $PXi<Output<OpenDrain>> {
+                    fn is_high(&mut self) -> Result<bool, Infallible> {
+                        InputPin::is_high(self)
+                    }
+
+                    fn is_low(&mut self) -> Result<bool, Infallible> {
+                        InputPin::is_low(self)
+                    }
+                }
+
+                #[cfg(feature = "eh1")]
+                impl<MODE> hal1::digital::OutputPin for $PXi<Output<MODE>> {
+                    fn set_high(&mut self) -> Result<(), Infallible> {
+                        OutputPin::set_high(self)
+                    }
+
+                    fn set_low(&mut self) -> Result<(), Infallible> {
+                        OutputPin::set_low(self)
+                    }
+                }
+
+                #[cfg(feature = "eh1")]
+                impl<MODE> hal1::digital::StatefulOutputPin for $PXi<Output<MODE>> {
+                    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+                        Ok($PXi::is_set_high(self))
+                    }
+
+                    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+                        Ok($PXi::is_set_low(self))
+                    }
+
+                    fn toggle(&mut self) -> Result<(), Infallible> {
+                        $PXi::toggle(self);
+                        Ok(())
                     }
                 }
             )+
@@ -319,46 +1074,46 @@ macro_rules! gpio {
     }
 }
 
-gpio!(GPIOA, gpioa, gpioa, iopaen, ioparst, PAx, [
-    PA0: (pa0, 0, Input<Floating>, CRL),
-    PA1: (pa1, 1, Input<Floating>, CRL),
-    PA2: (pa2, 2, Input<Floating>, CRL),
-    PA3: (pa3, 3, Input<Floating>, CRL),
-    PA4: (pa4, 4, Input<Floating>, CRL),
-    PA5: (pa5, 5, Input<Floating>, CRL),
-    PA6: (pa6, 6, Input<Floating>, CRL),
-    PA7: (pa7, 7, Input<Floating>, CRL),
-    PA8: (pa8, 8, Input<Floating>, CRH),
-    PA9: (pa9, 9, Input<Floating>, CRH),
-    PA10: (pa10, 10, Input<Floating>, CRH),
-    PA11: (pa11, 11, Input<Floating>, CRH),
-    PA12: (pa12, 12, Input<Floating>, CRH),
-    PA13: (pa13, 13, Input<Floating>, CRH),
-    PA14: (pa14, 14, Input<Floating>, CRH),
-    PA15: (pa15, 15, Input<Floating>, CRH),
+gpio!(GPIOA, gpioa, gpioa, PAx, 0, [
+    PA0: (pa0, 0, Input<Floating>, CRL, exticr1),
+    PA1: (pa1, 1, Input<Floating>, CRL, exticr1),
+    PA2: (pa2, 2, Input<Floating>, CRL, exticr1),
+    PA3: (pa3, 3, Input<Floating>, CRL, exticr1),
+    PA4: (pa4, 4, Input<Floating>, CRL, exticr2),
+    PA5: (pa5, 5, Input<Floating>, CRL, exticr2),
+    PA6: (pa6, 6, Input<Floating>, CRL, exticr2),
+    PA7: (pa7, 7, Input<Floating>, CRL, exticr2),
+    PA8: (pa8, 8, Input<Floating>, CRH, exticr3),
+    PA9: (pa9, 9, Input<Floating>, CRH, exticr3),
+    PA10: (pa10, 10, Input<Floating>, CRH, exticr3),
+    PA11: (pa11, 11, Input<Floating>, CRH, exticr3),
+    PA12: (pa12, 12, Input<Floating>, CRH, exticr4),
+    PA13: (pa13, 13, Input<Floating>, CRH, exticr4),
+    PA14: (pa14, 14, Input<Floating>, CRH, exticr4),
+    PA15: (pa15, 15, super::Debugger, CRH, exticr4),
 ]);
 
-gpio!(GPIOB, gpiob, gpioa, iopben, iopbrst, PBx, [
-    PB0: (pb0, 0, Input<Floating>, CRL),
-    PB1: (pb1, 1, Input<Floating>, CRL),
-    PB2: (pb2, 2, Input<Floating>, CRL),
-    PB3: (pb3, 3, Input<Floating>, CRL),
-    PB4: (pb4, 4, Input<Floating>, CRL),
-    PB5: (pb5, 5, Input<Floating>, CRL),
-    PB6: (pb6, 6, Input<Floating>, CRL),
-    PB7: (pb7, 7, Input<Floating>, CRL),
-    PB8: (pb8, 8, Input<Floating>, CRH),
-    PB9: (pb9, 9, Input<Floating>, CRH),
-    PB10: (pb10, 10, Input<Floating>, CRH),
-    PB11: (pb11, 11, Input<Floating>, CRH),
-    PB12: (pb12, 12, Input<Floating>, CRH),
-    PB13: (pb13, 13, Input<Floating>, CRH),
-    PB14: (pb14, 14, Input<Floating>, CRH),
-    PB15: (pb15, 15, Input<Floating>, CRH),
+gpio!(GPIOB, gpiob, gpioa, PBx, 1, [
+    PB0: (pb0, 0, Input<Floating>, CRL, exticr1),
+    PB1: (pb1, 1, Input<Floating>, CRL, exticr1),
+    PB2: (pb2, 2, Input<Floating>, CRL, exticr1),
+    PB3: (pb3, 3, super::Debugger, CRL, exticr1),
+    PB4: (pb4, 4, super::Debugger, CRL, exticr2),
+    PB5: (pb5, 5, Input<Floating>, CRL, exticr2),
+    PB6: (pb6, 6, Input<Floating>, CRL, exticr2),
+    PB7: (pb7, 7, Input<Floating>, CRL, exticr2),
+    PB8: (pb8, 8, Input<Floating>, CRH, exticr3),
+    PB9: (pb9, 9, Input<Floating>, CRH, exticr3),
+    PB10: (pb10, 10, Input<Floating>, CRH, exticr3),
+    PB11: (pb11, 11, Input<Floating>, CRH, exticr3),
+    PB12: (pb12, 12, Input<Floating>, CRH, exticr4),
+    PB13: (pb13, 13, Input<Floating>, CRH, exticr4),
+    PB14: (pb14, 14, Input<Floating>, CRH, exticr4),
+    PB15: (pb15, 15, Input<Floating>, CRH, exticr4),
 ]);
 
-gpio!(GPIOC, gpioc, gpioa, iopcen, iopcrst, PCx, [
-    PC13: (pc13, 13, Input<Floating>, CRH),
-    PC14: (pc14, 14, Input<Floating>, CRH),
-    PC15: (pc15, 15, Input<Floating>, CRH),
+gpio!(GPIOC, gpioc, gpioa, PCx, 2, [
+    PC13: (pc13, 13, Input<Floating>, CRH, exticr4),
+    PC14: (pc14, 14, Input<Floating>, CRH, exticr4),
+    PC15: (pc15, 15, Input<Floating>, CRH, exticr4),
 ]);
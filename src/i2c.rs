@@ -0,0 +1,1328 @@
+//! Inter-Integrated Circuit (I2C) bus
+
+use core::cmp;
+use core::marker::Unsize;
+use core::sync::atomic::{self, Ordering};
+
+use cast::{u16, u8};
+use cortex_m::interrupt;
+use stm32f103xx::{i2c1, I2C1, I2C2};
+
+use afio::MAPR;
+use dma::{dma1, Static, Transfer, R};
+use gpio::gpiob::{PB10, PB11, PB12, PB5, PB6, PB7, PB8, PB9};
+use gpio::{Alternate, OpenDrain};
+use hal::blocking::i2c::{Read, Write, WriteRead};
+#[cfg(feature = "eh1")]
+use hal1;
+use rcc::{APB1, Clocks, RccPeripheral};
+use time::Hertz;
+
+/// I2C error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// Bus error
+    Bus,
+    /// Arbitration loss
+    Arbitration,
+    /// No acknowledge received
+    Acknowledge,
+    /// The bus operation didn't complete in time (e.g. the bus is stuck); the peripheral sends a
+    /// STOP and is reset, keeping its configuration, so the next transfer can be attempted
+    Timeout,
+    /// A received byte was lost because the previous one hadn't been read yet (slave mode only)
+    Overrun,
+    // Pec, // SMBUS mode only
+    // Alert, // SMBUS mode only
+    #[doc(hidden)] _Extensible,
+}
+
+pub trait Pins<I2C> {
+    const REMAP: bool;
+}
+
+impl Pins<I2C1> for (PB6<Alternate<OpenDrain>>, PB7<Alternate<OpenDrain>>) {
+    const REMAP: bool = false;
+}
+
+impl Pins<I2C1> for (PB8<Alternate<OpenDrain>>, PB9<Alternate<OpenDrain>>) {
+    const REMAP: bool = true;
+}
+
+impl Pins<I2C2> for (PB10<Alternate<OpenDrain>>, PB11<Alternate<OpenDrain>>) {
+    const REMAP: bool = false;
+}
+
+#[cfg(feature = "eh1")]
+impl hal1::i2c::Error for Error {
+    fn kind(&self) -> hal1::i2c::ErrorKind {
+        use hal1::i2c::{ErrorKind, NoAcknowledgeSource};
+
+        match *self {
+            Error::Bus => ErrorKind::Bus,
+            Error::Arbitration => ErrorKind::ArbitrationLoss,
+            Error::Acknowledge => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            Error::Overrun => ErrorKind::Overrun,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// The general call address; a write to it is a broadcast to all the slaves that listen to it
+pub const GENERAL_CALL: u8 = 0x00;
+
+/// Address(es) the peripheral answers to when it's addressed as a slave
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OwnAddress {
+    /// A single 7-bit address
+    SevenBit(u8),
+    /// Two 7-bit addresses
+    Dual(u8, u8),
+    /// A single 10-bit address
+    TenBit(u16),
+}
+
+/// SMBus alert (SMBA) pin
+pub trait SmbAlertPin<I2C> {}
+
+impl SmbAlertPin<I2C1> for PB5<Alternate<OpenDrain>> {}
+
+impl SmbAlertPin<I2C2> for PB12<Alternate<OpenDrain>> {}
+
+/// I2C peripheral operating in master mode
+///
+/// Data goes through the DR register one byte at a time so there's no limit on the length of the
+/// transfers
+pub struct I2c<I2C, PINS> {
+    i2c: I2C,
+    pins: PINS,
+    timeout: u32,
+    own_address: Option<OwnAddress>,
+}
+
+impl<PINS> I2c<I2C1, PINS> {
+    /// Configures the I2C1 peripheral to work in master mode
+    pub fn i2c1<F>(
+        i2c: I2C1,
+        pins: PINS,
+        mapr: &mut MAPR,
+        freq: F,
+        clocks: Clocks,
+        apb: &mut APB1,
+    ) -> Self
+    where
+        F: Into<Hertz>,
+        PINS: Pins<I2C1>,
+    {
+        mapr.modify_mapr(|_, w| w.i2c1_remap().bit(PINS::REMAP));
+        I2c::_i2c1(i2c, pins, freq.into(), clocks, apb)
+    }
+}
+
+impl<PINS> I2c<I2C2, PINS> {
+    /// Configures the I2C2 peripheral to work in master mode
+    pub fn i2c2<F>(i2c: I2C2, pins: PINS, freq: F, clocks: Clocks, apb: &mut APB1) -> Self
+    where
+        F: Into<Hertz>,
+        PINS: Pins<I2C2>,
+    {
+        I2c::_i2c2(i2c, pins, freq.into(), clocks, apb)
+    }
+}
+
+/// An I2C peripheral that's feeding a DMA write transfer
+pub struct WriteDma<I2C, PINS> {
+    i2c: I2c<I2C, PINS>,
+}
+
+/// Outcome of an interrupt driven transfer
+pub enum Completion {
+    /// The transfer started with `start_write` finished
+    Written,
+    /// The transfer started with `start_write_read` finished; contains the receive buffer
+    Read(&'static mut [u8]),
+    /// The transfer was aborted; contains the receive buffer, if any
+    Failed(Error, Option<&'static mut [u8]>),
+}
+
+/// Progress of an interrupt driven transfer
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// START requested, the slave is about to be addressed in write mode
+    WriteStart,
+    /// Slave addressed in write mode, waiting for its ACK
+    WriteAddr,
+    /// Sending out the bytes
+    Writing,
+    /// (repeated) START requested, the slave is about to be addressed in read mode
+    ReadStart,
+    /// Slave addressed in read mode, waiting for its ACK
+    ReadAddr,
+    /// Receiving the bytes
+    Reading,
+}
+
+/// I2C peripheral operating in master mode where the transfers are driven by interrupts
+///
+/// `start_write` and `start_write_read` return immediately; the transfer then progresses in the
+/// background as long as `handle_event` is called from the `I2Cx_EV` interrupt handler and
+/// `handle_error` from the `I2Cx_ER` interrupt handler. Those return the `Completion` once the
+/// transfer is over.
+///
+/// NOTE unlike the blocking API there's no timeout; a stuck bus leaves the transfer pending
+pub struct IrqI2c<I2C, PINS> {
+    i2c: I2c<I2C, PINS>,
+    state: State,
+    addr: u8,
+    bytes: &'static [u8],
+    buffer: Option<&'static mut [u8]>,
+    index: usize,
+}
+
+/// Direction of a transfer, from the point of view of the master
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// The master writes to the slave
+    Write,
+    /// The master reads from the slave
+    Read,
+}
+
+/// Event of an I2C peripheral operating in slave mode
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SlaveEvent {
+    /// The master addressed this peripheral (START or repeated START)
+    AddressMatched(Direction),
+    /// The master wrote a byte
+    ByteReceived(u8),
+    /// The master wants to read a byte; answer it with `I2cSlave::send`
+    ByteRequested,
+    /// The master didn't acknowledge the last byte it read, which ends the read
+    Nack,
+    /// The master ended the transfer with a STOP condition
+    Stop,
+    /// The transfer was disturbed by a bus error or an overrun
+    Error(Error),
+}
+
+/// I2C peripheral operating in slave mode
+///
+/// The peripheral answers to the address(es) set with `I2c::set_own_address`. After `listen`ing,
+/// call `handle_event` from the `I2Cx_EV` interrupt handler and `handle_error` from the `I2Cx_ER`
+/// interrupt handler; they report what the master did.
+///
+/// The peripheral stretches the clock until each event has been handled. In particular the
+/// `ByteRequested` event keeps firing, and the bus stays stalled, until a byte is `send`.
+pub struct I2cSlave<I2C, PINS> {
+    i2c: I2c<I2C, PINS>,
+}
+
+/// A device with 8-bit register addresses on an I2C bus
+///
+/// Each access writes the register address and then reads / writes the register content in the
+/// same transaction, which is what most sensors and port expanders expect
+pub struct RegisterDevice<I2C> {
+    i2c: I2C,
+    addr: u8,
+}
+
+impl<I2C, E> RegisterDevice<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Wraps the bus `i2c` to talk to the device that has the 7-bit address `addr`
+    pub fn new(i2c: I2C, addr: u8) -> Self {
+        RegisterDevice { i2c, addr }
+    }
+
+    /// Releases the bus
+    pub fn free(self) -> I2C {
+        self.i2c
+    }
+
+    /// Reads the register `reg`
+    pub fn read_u8(&mut self, reg: u8) -> Result<u8, E> {
+        let mut buffer = [0];
+        self.i2c.write_read(self.addr, &[reg], &mut buffer)?;
+
+        Ok(buffer[0])
+    }
+
+    /// Writes `value` to the register `reg`
+    pub fn write_u8(&mut self, reg: u8, value: u8) -> Result<(), E> {
+        self.i2c.write(self.addr, &[reg, value])
+    }
+
+    /// Reads the 16-bit value stored in big endian order in the registers `reg` and `reg + 1`
+    ///
+    /// NOTE this relies on the device auto-incrementing the register address
+    pub fn read_u16_be(&mut self, reg: u8) -> Result<u16, E> {
+        let mut buffer = [0; 2];
+        self.i2c.write_read(self.addr, &[reg], &mut buffer)?;
+
+        Ok(u16::from(buffer[0]) << 8 | u16::from(buffer[1]))
+    }
+
+    /// Reads the 16-bit value stored in little endian order in the registers `reg` and `reg + 1`
+    ///
+    /// NOTE this relies on the device auto-incrementing the register address
+    pub fn read_u16_le(&mut self, reg: u8) -> Result<u16, E> {
+        let mut buffer = [0; 2];
+        self.i2c.write_read(self.addr, &[reg], &mut buffer)?;
+
+        Ok(u16::from(buffer[1]) << 8 | u16::from(buffer[0]))
+    }
+
+    /// Reads enough consecutive registers, starting at `reg`, to fill `buffer`
+    pub fn read_registers(&mut self, reg: u8, buffer: &mut [u8]) -> Result<(), E> {
+        self.i2c.write_read(self.addr, &[reg], buffer)
+    }
+
+    /// Updates the bits of the register `reg` selected by `mask` with the corresponding bits of
+    /// `value`, leaving the other bits untouched
+    ///
+    /// NOTE this is a read-modify-write sequence made of two separate transactions
+    pub fn modify(&mut self, reg: u8, mask: u8, value: u8) -> Result<(), E> {
+        let old = self.read_u8(reg)?;
+
+        self.write_u8(reg, (old & !mask) | (value & mask))
+    }
+}
+
+/// Default number of times a status flag is polled before giving up with `Error::Timeout`
+const TIMEOUT: u32 = 100_000;
+
+/// Lower bound of the number of core cycles spent in each iteration of a polling loop
+const CYCLES_PER_POLL: u32 = 4;
+
+/// Returns the first byte (in write mode) of a 10-bit address: `0b11110` followed by the two most
+/// significant bits of the address
+fn header_10bit(addr: u16) -> u8 {
+    assert!(addr < 1 << 10);
+
+    0b1111_0000 | ((addr >> 7) as u8 & 0b110)
+}
+
+/// Releases the bus after a timeout
+///
+/// This requests a STOP condition and then resets the peripheral (SWRST), which is the only way
+/// to clear a stuck BUSY flag, restoring its configuration afterwards
+fn recover(i2c: &i2c1::RegisterBlock) {
+    // NOTE(mask) START, STOP, POS, PEC and SWRST are not part of the configuration
+    const CR1_CONFIG: u32 = !(1 << 8 | 1 << 9 | 1 << 11 | 1 << 12 | 1 << 15);
+
+    i2c.cr1.modify(|_, w| w.stop().set_bit());
+
+    let cr1 = i2c.cr1.read().bits();
+    let cr2 = i2c.cr2.read().bits();
+    let ccr = i2c.ccr.read().bits();
+    let trise = i2c.trise.read().bits();
+    let oar1 = i2c.oar1.read().bits();
+    let oar2 = i2c.oar2.read().bits();
+
+    i2c.cr1.write(|w| w.swrst().set_bit());
+    i2c.cr1.write(|w| w.swrst().clear_bit());
+
+    // NOTE(unsafe) these values were read back from the registers. CCR and TRISE can only be
+    // written while the peripheral is disabled so CR1 (PE) goes last
+    unsafe {
+        i2c.cr2.write(|w| w.bits(cr2));
+        i2c.ccr.write(|w| w.bits(ccr));
+        i2c.trise.write(|w| w.bits(trise));
+        i2c.oar1.write(|w| w.bits(oar1));
+        i2c.oar2.write(|w| w.bits(oar2));
+        i2c.cr1.write(|w| w.bits(cr1 & CR1_CONFIG));
+    }
+}
+
+macro_rules! busy_wait {
+    ($i2c:expr, $flag:ident, $timeout:expr) => {
+        let mut timeout = $timeout;
+        loop {
+            let sr1 = $i2c.sr1.read();
+
+            if sr1.berr().bit_is_set() {
+                $i2c.sr1.modify(|_, w| w.berr().clear_bit());
+                return Err(Error::Bus);
+            } else if sr1.arlo().bit_is_set() {
+                $i2c.sr1.modify(|_, w| w.arlo().clear_bit());
+                return Err(Error::Arbitration);
+            } else if sr1.af().bit_is_set() {
+                // NOTE(stop) release the bus, the slave won't take any more data
+                $i2c.cr1.modify(|_, w| w.stop().set_bit());
+                $i2c.sr1.modify(|_, w| w.af().clear_bit());
+                return Err(Error::Acknowledge);
+            } else if sr1.$flag().bit_is_set() {
+                break;
+            } else if timeout == 0 {
+                recover(&$i2c);
+                return Err(Error::Timeout);
+            } else {
+                // try again
+                timeout -= 1;
+            }
+        }
+    };
+}
+
+macro_rules! hal {
+    ($($I2CX:ident: ($i2cX:ident, tx: $tx_chan:path),)+) => {
+        $(
+            impl<PINS> I2c<$I2CX, PINS> {
+                fn $i2cX(
+                    i2c: $I2CX,
+                    pins: PINS,
+                    freq: Hertz,
+                    clocks: Clocks,
+                    apb: &mut APB1,
+                ) -> Self {
+                    // enable and reset $I2CX
+                    $I2CX::enable(apb);
+                    $I2CX::reset(apb);
+
+                    let freq = freq.0;
+
+                    assert!(freq <= 400_000);
+
+                    let pclk1 = clocks.pclk1().0;
+                    let pclk1_mhz = u8(pclk1 / 1_000_000).unwrap();
+
+                    assert!(pclk1_mhz >= 2 && pclk1_mhz <= 36);
+
+                    i2c.cr2.write(|w| unsafe { w.freq().bits(pclk1_mhz) });
+
+                    if freq <= 100_000 {
+                        // Standard mode: T_high = T_low = CCR * T_pclk1
+                        // NOTE(round up) so the bus never runs faster than requested
+                        let ccr = (pclk1 + freq * 2 - 1) / (freq * 2);
+                        // NOTE(max) 4 is the minimum value allowed in standard mode
+                        let ccr = if ccr < 4 { 4 } else { ccr };
+
+                        i2c.ccr.write(|w| unsafe { w.ccr().bits(ccr as u16) });
+
+                        // maximum SCL rise time: 1000 ns in standard mode
+                        i2c.trise.write(|w| unsafe { w.trise().bits(pclk1_mhz + 1) });
+                    } else {
+                        // Fast mode, duty cycle 2: T_low = 2 * T_high = 2 * CCR * T_pclk1
+                        // NOTE(round up) e.g. 8 MHz / 400 kHz would otherwise result in 444 kHz
+                        let ccr = (pclk1 + freq * 3 - 1) / (freq * 3);
+                        let ccr = if ccr < 1 { 1 } else { ccr };
+
+                        i2c.ccr.write(|w| unsafe {
+                            w.f_s().set_bit().duty().clear_bit().ccr().bits(ccr as u16)
+                        });
+
+                        // maximum SCL rise time: 300 ns in fast mode
+                        i2c.trise
+                            .write(|w| unsafe { w.trise().bits(pclk1_mhz * 3 / 10 + 1) });
+                    }
+
+                    // enable the peripheral
+                    i2c.cr1.write(|w| w.pe().set_bit());
+
+                    I2c {
+                        i2c,
+                        pins,
+                        timeout: TIMEOUT,
+                        own_address: None,
+                    }
+                }
+
+                /// Releases the I2C peripheral and associated pins
+                pub fn free(self) -> ($I2CX, PINS) {
+                    (self.i2c, self.pins)
+                }
+
+                /// Sets how long, in microseconds, the bus operations wait for the slave (e.g. while
+                /// it stretches SCL) before giving up with `Error::Timeout`
+                ///
+                /// NOTE the timeout is implemented by counting polling iterations so the actual
+                /// timeout is at least, but likely longer than, `timeout_us`
+                pub fn set_timeout_us(&mut self, timeout_us: u32, clocks: Clocks) {
+                    let polls = u64::from(timeout_us) * u64::from(clocks.sysclk().0)
+                        / 1_000_000
+                        / u64::from(CYCLES_PER_POLL);
+
+                    self.timeout = cmp::min(polls, u64::from(u32::max_value())) as u32;
+                }
+
+                /// Sets the address(es) this peripheral answers to when it's addressed as a slave
+                ///
+                /// NOTE master mode transfers ignore the own address
+                pub fn set_own_address(&mut self, addr: OwnAddress) {
+                    // NOTE(bit 14) must be kept at 1 by software
+                    const OAR1_BIT14: u32 = 1 << 14;
+
+                    match addr {
+                        OwnAddress::SevenBit(add) | OwnAddress::Dual(add, _) => {
+                            assert!(add <= 0x7f);
+
+                            self.i2c
+                                .oar1
+                                .write(|w| unsafe { w.bits(OAR1_BIT14 | u32::from(add) << 1) });
+                        }
+                        OwnAddress::TenBit(add) => {
+                            assert!(add <= 0x3ff);
+
+                            // ADDMODE: 10-bit addressing
+                            self.i2c.oar1.write(|w| unsafe {
+                                w.bits(OAR1_BIT14 | 1 << 15 | u32::from(add))
+                            });
+                        }
+                    }
+
+                    match addr {
+                        OwnAddress::Dual(_, add2) => {
+                            assert!(add2 <= 0x7f);
+
+                            self.i2c
+                                .oar2
+                                .write(|w| unsafe { w.add2().bits(add2).endual().set_bit() });
+                        }
+                        _ => self.i2c.oar2.write(|w| w.endual().clear_bit()),
+                    }
+
+                    self.own_address = Some(addr);
+                }
+
+                /// Returns the address(es) set with `set_own_address`, if any
+                pub fn own_address(&self) -> Option<OwnAddress> {
+                    self.own_address
+                }
+
+                /// Broadcasts `bytes` to all the slaves that have general call recognition enabled
+                ///
+                /// This is a plain write to the `GENERAL_CALL` address; it fails with
+                /// `Error::Acknowledge` if no slave acknowledges the broadcast
+                pub fn general_call(&mut self, bytes: &[u8]) -> Result<(), Error> {
+                    self.write(GENERAL_CALL, bytes)
+                }
+
+                /// Makes this peripheral acknowledge the general call address when it's being
+                /// addressed as a slave
+                ///
+                /// NOTE this has no effect on the master mode transfers
+                pub fn enable_general_call(&mut self) {
+                    self.i2c.cr1.modify(|_, w| w.engc().set_bit());
+                }
+
+                /// Stops acknowledging the general call address in slave mode
+                pub fn disable_general_call(&mut self) {
+                    self.i2c.cr1.modify(|_, w| w.engc().clear_bit());
+                }
+
+                /// Switches to SMBus host mode and starts listening for alerts on the SMBA pin
+                ///
+                /// The error interrupt (`I2Cx_ER`) fires when a device pulls SMBA low; use
+                /// `is_smbus_alert` in the handler to tell the alert apart from bus errors and
+                /// `clear_smbus_alert` to acknowledge it
+                pub fn listen_smbus_alert<P>(&mut self, _smba: &P)
+                where
+                    P: SmbAlertPin<$I2CX>,
+                {
+                    self.i2c.cr1.modify(|_, w| {
+                        w.smbus().set_bit().smbtype().set_bit().alert().set_bit()
+                    });
+                    self.i2c.cr2.modify(|_, w| w.iterren().set_bit());
+                }
+
+                /// Stops listening for SMBus alerts and goes back to plain I2C mode
+                pub fn unlisten_smbus_alert(&mut self) {
+                    self.i2c.cr2.modify(|_, w| w.iterren().clear_bit());
+                    self.i2c.cr1.modify(|_, w| {
+                        w.smbus().clear_bit().smbtype().clear_bit().alert().clear_bit()
+                    });
+                }
+
+                /// Returns `true` if a device has raised an SMBus alert
+                pub fn is_smbus_alert(&self) -> bool {
+                    self.i2c.sr1.read().smbalert().bit_is_set()
+                }
+
+                /// Acknowledges an SMBus alert
+                ///
+                /// NOTE the alerting device must still be queried (Alert Response Address) to
+                /// release the SMBA line
+                pub fn clear_smbus_alert(&mut self) {
+                    self.i2c.sr1.modify(|_, w| w.smbalert().clear_bit());
+                }
+
+                /// Switches to interrupt driven transfers
+                ///
+                /// This enables the event and error interrupts of the peripheral while a transfer
+                /// is ongoing; the `I2Cx_EV` and `I2Cx_ER` interrupts must be unmasked in the NVIC
+                pub fn into_irq(self) -> IrqI2c<$I2CX, PINS> {
+                    IrqI2c {
+                        i2c: self,
+                        state: State::Idle,
+                        addr: 0,
+                        bytes: &[],
+                        buffer: None,
+                        index: 0,
+                    }
+                }
+
+                /// Switches to slave mode
+                ///
+                /// The own address must have been set with `set_own_address`; the peripheral
+                /// doesn't answer the master until `listen` is called
+                pub fn into_slave(self) -> I2cSlave<$I2CX, PINS> {
+                    assert!(self.own_address.is_some());
+
+                    I2cSlave { i2c: self }
+                }
+
+                /// Generates a START condition and addresses the slave in write mode
+                fn start_write(&mut self, addr: u8) -> Result<(), Error> {
+                    // START
+                    self.i2c.cr1.modify(|_, w| w.start().set_bit());
+                    busy_wait!(self.i2c, sb, self.timeout);
+
+                    // slave address + write
+                    self.i2c.dr.write(|w| unsafe { w.dr().bits(addr << 1) });
+                    busy_wait!(self.i2c, addr, self.timeout);
+
+                    // NOTE(read) reading SR2 after SR1 clears the ADDR flag
+                    self.i2c.sr2.read();
+
+                    Ok(())
+                }
+
+                /// Generates a START condition and addresses the slave in write mode using a
+                /// 10-bit address
+                fn start_write_10bit(&mut self, addr: u16) -> Result<(), Error> {
+                    // START
+                    self.i2c.cr1.modify(|_, w| w.start().set_bit());
+                    busy_wait!(self.i2c, sb, self.timeout);
+
+                    // header (two MSBs of the address) + write
+                    let header = header_10bit(addr);
+                    self.i2c.dr.write(|w| unsafe { w.dr().bits(header) });
+                    busy_wait!(self.i2c, add10, self.timeout);
+
+                    // rest of the address
+                    self.i2c.dr.write(|w| unsafe { w.dr().bits(addr as u8) });
+                    busy_wait!(self.i2c, addr, self.timeout);
+
+                    // NOTE(read) reading SR2 after SR1 clears the ADDR flag
+                    self.i2c.sr2.read();
+
+                    Ok(())
+                }
+
+                /// Sends `bytes` to the slave, which must already be addressed in write mode
+                fn send(&mut self, bytes: &[u8]) -> Result<(), Error> {
+                    for byte in bytes {
+                        // wait until we are allowed to send data (START has been ACKed or last
+                        // byte went through)
+                        busy_wait!(self.i2c, tx_e, self.timeout);
+
+                        // put byte in the data register
+                        self.i2c.dr.write(|w| unsafe { w.dr().bits(*byte) });
+                    }
+
+                    // wait until the last byte has been shifted out and ACKed
+                    // NOTE(if) BTF never gets set if no data was sent (e.g. when probing addresses)
+                    if !bytes.is_empty() {
+                        busy_wait!(self.i2c, btf, self.timeout);
+                    }
+
+                    Ok(())
+                }
+
+                /// Generates a STOP condition and waits until it has been put on the bus
+                fn stop(&mut self) -> Result<(), Error> {
+                    self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+
+                    // NOTE the hardware clears the STOP bit once the STOP condition has been put on
+                    // the bus; a START requested before that point would be ignored
+                    self.wait_stop()
+                }
+
+                /// Writes `bytes` to the slave that has the 10-bit address `addr`
+                pub fn write_10bit(&mut self, addr: u16, bytes: &[u8]) -> Result<(), Error> {
+                    self.start_write_10bit(addr)?;
+                    self.send(bytes)?;
+                    self.stop()
+                }
+
+                /// Reads enough bytes from the slave that has the 10-bit address `addr` to fill
+                /// `buffer`
+                pub fn read_10bit(&mut self, addr: u16, buffer: &mut [u8]) -> Result<(), Error> {
+                    // NOTE(return) the hardware can't address a slave in read mode without
+                    // clocking in at least one byte
+                    if buffer.is_empty() {
+                        return Ok(());
+                    }
+
+                    // NOTE a 10-bit read always starts by sending the full address in write mode;
+                    // the repeated START then only carries the header
+                    self.start_write_10bit(addr)?;
+                    self.recv(header_10bit(addr) | 1, buffer)
+                }
+
+                /// Writes `bytes` to the slave that has the 10-bit address `addr` and then reads
+                /// enough bytes back to fill `buffer` *in a single transaction*
+                pub fn write_read_10bit(
+                    &mut self,
+                    addr: u16,
+                    bytes: &[u8],
+                    buffer: &mut [u8],
+                ) -> Result<(), Error> {
+                    // NOTE(write_10bit) nothing to read back; see `read_10bit`
+                    if buffer.is_empty() {
+                        return self.write_10bit(addr, bytes);
+                    }
+
+                    self.start_write_10bit(addr)?;
+                    self.send(bytes)?;
+                    self.recv(header_10bit(addr) | 1, buffer)
+                }
+
+                /// Writes `buffer` to the slave using a DMA transfer
+                ///
+                /// Once the DMA transfer is done the bus must be released with
+                /// [`WriteDma.stop`](struct.WriteDma.html#method.stop)
+                pub fn write_dma<A, B>(
+                    mut self,
+                    addr: u8,
+                    mut chan: $tx_chan,
+                    buffer: B,
+                ) -> Result<
+                    Transfer<R, B, $tx_chan, WriteDma<$I2CX, PINS>>,
+                    (Error, Self, $tx_chan, B),
+                >
+                where
+                    A: Unsize<[u8]>,
+                    B: Static<A>,
+                {
+                    {
+                        let buffer: &[u8] = buffer.borrow();
+                        assert!(buffer.len() > 0);
+
+                        chan.cmar().write(|w| unsafe {
+                            w.ma().bits(buffer.as_ptr() as usize as u32)
+                        });
+                        chan.cndtr().write(|w| unsafe{
+                            w.ndt().bits(u16(buffer.len()).unwrap())
+                        });
+                        chan.cpar().write(|w| unsafe {
+                            w.pa().bits(&(*$I2CX::ptr()).dr as *const _ as usize as u32)
+                        });
+
+                        // TODO can we weaken this compiler barrier?
+                        // NOTE(compiler_fence) operations on `buffer` should not be reordered after
+                        // the next statement, which starts the DMA transfer
+                        atomic::compiler_fence(Ordering::SeqCst);
+
+                        chan.ccr().modify(|_, w| {
+                            w.mem2mem()
+                                .clear_bit()
+                                .pl()
+                                .medium()
+                                .msize()
+                                .bit8()
+                                .psize()
+                                .bit8()
+                                .minc()
+                                .set_bit()
+                                .pinc()
+                                .clear_bit()
+                                .circ()
+                                .clear_bit()
+                                .dir()
+                                .set_bit()
+                                .en()
+                                .set_bit()
+                        });
+                    }
+
+                    // NOTE the DMA requests start once the ADDR flag has been cleared
+                    self.i2c.cr2.modify(|_, w| w.dmaen().set_bit());
+
+                    if let Err(e) = self.start_write(addr) {
+                        chan.ccr().modify(|_, w| w.en().clear_bit());
+                        self.i2c.cr2.modify(|_, w| w.dmaen().clear_bit());
+
+                        return Err((e, self, chan, buffer));
+                    }
+
+                    Ok(Transfer::r(buffer, chan, WriteDma { i2c: self }))
+                }
+
+                /// Waits until the hardware has put the requested STOP condition on the bus
+                fn wait_stop(&self) -> Result<(), Error> {
+                    let mut timeout = self.timeout;
+                    while self.i2c.cr1.read().stop().bit_is_set() {
+                        if timeout == 0 {
+                            recover(&self.i2c);
+                            return Err(Error::Timeout);
+                        }
+
+                        timeout -= 1;
+                    }
+
+                    Ok(())
+                }
+
+                /// Receives `buffer.len()` bytes from the slave and then generates a STOP
+                /// condition; the START condition, followed by `header` (the address byte, read
+                /// bit included), is generated by this method
+                fn recv(&mut self, header: u8, buffer: &mut [u8]) -> Result<(), Error> {
+                    // (repeated) START
+                    self.i2c.cr1.modify(|_, w| w.start().set_bit());
+                    busy_wait!(self.i2c, sb, self.timeout);
+
+                    // NOTE the ACK / NACK of the last bytes has to be configured before they are
+                    // clocked in; see section 26.3.3 of the reference manual (RM0008)
+                    let len = buffer.len();
+                    match len {
+                        // the POS bit makes the NACK apply to the *next* byte
+                        2 => self.i2c.cr1.modify(|_, w| w.pos().set_bit().ack().set_bit()),
+                        1 => self.i2c.cr1.modify(|_, w| w.pos().clear_bit().ack().clear_bit()),
+                        _ => self.i2c.cr1.modify(|_, w| w.pos().clear_bit().ack().set_bit()),
+                    }
+
+                    // slave address + read
+                    self.i2c.dr.write(|w| unsafe { w.dr().bits(header) });
+                    busy_wait!(self.i2c, addr, self.timeout);
+
+                    match len {
+                        1 => {
+                            // NOTE(interrupt::free) STOP must be requested before the byte has
+                            // been received, otherwise the slave will send out one more byte
+                            interrupt::free(|_| {
+                                self.i2c.sr2.read();
+                                self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                            });
+
+                            busy_wait!(self.i2c, rx_ne, self.timeout);
+                            buffer[0] = self.i2c.dr.read().dr().bits();
+                        }
+                        2 => {
+                            self.i2c.sr2.read();
+                            self.i2c.cr1.modify(|_, w| w.ack().clear_bit());
+
+                            // byte 1 in DR, byte 2 in the shift register
+                            busy_wait!(self.i2c, btf, self.timeout);
+
+                            interrupt::free(|_| {
+                                self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                                buffer[0] = self.i2c.dr.read().dr().bits();
+                            });
+                            buffer[1] = self.i2c.dr.read().dr().bits();
+                        }
+                        _ => {
+                            self.i2c.sr2.read();
+
+                            let (head, tail) = buffer.split_at_mut(len - 3);
+                            for byte in head {
+                                busy_wait!(self.i2c, rx_ne, self.timeout);
+                                *byte = self.i2c.dr.read().dr().bits();
+                            }
+
+                            // byte N-2 in DR, byte N-1 in the shift register
+                            busy_wait!(self.i2c, btf, self.timeout);
+                            self.i2c.cr1.modify(|_, w| w.ack().clear_bit());
+                            tail[0] = self.i2c.dr.read().dr().bits();
+
+                            // byte N-1 in DR, byte N in the shift register
+                            busy_wait!(self.i2c, btf, self.timeout);
+                            interrupt::free(|_| {
+                                self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                                tail[1] = self.i2c.dr.read().dr().bits();
+                            });
+
+                            busy_wait!(self.i2c, rx_ne, self.timeout);
+                            tail[2] = self.i2c.dr.read().dr().bits();
+                        }
+                    }
+
+                    // wait for the STOP condition to be generated (see `write`)
+                    self.wait_stop()?;
+
+                    // leave the peripheral ready for the next transfer
+                    self.i2c.cr1.modify(|_, w| w.pos().clear_bit().ack().set_bit());
+
+                    Ok(())
+                }
+            }
+
+            impl<PINS> IrqI2c<$I2CX, PINS> {
+                /// Returns `true` if no transfer is ongoing and a new one can be started
+                pub fn is_idle(&self) -> bool {
+                    // NOTE a START requested before the hardware clears the STOP bit is ignored
+                    self.state == State::Idle && self.i2c.i2c.cr1.read().stop().bit_is_clear()
+                }
+
+                /// Starts writing `bytes` to the slave
+                ///
+                /// `bytes` is handed back if a transfer is already ongoing
+                pub fn start_write(
+                    &mut self,
+                    addr: u8,
+                    bytes: &'static [u8],
+                ) -> Result<(), &'static [u8]> {
+                    if !self.is_idle() {
+                        return Err(bytes);
+                    }
+
+                    self.addr = addr;
+                    self.bytes = bytes;
+                    self.buffer = None;
+                    self.start(State::WriteStart);
+
+                    Ok(())
+                }
+
+                /// Starts writing `bytes` to the slave and then, after a repeated START, reading
+                /// `buffer.len()` bytes back from it
+                ///
+                /// If `bytes` is empty the slave is directly addressed in read mode; if `buffer` is
+                /// empty the transfer ends after the write phase. `bytes` and `buffer` are handed
+                /// back if a transfer is already ongoing
+                pub fn start_write_read(
+                    &mut self,
+                    addr: u8,
+                    bytes: &'static [u8],
+                    buffer: &'static mut [u8],
+                ) -> Result<(), (&'static [u8], &'static mut [u8])> {
+                    if !self.is_idle() {
+                        return Err((bytes, buffer));
+                    }
+
+                    let read = !buffer.is_empty();
+                    self.addr = addr;
+                    self.bytes = bytes;
+                    self.buffer = Some(buffer);
+                    self.start(if bytes.is_empty() && read {
+                        State::ReadStart
+                    } else {
+                        State::WriteStart
+                    });
+
+                    Ok(())
+                }
+
+                /// Advances the ongoing transfer; call this from the `I2Cx_EV` interrupt handler
+                ///
+                /// Returns the outcome of the transfer once it's over
+                pub fn handle_event(&mut self) -> Option<Completion> {
+                    let sr1 = self.i2c.i2c.sr1.read();
+
+                    match self.state {
+                        State::Idle => {}
+                        State::WriteStart => if sr1.sb().bit_is_set() {
+                            let addr = self.addr;
+                            self.i2c.i2c.dr.write(|w| unsafe { w.dr().bits(addr << 1) });
+                            self.state = State::WriteAddr;
+                        },
+                        State::WriteAddr => if sr1.addr().bit_is_set() {
+                            // NOTE(read) reading SR2 after SR1 clears the ADDR flag
+                            self.i2c.i2c.sr2.read();
+
+                            // NOTE(if) BTF never gets set if no data is sent
+                            if self.bytes.is_empty() {
+                                return self.end_write();
+                            }
+
+                            self.index = 0;
+                            self.state = State::Writing;
+                            self.i2c.i2c.cr2.modify(|_, w| w.itbufen().set_bit());
+                        },
+                        State::Writing => if self.index < self.bytes.len() {
+                            if sr1.tx_e().bit_is_set() {
+                                let byte = self.bytes[self.index];
+                                self.i2c.i2c.dr.write(|w| unsafe { w.dr().bits(byte) });
+                                self.index += 1;
+
+                                // NOTE(itbufen) from now on wait (BTF) until the last byte has
+                                // been shifted out and ACKed
+                                if self.index == self.bytes.len() {
+                                    self.i2c.i2c.cr2.modify(|_, w| w.itbufen().clear_bit());
+                                }
+                            }
+                        } else if sr1.btf().bit_is_set() {
+                            return self.end_write();
+                        },
+                        State::ReadStart => if sr1.sb().bit_is_set() {
+                            // NOTE the ACK / NACK of the last bytes has to be configured before
+                            // they are clocked in; see `recv`
+                            let (pos, ack) = match self.len() {
+                                2 => (true, true),
+                                1 => (false, false),
+                                _ => (false, true),
+                            };
+                            self.i2c.i2c.cr1.modify(|_, w| w.pos().bit(pos).ack().bit(ack));
+
+                            let addr = self.addr;
+                            self.i2c.i2c.dr.write(|w| unsafe { w.dr().bits((addr << 1) | 1) });
+                            self.state = State::ReadAddr;
+                        },
+                        State::ReadAddr => if sr1.addr().bit_is_set() {
+                            let len = self.len();
+                            match len {
+                                1 => {
+                                    // NOTE(interrupt::free) STOP must be requested before the
+                                    // byte has been received
+                                    interrupt::free(|_| {
+                                        self.i2c.i2c.sr2.read();
+                                        self.i2c.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                                    });
+                                }
+                                2 => {
+                                    self.i2c.i2c.sr2.read();
+                                    self.i2c.i2c.cr1.modify(|_, w| w.ack().clear_bit());
+                                }
+                                _ => {
+                                    self.i2c.i2c.sr2.read();
+                                }
+                            }
+
+                            // NOTE(itbufen) the last 2 or 3 bytes are handled on BTF
+                            if len == 1 || len > 3 {
+                                self.i2c.i2c.cr2.modify(|_, w| w.itbufen().set_bit());
+                            }
+
+                            self.index = 0;
+                            self.state = State::Reading;
+                        },
+                        State::Reading => {
+                            let remaining = self.len() - self.index;
+
+                            if remaining == 1 || remaining > 3 {
+                                if sr1.rx_ne().bit_is_set() {
+                                    self.recv_byte();
+
+                                    match remaining {
+                                        1 => return self.end_read(),
+                                        4 => {
+                                            self.i2c.i2c.cr2.modify(|_, w| w.itbufen().clear_bit())
+                                        }
+                                        _ => {}
+                                    }
+                                }
+                            } else if sr1.btf().bit_is_set() {
+                                if remaining == 3 {
+                                    // byte N-2 in DR, byte N-1 in the shift register
+                                    self.i2c.i2c.cr1.modify(|_, w| w.ack().clear_bit());
+                                    self.recv_byte();
+                                } else {
+                                    // byte N-1 in DR, byte N in the shift register
+                                    interrupt::free(|_| {
+                                        self.i2c.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                                        self.recv_byte();
+                                    });
+                                    self.recv_byte();
+
+                                    return self.end_read();
+                                }
+                            }
+                        }
+                    }
+
+                    None
+                }
+
+                /// Handles bus errors; call this from the `I2Cx_ER` interrupt handler
+                ///
+                /// Returns the outcome of the ongoing transfer, if it had to be aborted
+                pub fn handle_error(&mut self) -> Option<Completion> {
+                    let sr1 = self.i2c.i2c.sr1.read();
+
+                    let error = if sr1.berr().bit_is_set() {
+                        self.i2c.i2c.sr1.modify(|_, w| w.berr().clear_bit());
+                        Error::Bus
+                    } else if sr1.arlo().bit_is_set() {
+                        self.i2c.i2c.sr1.modify(|_, w| w.arlo().clear_bit());
+                        Error::Arbitration
+                    } else if sr1.af().bit_is_set() {
+                        // NOTE(stop) release the bus, the slave won't take any more data
+                        self.i2c.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                        self.i2c.i2c.sr1.modify(|_, w| w.af().clear_bit());
+                        Error::Acknowledge
+                    } else {
+                        return None;
+                    };
+
+                    if self.state == State::Idle {
+                        return None;
+                    }
+
+                    self.i2c.i2c.cr1.modify(|_, w| w.pos().clear_bit().ack().set_bit());
+                    self.finish();
+
+                    Some(Completion::Failed(error, self.buffer.take()))
+                }
+
+                /// Switches back to blocking transfers
+                ///
+                /// NOTE an ongoing transfer is abandoned
+                pub fn release(mut self) -> I2c<$I2CX, PINS> {
+                    self.finish();
+                    self.i2c
+                }
+
+                fn start(&mut self, state: State) {
+                    self.state = state;
+                    self.i2c.i2c.cr2.modify(|_, w| w.itevten().set_bit().iterren().set_bit());
+                    self.i2c.i2c.cr1.modify(|_, w| w.start().set_bit());
+                }
+
+                fn len(&self) -> usize {
+                    self.buffer.as_ref().map(|buffer| buffer.len()).unwrap_or(0)
+                }
+
+                fn recv_byte(&mut self) {
+                    let byte = self.i2c.i2c.dr.read().dr().bits();
+                    if let Some(ref mut buffer) = self.buffer {
+                        buffer[self.index] = byte;
+                    }
+                    self.index += 1;
+                }
+
+                fn end_write(&mut self) -> Option<Completion> {
+                    // NOTE(len) an empty receive buffer is handed back without a read phase
+                    if self.len() > 0 {
+                        // repeated START
+                        self.state = State::ReadStart;
+                        self.i2c.i2c.cr1.modify(|_, w| w.start().set_bit());
+                        None
+                    } else {
+                        self.i2c.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                        self.finish();
+                        Some(
+                            self.buffer
+                                .take()
+                                .map(Completion::Read)
+                                .unwrap_or(Completion::Written),
+                        )
+                    }
+                }
+
+                fn end_read(&mut self) -> Option<Completion> {
+                    // leave the peripheral ready for the next transfer
+                    self.i2c.i2c.cr1.modify(|_, w| w.pos().clear_bit().ack().set_bit());
+                    self.finish();
+
+                    self.buffer.take().map(Completion::Read)
+                }
+
+                fn finish(&mut self) {
+                    self.state = State::Idle;
+
+                    // NOTE(alert) the error interrupt is still needed for the SMBus alerts
+                    let alert = self.i2c.i2c.cr1.read().alert().bit_is_set();
+                    self.i2c.i2c.cr2.modify(|_, w| {
+                        w.itevten()
+                            .clear_bit()
+                            .itbufen()
+                            .clear_bit()
+                            .iterren()
+                            .bit(alert)
+                    });
+                }
+            }
+
+            impl<PINS> I2cSlave<$I2CX, PINS> {
+                /// Starts acknowledging the own address and enables the event and error
+                /// interrupts; the `I2Cx_EV` and `I2Cx_ER` interrupts must be unmasked in the NVIC
+                pub fn listen(&mut self) {
+                    self.i2c.i2c.cr1.modify(|_, w| w.ack().set_bit());
+                    self.i2c.i2c.cr2.modify(|_, w| {
+                        w.itevten().set_bit().itbufen().set_bit().iterren().set_bit()
+                    });
+                }
+
+                /// Stops answering the master and disables the interrupts
+                pub fn unlisten(&mut self) {
+                    // NOTE(alert) the error interrupt is still needed for the SMBus alerts
+                    let alert = self.i2c.i2c.cr1.read().alert().bit_is_set();
+                    self.i2c.i2c.cr2.modify(|_, w| {
+                        w.itevten()
+                            .clear_bit()
+                            .itbufen()
+                            .clear_bit()
+                            .iterren()
+                            .bit(alert)
+                    });
+                    self.i2c.i2c.cr1.modify(|_, w| w.ack().clear_bit());
+                }
+
+                /// Services the peripheral; call this from the `I2Cx_EV` interrupt handler
+                pub fn handle_event(&mut self) -> Option<SlaveEvent> {
+                    let sr1 = self.i2c.i2c.sr1.read();
+
+                    if sr1.addr().bit_is_set() {
+                        // NOTE(read) reading SR2 after SR1 clears the ADDR flag
+                        let direction = if self.i2c.i2c.sr2.read().tra().bit_is_set() {
+                            Direction::Read
+                        } else {
+                            Direction::Write
+                        };
+
+                        Some(SlaveEvent::AddressMatched(direction))
+                    } else if sr1.rx_ne().bit_is_set() {
+                        Some(SlaveEvent::ByteReceived(self.i2c.i2c.dr.read().dr().bits()))
+                    } else if sr1.tx_e().bit_is_set() {
+                        Some(SlaveEvent::ByteRequested)
+                    } else if sr1.stopf().bit_is_set() {
+                        // NOTE(modify) writing CR1 after reading SR1 clears the STOPF flag
+                        self.i2c.i2c.cr1.modify(|_, w| w);
+
+                        Some(SlaveEvent::Stop)
+                    } else {
+                        None
+                    }
+                }
+
+                /// Handles the error conditions; call this from the `I2Cx_ER` interrupt handler
+                pub fn handle_error(&mut self) -> Option<SlaveEvent> {
+                    let sr1 = self.i2c.i2c.sr1.read();
+
+                    if sr1.af().bit_is_set() {
+                        // NOTE this is how the master ends a read, not an actual error
+                        self.i2c.i2c.sr1.modify(|_, w| w.af().clear_bit());
+                        Some(SlaveEvent::Nack)
+                    } else if sr1.berr().bit_is_set() {
+                        self.i2c.i2c.sr1.modify(|_, w| w.berr().clear_bit());
+                        Some(SlaveEvent::Error(Error::Bus))
+                    } else if sr1.ovr().bit_is_set() {
+                        self.i2c.i2c.sr1.modify(|_, w| w.ovr().clear_bit());
+                        Some(SlaveEvent::Error(Error::Overrun))
+                    } else {
+                        None
+                    }
+                }
+
+                /// Sends `byte` to the master; call this in response to `ByteRequested`
+                pub fn send(&mut self, byte: u8) {
+                    self.i2c.i2c.dr.write(|w| unsafe { w.dr().bits(byte) });
+                }
+
+                /// Switches back to master mode
+                pub fn release(mut self) -> I2c<$I2CX, PINS> {
+                    self.unlisten();
+                    self.i2c
+                }
+            }
+
+            impl<PINS> WriteDma<$I2CX, PINS> {
+                /// Generates the STOP condition that ends the DMA write and releases the I2C
+                /// peripheral
+                pub fn stop(mut self) -> Result<I2c<$I2CX, PINS>, (Error, I2c<$I2CX, PINS>)> {
+                    match self.finish() {
+                        Ok(()) => Ok(self.i2c),
+                        Err(e) => Err((e, self.i2c)),
+                    }
+                }
+
+                fn finish(&mut self) -> Result<(), Error> {
+                    self.i2c.i2c.cr2.modify(|_, w| w.dmaen().clear_bit());
+
+                    // wait until the last byte has been shifted out and ACKed
+                    busy_wait!(self.i2c.i2c, btf, self.i2c.timeout);
+
+                    self.i2c.stop()
+                }
+            }
+
+            impl<PINS> Write for I2c<$I2CX, PINS> {
+                type Error = Error;
+
+                fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+                    self.start_write(addr)?;
+                    self.send(bytes)?;
+                    self.stop()
+                }
+            }
+
+            impl<PINS> Read for I2c<$I2CX, PINS> {
+                type Error = Error;
+
+                fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
+                    // NOTE(return) see `read_10bit`
+                    if buffer.is_empty() {
+                        return Ok(());
+                    }
+
+                    self.recv((addr << 1) | 1, buffer)
+                }
+            }
+
+            impl<PINS> WriteRead for I2c<$I2CX, PINS> {
+                type Error = Error;
+
+                fn write_read(
+                    &mut self,
+                    addr: u8,
+                    bytes: &[u8],
+                    buffer: &mut [u8],
+                ) -> Result<(), Error> {
+                    // NOTE(write) nothing to read back; see `read_10bit`
+                    if buffer.is_empty() {
+                        return Write::write(self, addr, bytes);
+                    }
+
+                    self.start_write(addr)?;
+                    self.send(bytes)?;
+                    self.recv((addr << 1) | 1, buffer)
+                }
+            }
+
+            #[cfg(feature = "eh1")]
+            impl<PINS> hal1::i2c::ErrorType for I2c<$I2CX, PINS> {
+                type Error = Error;
+            }
+
+            #[cfg(feature = "eh1")]
+            impl<PINS> hal1::i2c::I2c for I2c<$I2CX, PINS> {
+                /// NOTE the hardware can't hold the bus after the last byte of a read has been
+                /// clocked in so every (non-empty) `Read` operation ends with a STOP condition;
+                /// the next operation, if any, starts a new transfer
+                fn transaction(
+                    &mut self,
+                    addr: u8,
+                    operations: &mut [hal1::i2c::Operation],
+                ) -> Result<(), Error> {
+                    use hal1::i2c::Operation;
+
+                    // `true` if the slave is currently addressed in write mode
+                    let mut writing = false;
+                    for op in operations {
+                        match *op {
+                            Operation::Write(bytes) => {
+                                if !writing {
+                                    self.start_write(addr)?;
+                                    writing = true;
+                                }
+
+                                self.send(bytes)?;
+                            }
+                            Operation::Read(ref mut buffer) => {
+                                if buffer.is_empty() {
+                                    continue;
+                                }
+
+                                self.recv((addr << 1) | 1, buffer)?;
+                                writing = false;
+                            }
+                        }
+                    }
+
+                    if writing {
+                        self.stop()?;
+                    }
+
+                    Ok(())
+                }
+            }
+        )+
+    }
+}
+
+hal! {
+    I2C1: (_i2c1, tx: dma1::C6),
+    I2C2: (_i2c2, tx: dma1::C4),
+}
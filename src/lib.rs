@@ -28,6 +28,12 @@
 //!
 //! [cortex-m-quickstart]: https://docs.rs/cortex-m-quickstart/~0.2.3
 //!
+//! - embedded-hal 1.0
+//!
+//! The drivers implement the [`embedded-hal`] 0.2 traits. Enabling the "eh1" Cargo feature
+//! additionally implements the embedded-hal 1.0 `digital`, `i2c::I2c`, `spi::SpiBus` and
+//! `spi::SpiDevice` traits so the crate can be used with drivers written against 1.0.
+//!
 //! # Examples
 //!
 //! See the [examples] module.
@@ -41,22 +47,36 @@
 extern crate cast;
 extern crate cortex_m;
 extern crate embedded_hal as hal;
+#[cfg(feature = "eh1")]
+extern crate embedded_hal_1 as hal1;
+#[macro_use(block)]
 extern crate nb;
 pub extern crate stm32f103xx;
+extern crate void;
 
+pub mod adc;
 pub mod afio;
+pub mod backup_domain;
 pub mod bb;
+pub mod bitbang;
+pub mod can;
+pub mod capture;
+pub mod dac;
 pub mod delay;
 pub mod dma;
 #[cfg(feature = "doc")]
 pub mod examples;
 pub mod flash;
 pub mod gpio;
+pub mod i2c;
 pub mod prelude;
 pub mod pwm;
+pub mod pwr;
 pub mod qei;
 pub mod rcc;
+pub mod rtc;
 pub mod serial;
 pub mod spi;
 pub mod time;
 pub mod timer;
+pub mod watchdog;
@@ -1,8 +1,21 @@
+//! Prelude
+//!
+//! Re-exports the extension traits of this crate and the `embedded-hal` traits, renamed to avoid
+//! collisions, so that a single `use stm32f103xx_hal::prelude::*;` brings their methods into
+//! scope. No types or peripherals are exported.
+
 pub use afio::AfioExt as _stm32f103xx_hal_afio_AfioExt;
+pub use backup_domain::BkpExt as _stm32f103xx_hal_backup_domain_BkpExt;
+pub use dac::DacExt as _stm32f103xx_hal_dac_DacExt;
 pub use dma::DmaExt as _stm32f103xx_hal_dma_DmaExt;
 pub use flash::FlashExt as _stm32f103xx_hal_flash_FlashExt;
 pub use gpio::GpioExt as _stm32f103xx_hal_gpio_GpioExt;
+pub use hal::digital::v2::InputPin as _embedded_hal_digital_v2_InputPin;
+pub use hal::digital::v2::OutputPin as _embedded_hal_digital_v2_OutputPin;
+pub use hal::digital::v2::StatefulOutputPin as _embedded_hal_digital_v2_StatefulOutputPin;
+pub use hal::digital::v2::ToggleableOutputPin as _embedded_hal_digital_v2_ToggleableOutputPin;
 pub use hal::prelude::*;
 pub use pwm::PwmExt as _stm32f103xx_hal_pwm_PwmExt;
+pub use pwr::PwrExt as _stm32f103xx_hal_pwr_PwrExt;
 pub use rcc::RccExt as _stm32f103xx_hal_rcc_RccExt;
 pub use time::U32Ext as _stm32f103xx_hal_time_U32Ext;
@@ -3,14 +3,14 @@ use core::mem;
 
 use cast::{u16, u32};
 use hal;
-use stm32f103xx::{TIM2, TIM3, TIM4};
+use stm32f103xx::{TIM1, TIM2, TIM3, TIM4};
 
 use afio::MAPR;
 use bb;
-use gpio::gpioa::{PA0, PA1, PA2, PA3, PA6, PA7};
-use gpio::gpiob::{PB0, PB1, PB6, PB7, PB8, PB9};
-use gpio::{Alternate, PushPull};
-use rcc::{APB1, Clocks};
+use gpio::gpioa::{PA0, PA1, PA10, PA11, PA2, PA3, PA6, PA7, PA8, PA9};
+use gpio::gpiob::{PB0, PB1, PB12, PB13, PB14, PB15, PB6, PB7, PB8, PB9};
+use gpio::{Alternate, Floating, Input, PushPull};
+use rcc::{APB1, APB2, Clocks, RccPeripheral};
 use time::Hertz;
 
 pub trait Pins<TIM> {
@@ -22,6 +22,57 @@ pub trait Pins<TIM> {
     type Channels;
 }
 
+impl Pins<TIM1>
+    for (
+        PA8<Alternate<PushPull>>,
+        PA9<Alternate<PushPull>>,
+        PA10<Alternate<PushPull>>,
+        PA11<Alternate<PushPull>>,
+    ) {
+    const REMAP: u8 = 0b00;
+    const C1: bool = true;
+    const C2: bool = true;
+    const C3: bool = true;
+    const C4: bool = true;
+    type Channels = (Pwm<TIM1, C1>, Pwm<TIM1, C2>, Pwm<TIM1, C3>, Pwm<TIM1, C4>);
+}
+
+impl Pins<TIM1> for PA8<Alternate<PushPull>> {
+    const REMAP: u8 = 0b00;
+    const C1: bool = true;
+    const C2: bool = false;
+    const C3: bool = false;
+    const C4: bool = false;
+    type Channels = Pwm<TIM1, C1>;
+}
+
+impl Pins<TIM1> for (PA8<Alternate<PushPull>>, PB13<Alternate<PushPull>>) {
+    const REMAP: u8 = 0b00;
+    const C1: bool = true;
+    const C2: bool = false;
+    const C3: bool = false;
+    const C4: bool = false;
+    type Channels = ComplementaryPwm<TIM1, C1>;
+}
+
+impl Pins<TIM1>
+    for (
+        (PA8<Alternate<PushPull>>, PB13<Alternate<PushPull>>),
+        (PA9<Alternate<PushPull>>, PB14<Alternate<PushPull>>),
+        (PA10<Alternate<PushPull>>, PB15<Alternate<PushPull>>),
+    ) {
+    const REMAP: u8 = 0b00;
+    const C1: bool = true;
+    const C2: bool = true;
+    const C3: bool = true;
+    const C4: bool = false;
+    type Channels = (
+        ComplementaryPwm<TIM1, C1>,
+        ComplementaryPwm<TIM1, C2>,
+        ComplementaryPwm<TIM1, C3>,
+    );
+}
+
 impl Pins<TIM2>
     for (
         PA0<Alternate<PushPull>>,
@@ -85,21 +136,55 @@ impl Pins<TIM4>
     type Channels = (Pwm<TIM4, C1>, Pwm<TIM4, C2>, Pwm<TIM4, C3>, Pwm<TIM4, C4>);
 }
 
+/// Extension trait to configure a timer for PWM generation
 pub trait PwmExt: Sized {
+    /// Peripheral bus the timer is connected to
+    type Apb;
+
+    /// Configures the timer to generate PWM signals of frequency `frequency` on `PINS` and
+    /// returns one `PwmPin` per channel
     fn pwm<PINS, T>(
         self,
         PINS,
         mapr: &mut MAPR,
         frequency: T,
         clocks: Clocks,
-        apb: &mut APB1,
+        apb: &mut Self::Apb,
     ) -> PINS::Channels
     where
         PINS: Pins<Self>,
         T: Into<Hertz>;
 }
 
+impl PwmExt for TIM1 {
+    type Apb = APB2;
+
+    fn pwm<PINS, T>(
+        self,
+        _pins: PINS,
+        mapr: &mut MAPR,
+        freq: T,
+        clocks: Clocks,
+        apb: &mut APB2,
+    ) -> PINS::Channels
+    where
+        PINS: Pins<Self>,
+        T: Into<Hertz>,
+    {
+        mapr.modify_mapr(|_, w| unsafe { w.tim1_remap().bits(PINS::REMAP) });
+
+        let channels = tim1(self, _pins, freq.into(), clocks, apb);
+
+        // NOTE the outputs of the advanced timer stay disabled until MOE is set
+        unsafe { (*TIM1::ptr()).bdtr.modify(|_, w| w.moe().set_bit()) }
+
+        channels
+    }
+}
+
 impl PwmExt for TIM2 {
+    type Apb = APB1;
+
     fn pwm<PINS, T>(
         self,
         _pins: PINS,
@@ -112,14 +197,15 @@ impl PwmExt for TIM2 {
         PINS: Pins<Self>,
         T: Into<Hertz>,
     {
-        mapr.mapr()
-            .modify(|_, w| unsafe { w.tim2_remap().bits(PINS::REMAP) });
+        mapr.modify_mapr(|_, w| unsafe { w.tim2_remap().bits(PINS::REMAP) });
 
         tim2(self, _pins, freq.into(), clocks, apb)
     }
 }
 
 impl PwmExt for TIM3 {
+    type Apb = APB1;
+
     fn pwm<PINS, T>(
         self,
         _pins: PINS,
@@ -132,14 +218,15 @@ impl PwmExt for TIM3 {
         PINS: Pins<Self>,
         T: Into<Hertz>,
     {
-        mapr.mapr()
-            .modify(|_, w| unsafe { w.tim3_remap().bits(PINS::REMAP) });
+        mapr.modify_mapr(|_, w| unsafe { w.tim3_remap().bits(PINS::REMAP) });
 
         tim3(self, _pins, freq.into(), clocks, apb)
     }
 }
 
 impl PwmExt for TIM4 {
+    type Apb = APB1;
+
     fn pwm<PINS, T>(
         self,
         _pins: PINS,
@@ -152,39 +239,155 @@ impl PwmExt for TIM4 {
         PINS: Pins<Self>,
         T: Into<Hertz>,
     {
-        mapr.mapr()
-            .modify(|_, w| w.tim4_remap().bit(PINS::REMAP == 1));
+        mapr.modify_mapr(|_, w| w.tim4_remap().bit(PINS::REMAP == 1));
 
         tim4(self, _pins, freq.into(), clocks, apb)
     }
 }
 
+/// PWM channel `CHANNEL` of timer `TIM`
 pub struct Pwm<TIM, CHANNEL> {
     _channel: PhantomData<CHANNEL>,
     _tim: PhantomData<TIM>,
 }
 
+/// PWM channel `CHANNEL` of the advanced timer `TIM` driving a pair of complementary outputs
+///
+/// Enabling / disabling the channel enables / disables both outputs
+pub struct ComplementaryPwm<TIM, CHANNEL> {
+    _channel: PhantomData<CHANNEL>,
+    _tim: PhantomData<TIM>,
+}
+
 pub struct C1;
 pub struct C2;
 pub struct C3;
 pub struct C4;
 
+/// Level of an output of the advanced timer while its outputs are disabled (MOE cleared)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IdleState {
+    /// The output is driven low
+    Low,
+    /// The output is driven high
+    High,
+}
+
+/// Level of the break input that signals a fault
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BreakPolarity {
+    /// The break is active while the pin is low
+    ActiveLow,
+    /// The break is active while the pin is high
+    ActiveHigh,
+}
+
+/// Break input (BKIN) of the advanced timer TIM1
+///
+/// When the break input becomes active the hardware immediately clears MOE, which forces all the
+/// TIM1 outputs to their idle level (low unless changed with `ComplementaryPwm::set_idle_state`),
+/// and raises the `TIM1_BRK` interrupt if listened for.
+/// The outputs stay in that state until `rearm` is called
+pub struct Break {
+    pin: PB12<Input<Floating>>,
+}
+
+impl Break {
+    /// Enables the break input on PB12
+    ///
+    /// Use this after configuring TIM1 with `PwmExt::pwm`
+    pub fn tim1(pin: PB12<Input<Floating>>, polarity: BreakPolarity) -> Self {
+        // NOTE(OSSI, OSSR) drive the outputs to their idle level rather than leaving them
+        // floating when MOE is cleared
+        unsafe {
+            (*TIM1::ptr()).bdtr.modify(|_, w| {
+                w.ossi()
+                    .set_bit()
+                    .ossr()
+                    .set_bit()
+                    .bkp()
+                    .bit(polarity == BreakPolarity::ActiveHigh)
+                    .bke()
+                    .set_bit()
+            })
+        }
+
+        Break { pin }
+    }
+
+    /// Starts listening for the break event (`TIM1_BRK` interrupt)
+    pub fn listen(&mut self) {
+        unsafe { bb::set(&(*TIM1::ptr()).dier, 7) }
+    }
+
+    /// Stops listening for the break event
+    pub fn unlisten(&mut self) {
+        unsafe { bb::clear(&(*TIM1::ptr()).dier, 7) }
+    }
+
+    /// Returns `true` if a break event has occurred
+    pub fn is_pending(&self) -> bool {
+        unsafe { (*TIM1::ptr()).sr.read().bif().bit_is_set() }
+    }
+
+    /// Clears the break event flag; call this from the interrupt handler
+    pub fn clear_pending(&mut self) {
+        unsafe { (*TIM1::ptr()).sr.modify(|_, w| w.bif().clear_bit()) }
+    }
+
+    /// Re-enables the outputs after a break
+    ///
+    /// NOTE this has no effect while the break input is still active
+    pub fn rearm(&mut self) {
+        self.clear_pending();
+
+        unsafe { (*TIM1::ptr()).bdtr.modify(|_, w| w.moe().set_bit()) }
+    }
+
+    /// Disables the break input and releases the pin
+    pub fn free(self) -> PB12<Input<Floating>> {
+        unsafe { (*TIM1::ptr()).bdtr.modify(|_, w| w.bke().clear_bit()) }
+
+        self.pin
+    }
+}
+
+/// Returns the DTG field of the BDTR register that inserts at least `ticks` timer clock cycles of
+/// dead time
+fn dead_time_bits(ticks: u32) -> u8 {
+    // NOTE see the description of the BDTR register in the reference manual (RM0008)
+    if ticks < 128 {
+        ticks as u8
+    } else if ticks <= 254 {
+        0b1000_0000 | ((ticks + 1) / 2 - 64) as u8
+    } else if ticks <= 504 {
+        0b1100_0000 | ((ticks + 7) / 8 - 32) as u8
+    } else {
+        assert!(ticks <= 1008);
+
+        0b1110_0000 | ((ticks + 15) / 16 - 32) as u8
+    }
+}
+
 macro_rules! hal {
-    ($($TIMX:ident: ($timX:ident, $timXen:ident, $timXrst:ident),)+) => {
+    ($($TIMX:ident: (
+        $timX:ident,
+        $APB:ident,
+        $pclk_timX:ident
+    ),)+) => {
         $(
             fn $timX<PINS>(
                 tim: $TIMX,
                 _pins: PINS,
                 freq: Hertz,
                 clocks: Clocks,
-                apb: &mut APB1,
+                apb: &mut $APB,
             ) -> PINS::Channels
             where
                 PINS: Pins<$TIMX>,
             {
-                apb.enr().modify(|_, w| w.$timXen().enabled());
-                apb.rstr().modify(|_, w| w.$timXrst().set_bit());
-                apb.rstr().modify(|_, w| w.$timXrst().clear_bit());
+                $TIMX::enable(apb);
+                $TIMX::reset(apb);
 
                 if PINS::C1 {
                     tim.ccmr1_output
@@ -206,7 +409,7 @@ macro_rules! hal {
                         .modify(|_, w| w.oc4pe().set_bit().oc4m().pwm1());
                 }
 
-                let clk = clocks.pclk1().0 * if clocks.ppre1() == 1 { 1 } else { 2 };
+                let clk = clocks.$pclk_timX().0;
                 let freq = freq.0;
                 let ticks = clk / freq;
                 let psc = u16(ticks / (1 << 16)).unwrap();
@@ -214,6 +417,10 @@ macro_rules! hal {
                 let arr = u16(ticks / u32(psc + 1)).unwrap();
                 tim.arr.write(|w| w.arr().bits(arr));
 
+                // NOTE PSC is buffered; generate an update event so the prescaler (and the
+                // preloaded duty cycles) are loaded before the counter starts
+                tim.egr.write(|w| w.ug().set_bit());
+
                 tim.cr1.write(|w| unsafe {
                     w.cms()
                         .bits(0b00)
@@ -328,7 +535,92 @@ macro_rules! hal {
 }
 
 hal! {
-    TIM2: (tim2, tim2en, tim2rst),
-    TIM3: (tim3, tim3en, tim3rst),
-    TIM4: (tim4, tim4en, tim4rst),
+    TIM1: (tim1, APB2, pclk2_tim),
+    TIM2: (tim2, APB1, pclk1_tim),
+    TIM3: (tim3, APB1, pclk1_tim),
+    TIM4: (tim4, APB1, pclk1_tim),
+}
+
+macro_rules! complementary {
+    ($($CX:ident: ($ccrX:ident, $e:expr, $ne:expr, $ois:expr, $oisn:expr),)+) => {
+        $(
+            impl ComplementaryPwm<TIM1, $CX> {
+                /// Sets the dead time inserted between the falling edge of one output and the
+                /// rising edge of the other, to at least `dead_time_ns` nanoseconds
+                ///
+                /// NOTE this setting is shared by all the channels of the timer
+                pub fn set_dead_time(&mut self, dead_time_ns: u32, clocks: Clocks) {
+                    let clk = clocks.pclk2_tim().0;
+                    let ticks = (u64::from(dead_time_ns) * u64::from(clk) + 999_999_999)
+                        / 1_000_000_000;
+                    let dtg = dead_time_bits(u32(ticks).unwrap());
+
+                    unsafe { (*TIM1::ptr()).bdtr.modify(|_, w| w.dtg().bits(dtg)) }
+                }
+
+                /// Sets the levels the output (`output`) and the complementary output
+                /// (`complementary`) take when the timer outputs are disabled, e.g. after a break
+                ///
+                /// NOTE check these against the polarity of the gate driver inputs: idle levels
+                /// that turn on both switches of a half-bridge short the supply
+                pub fn set_idle_state(&mut self, output: IdleState, complementary: IdleState) {
+                    unsafe {
+                        let tim1 = &*TIM1::ptr();
+
+                        if output == IdleState::High {
+                            bb::set(&tim1.cr2, $ois);
+                        } else {
+                            bb::clear(&tim1.cr2, $ois);
+                        }
+
+                        if complementary == IdleState::High {
+                            bb::set(&tim1.cr2, $oisn);
+                        } else {
+                            bb::clear(&tim1.cr2, $oisn);
+                        }
+
+                        // NOTE(OSSI) without it the disabled outputs would float rather than
+                        // being driven to their idle levels
+                        tim1.bdtr.modify(|_, w| w.ossi().set_bit());
+                    }
+                }
+            }
+
+            impl hal::PwmPin for ComplementaryPwm<TIM1, $CX> {
+                type Duty = u16;
+
+                fn disable(&mut self) {
+                    unsafe {
+                        bb::clear(&(*TIM1::ptr()).ccer, $e);
+                        bb::clear(&(*TIM1::ptr()).ccer, $ne);
+                    }
+                }
+
+                fn enable(&mut self) {
+                    unsafe {
+                        bb::set(&(*TIM1::ptr()).ccer, $e);
+                        bb::set(&(*TIM1::ptr()).ccer, $ne);
+                    }
+                }
+
+                fn get_duty(&self) -> u16 {
+                    unsafe { (*TIM1::ptr()).$ccrX.read().$ccrX().bits() }
+                }
+
+                fn get_max_duty(&self) -> u16 {
+                    unsafe { (*TIM1::ptr()).arr.read().arr().bits() }
+                }
+
+                fn set_duty(&mut self, duty: u16) {
+                    unsafe { (*TIM1::ptr()).$ccrX.write(|w| w.$ccrX().bits(duty)) }
+                }
+            }
+        )+
+    }
+}
+
+complementary! {
+    C1: (ccr1, 0, 2, 8, 9),
+    C2: (ccr2, 4, 6, 10, 11),
+    C3: (ccr3, 8, 10, 12, 13),
 }
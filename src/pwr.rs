@@ -0,0 +1,149 @@
+//! Power control
+//!
+//! # Low power modes
+//!
+//! - Sleep: only the core clock is stopped; any interrupt wakes the device up
+//! - Stop: all clocks in the 1.8 V domain are stopped; any EXTI line (e.g. a pin configured with
+//!   `enable_interrupt` or the RTC alarm, see `Rtc::listen_alarm`) wakes the device up
+//! - Standby: the 1.8 V domain is powered off; only the WKUP pin (PA0), the RTC alarm, NRST or the
+//!   IWDG wake the device up, which then starts over from reset
+
+use cortex_m::asm;
+use cortex_m::peripheral::SCB;
+use stm32f103xx::{pwr, PWR, RCC};
+
+use rcc::{APB1, RccPeripheral};
+
+/// SLEEPDEEP bit of the System Control Register
+const SCR_SLEEPDEEP: u32 = 1 << 2;
+
+/// Voltage regulator state while in Stop mode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Regulator {
+    /// Regulator on; faster wake up
+    On,
+    /// Regulator in low power mode; lower consumption but slower wake up
+    LowPower,
+}
+
+/// Extension trait that constrains the `PWR` peripheral
+pub trait PwrExt {
+    /// Enables the PWR clock
+    fn constrain(self, apb1: &mut APB1) -> Pwr;
+}
+
+impl PwrExt for PWR {
+    fn constrain(self, apb1: &mut APB1) -> Pwr {
+        PWR::enable(apb1);
+
+        Pwr { pwr: self }
+    }
+}
+
+/// Constrained PWR peripheral
+pub struct Pwr {
+    pwr: PWR,
+}
+
+impl Pwr {
+    /// Enters Sleep mode until an interrupt is triggered
+    pub fn sleep(&mut self, scb: &mut SCB) {
+        unsafe { scb.scr.modify(|r| r & !SCR_SLEEPDEEP) }
+
+        asm::wfi();
+    }
+
+    /// Enters Stop mode until an EXTI line is triggered
+    ///
+    /// On wake up the HSE and PLL are re-enabled and the system clock is switched back to the
+    /// source it had before entering Stop mode, so the frozen `Clocks` remain valid.
+    ///
+    /// NOTE the interrupt that wakes the device up is serviced *before* the clocks are restored,
+    /// i.e. while the device runs from the 8 MHz HSI
+    pub fn stop(&mut self, scb: &mut SCB, regulator: Regulator) {
+        self.pwr.cr.modify(|_, w| {
+            w.pdds()
+                .clear_bit()
+                .lpds()
+                .bit(regulator == Regulator::LowPower)
+                .cwuf()
+                .set_bit()
+        });
+
+        // NOTE(unsafe) we only restore the clock configuration that was frozen before
+        let rcc = unsafe { &*RCC::ptr() };
+        let cr = rcc.cr.read();
+        let sw = rcc.cfgr.read().sw().bits();
+
+        unsafe { scb.scr.modify(|r| r | SCR_SLEEPDEEP) }
+        asm::wfi();
+        unsafe { scb.scr.modify(|r| r & !SCR_SLEEPDEEP) }
+
+        if cr.hseon().bit_is_set() {
+            rcc.cr.modify(|_, w| w.hseon().enabled());
+            while rcc.cr.read().hserdy().bit_is_clear() {}
+        }
+
+        if cr.pllon().bit_is_set() {
+            rcc.cr.modify(|_, w| w.pllon().enabled());
+            while rcc.cr.read().pllrdy().is_unlocked() {}
+        }
+
+        rcc.cfgr.modify(|_, w| unsafe { w.sw().bits(sw) });
+        while rcc.cfgr.read().sws().bits() != sw {}
+    }
+
+    /// Enters Standby mode
+    ///
+    /// The device is reset when it wakes up so this function never returns
+    pub fn standby(&mut self, scb: &mut SCB) -> ! {
+        self.pwr
+            .cr
+            .modify(|_, w| w.pdds().set_bit().cwuf().set_bit());
+
+        unsafe { scb.scr.modify(|r| r | SCR_SLEEPDEEP) }
+
+        loop {
+            asm::wfi();
+        }
+    }
+
+    /// Enables waking up from Standby mode with a rising edge on the WKUP pin (PA0)
+    pub fn enable_wakeup_pin(&mut self) {
+        self.pwr.csr.modify(|_, w| w.ewup().set_bit());
+    }
+
+    /// Disables the WKUP pin
+    pub fn disable_wakeup_pin(&mut self) {
+        self.pwr.csr.modify(|_, w| w.ewup().clear_bit());
+    }
+
+    /// Returns `true` if the device has been woken up by a wake up event
+    pub fn is_woken_up(&self) -> bool {
+        self.pwr.csr.read().wuf().bit_is_set()
+    }
+
+    /// Returns `true` if the device has been reset because it left Standby mode
+    pub fn is_standby_reset(&self) -> bool {
+        self.pwr.csr.read().sbf().bit_is_set()
+    }
+
+    /// Clears the wake up flag
+    pub fn clear_wakeup_flag(&mut self) {
+        self.pwr.cr.modify(|_, w| w.cwuf().set_bit());
+    }
+
+    /// Clears the standby flag
+    pub fn clear_standby_flag(&mut self) {
+        self.pwr.cr.modify(|_, w| w.csbf().set_bit());
+    }
+
+    /// Releases the PWR peripheral
+    pub fn release(self) -> PWR {
+        self.pwr
+    }
+
+    pub(crate) fn cr(&mut self) -> &pwr::CR {
+        &self.pwr.cr
+    }
+}
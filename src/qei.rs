@@ -4,11 +4,12 @@ use hal::{self, Direction};
 use stm32f103xx::{TIM2, TIM3, TIM4};
 
 use afio::MAPR;
-use gpio::gpioa::{PA0, PA1, PA6, PA7};
-use gpio::gpiob::{PB6, PB7};
+use gpio::gpioa::{PA0, PA1, PA15, PA6, PA7};
+use gpio::gpiob::{PB3, PB4, PB5, PB6, PB7};
 use gpio::{Floating, Input};
-use rcc::APB1;
+use rcc::{APB1, RccPeripheral};
 
+/// Encoder inputs: `(TI1, TI2)`
 pub trait Pins<TIM> {
     const REMAP: u8;
 }
@@ -17,14 +18,24 @@ impl Pins<TIM2> for (PA0<Input<Floating>>, PA1<Input<Floating>>) {
     const REMAP: u8 = 0b00;
 }
 
+// NOTE PA15, PB3 and PB4 are JTAG pins after reset
+impl Pins<TIM2> for (PA15<Input<Floating>>, PB3<Input<Floating>>) {
+    const REMAP: u8 = 0b01;
+}
+
 impl Pins<TIM3> for (PA6<Input<Floating>>, PA7<Input<Floating>>) {
     const REMAP: u8 = 0b00;
 }
 
+impl Pins<TIM3> for (PB4<Input<Floating>>, PB5<Input<Floating>>) {
+    const REMAP: u8 = 0b10;
+}
+
 impl Pins<TIM4> for (PB6<Input<Floating>>, PB7<Input<Floating>>) {
     const REMAP: u8 = 0b00;
 }
 
+/// Quadrature encoder interface
 pub struct Qei<TIM, PINS> {
     tim: TIM,
     pins: PINS,
@@ -35,8 +46,7 @@ impl<PINS> Qei<TIM2, PINS> {
     where
         PINS: Pins<TIM2>,
     {
-        mapr.mapr()
-            .modify(|_, w| unsafe { w.tim2_remap().bits(PINS::REMAP) });
+        mapr.modify_mapr(|_, w| unsafe { w.tim2_remap().bits(PINS::REMAP) });
 
         Qei::_tim2(tim, pins, apb)
     }
@@ -47,8 +57,7 @@ impl<PINS> Qei<TIM3, PINS> {
         where
         PINS: Pins<TIM3>,
     {
-        mapr.mapr()
-            .modify(|_, w| unsafe { w.tim3_remap().bits(PINS::REMAP) });
+        mapr.modify_mapr(|_, w| unsafe { w.tim3_remap().bits(PINS::REMAP) });
 
         Qei::_tim3(tim, pins, apb)
     }
@@ -59,22 +68,20 @@ impl<PINS> Qei<TIM4, PINS> {
         where
         PINS: Pins<TIM4>,
     {
-        mapr.mapr()
-            .modify(|_, w| w.tim4_remap().bit(PINS::REMAP == 1));
+        mapr.modify_mapr(|_, w| w.tim4_remap().bit(PINS::REMAP == 1));
 
         Qei::_tim4(tim, pins, apb)
     }
 }
 
 macro_rules! hal {
-    ($($TIMX:ident: ($timX:ident, $timXen:ident, $timXrst:ident),)+) => {
+    ($($TIMX:ident: ($timX:ident),)+) => {
         $(
             impl<PINS> Qei<$TIMX, PINS> {
                 fn $timX(tim: $TIMX, pins: PINS, apb: &mut APB1) -> Self {
                     // enable and reset peripheral to a clean slate state
-                    apb.enr().modify(|_, w| w.$timXen().set_bit());
-                    apb.rstr().modify(|_, w| w.$timXrst().set_bit());
-                    apb.rstr().modify(|_, w| w.$timXrst().clear_bit());
+                    $TIMX::enable(apb);
+                    $TIMX::reset(apb);
 
                     // Configure TxC1 and TxC2 as captures
                     tim.ccmr1_output
@@ -127,7 +134,7 @@ macro_rules! hal {
 }
 
 hal! {
-    TIM2: (_tim2, tim2en, tim2rst),
-    TIM3: (_tim3, tim3en, tim3rst),
-    TIM4: (_tim4, tim4en, tim4rst),
+    TIM2: (_tim2),
+    TIM3: (_tim3),
+    TIM4: (_tim4),
 }
@@ -2,8 +2,12 @@ use core::cmp;
 
 use cast::u32;
 use stm32f103xx::{rcc, RCC};
+use stm32f103xx::{ADC1, ADC2, AFIO, GPIOA, GPIOB, GPIOC, SPI1, TIM1, USART1};
+use stm32f103xx::{BKP, CAN, DAC, I2C1, I2C2, PWR, SPI2, TIM2, TIM3, TIM4, USART2, USART3, WWDG};
 
-use flash::ACR;
+use flash::{Latency, ACR};
+use gpio::gpioa::PA8;
+use gpio::{Alternate, PushPull};
 use time::Hertz;
 
 /// Extension trait that constrains the `RCC` peripheral
@@ -19,7 +23,10 @@ impl RccExt for RCC {
             apb1: APB1 { _0: () },
             apb2: APB2 { _0: () },
             cfgr: CFGR {
+                adcclk: None,
                 hclk: None,
+                hse: None,
+                hse_bypass: false,
                 pclk1: None,
                 pclk2: None,
                 sysclk: None,
@@ -85,16 +92,98 @@ impl APB2 {
     }
 }
 
+/// Peripheral that's clocked by, and can be reset through, one of the APB buses
+pub trait RccPeripheral {
+    /// Bus the peripheral is connected to
+    type Bus;
+
+    /// Enables the clock of the peripheral
+    fn enable(bus: &mut Self::Bus);
+
+    /// Disables the clock of the peripheral
+    fn disable(bus: &mut Self::Bus);
+
+    /// Puts the registers of the peripheral back in their reset state
+    fn reset(bus: &mut Self::Bus);
+}
+
+macro_rules! rcc_peripheral {
+    ($($BUS:ident: [$($PER:ident: ($perXen:ident, $perXrst:ident),)+],)+) => {
+        $(
+            $(
+                impl RccPeripheral for $PER {
+                    type Bus = $BUS;
+
+                    fn enable(bus: &mut $BUS) {
+                        bus.enr().modify(|_, w| w.$perXen().set_bit());
+                    }
+
+                    fn disable(bus: &mut $BUS) {
+                        bus.enr().modify(|_, w| w.$perXen().clear_bit());
+                    }
+
+                    fn reset(bus: &mut $BUS) {
+                        bus.rstr().modify(|_, w| w.$perXrst().set_bit());
+                        bus.rstr().modify(|_, w| w.$perXrst().clear_bit());
+                    }
+                }
+            )+
+        )+
+    }
+}
+
+rcc_peripheral! {
+    APB1: [
+        BKP: (bkpen, bkprst),
+        CAN: (canen, canrst),
+        DAC: (dacen, dacrst),
+        I2C1: (i2c1en, i2c1rst),
+        I2C2: (i2c2en, i2c2rst),
+        PWR: (pwren, pwrrst),
+        SPI2: (spi2en, spi2rst),
+        TIM2: (tim2en, tim2rst),
+        TIM3: (tim3en, tim3rst),
+        TIM4: (tim4en, tim4rst),
+        USART2: (usart2en, usart2rst),
+        USART3: (usart3en, usart3rst),
+        WWDG: (wwdgen, wwdgrst),
+    ],
+    APB2: [
+        ADC1: (adc1en, adc1rst),
+        ADC2: (adc2en, adc2rst),
+        AFIO: (afioen, afiorst),
+        GPIOA: (iopaen, ioparst),
+        GPIOB: (iopben, iopbrst),
+        GPIOC: (iopcen, iopcrst),
+        SPI1: (spi1en, spi1rst),
+        TIM1: (tim1en, tim1rst),
+        USART1: (usart1en, usart1rst),
+    ],
+}
+
 const HSI: u32 = 8_000_000; // Hz
 
 pub struct CFGR {
+    adcclk: Option<u32>,
     hclk: Option<u32>,
+    hse: Option<u32>,
+    hse_bypass: bool,
     pclk1: Option<u32>,
     pclk2: Option<u32>,
     sysclk: Option<u32>,
 }
 
 impl CFGR {
+    /// Sets the maximum ADC clock frequency; the ADC prescaler (2, 4, 6 or 8) is picked so that
+    /// the ADC clock doesn't exceed this value nor 14 MHz
+    pub fn adcclk<F>(mut self, freq: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        self.adcclk = Some(freq.into().0);
+        self
+    }
+
     pub fn hclk<F>(mut self, freq: F) -> Self
     where
         F: Into<Hertz>,
@@ -103,6 +192,27 @@ impl CFGR {
         self
     }
 
+    /// Uses an external crystal of frequency `freq` (4 - 16 MHz) as the clock source
+    pub fn use_hse<F>(mut self, freq: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        self.hse = Some(freq.into().0);
+        self.hse_bypass = false;
+        self
+    }
+
+    /// Uses an external clock signal of frequency `freq` (up to 25 MHz), fed into OSC_IN, as the
+    /// clock source
+    pub fn use_hse_bypass<F>(mut self, freq: F) -> Self
+    where
+        F: Into<Hertz>,
+    {
+        self.hse = Some(freq.into().0);
+        self.hse_bypass = true;
+        self
+    }
+
     pub fn pclk1<F>(mut self, freq: F) -> Self
     where
         F: Into<Hertz>,
@@ -119,6 +229,9 @@ impl CFGR {
         self
     }
 
+    /// Sets the desired frequency of the system clock
+    ///
+    /// `try_freeze` fails if this frequency can't be produced exactly from the clock source
     pub fn sysclk<F>(mut self, freq: F) -> Self
     where
         F: Into<Hertz>,
@@ -127,20 +240,54 @@ impl CFGR {
         self
     }
 
+    /// Applies the clock configuration
+    ///
+    /// # Panics
+    ///
+    /// If the configuration is invalid; see `try_freeze`
     pub fn freeze(self, acr: &mut ACR) -> Clocks {
-        // TODO ADC & USB clocks
+        self.try_freeze(acr).unwrap()
+    }
+
+    /// Applies the clock configuration or returns an error, without touching the clock
+    /// configuration, if it's invalid
+    pub fn try_freeze(self, acr: &mut ACR) -> Result<Clocks, ClockError> {
+        if let Some(hse) = self.hse {
+            let valid = if self.hse_bypass {
+                hse > 0 && hse <= 25_000_000
+            } else {
+                hse >= 4_000_000 && hse <= 16_000_000
+            };
+
+            if !valid {
+                return Err(ClockError::HseOutOfRange);
+            }
+        }
+
+        // NOTE the PLL is fed either by HSE or by HSI / 2
+        let pllsrcclk = self.hse.unwrap_or(HSI / 2);
+        let srcclk = self.hse.unwrap_or(HSI);
 
-        let pllmul = (4 * self.sysclk.unwrap_or(HSI) + HSI) / HSI / 2;
-        let pllmul = cmp::min(cmp::max(pllmul, 2), 16);
-        let pllmul_bits = if pllmul == 2 {
+        let pllmul = (2 * self.sysclk.unwrap_or(srcclk) + pllsrcclk) / pllsrcclk / 2;
+        let pllmul = cmp::min(cmp::max(pllmul, 1), 16);
+        let pllmul_bits = if pllmul * pllsrcclk <= srcclk {
             None
         } else {
-            Some(pllmul as u8 - 2)
+            Some(cmp::max(pllmul, 2) as u8 - 2)
+        };
+
+        let sysclk = match pllmul_bits {
+            Some(bits) => u32(bits + 2) * pllsrcclk,
+            None => srcclk,
         };
 
-        let sysclk = pllmul * HSI / 2;
+        if sysclk > 72_000_000 || self.sysclk.map(|req| req != sysclk) == Some(true) {
+            return Err(ClockError::SysclkUnreachable);
+        }
 
-        assert!(sysclk < 72_000_000);
+        if self.hclk.map(|hclk| hclk == 0 || hclk > sysclk) == Some(true) {
+            return Err(ClockError::InvalidPrescaler);
+        }
 
         let hpre_bits = self.hclk
             .map(|hclk| match sysclk / hclk {
@@ -159,7 +306,15 @@ impl CFGR {
 
         let hclk = sysclk / (1 << (hpre_bits - 0b0111));
 
-        assert!(hclk < 72_000_000);
+        if hclk > 72_000_000 {
+            return Err(ClockError::HclkOverspeed);
+        }
+
+        if self.pclk1.map(|pclk1| pclk1 == 0 || pclk1 > hclk) == Some(true)
+            || self.pclk2.map(|pclk2| pclk2 == 0 || pclk2 > hclk) == Some(true)
+        {
+            return Err(ClockError::InvalidPrescaler);
+        }
 
         let ppre1_bits = self.pclk1
             .map(|pclk1| match hclk / pclk1 {
@@ -175,7 +330,9 @@ impl CFGR {
         let ppre1 = 1 << (ppre1_bits - 0b011);
         let pclk1 = hclk / u32(ppre1);
 
-        assert!(pclk1 < 36_000_000);
+        if pclk1 > 36_000_000 {
+            return Err(ClockError::Pclk1Overspeed);
+        }
 
         let ppre2_bits = self.pclk2
             .map(|pclk2| match hclk / pclk2 {
@@ -191,33 +348,72 @@ impl CFGR {
         let ppre2 = 1 << (ppre2_bits - 0b011);
         let pclk2 = hclk / u32(ppre2);
 
-        assert!(pclk2 < 72_000_000);
+        if pclk2 > 72_000_000 {
+            return Err(ClockError::Pclk2Overspeed);
+        }
 
-        // adjust flash wait states
-        unsafe {
-            acr.acr().write(|w| {
-                w.latency().bits(if sysclk <= 24_000_000 {
-                    0b000
-                } else if sysclk <= 48_000_000 {
-                    0b001
-                } else {
-                    0b010
-                })
-            })
+        // NOTE the ADC clock must not exceed 14 MHz; use the smallest divider that satisfies this
+        let max_adcclk = cmp::min(self.adcclk.unwrap_or(14_000_000), 14_000_000);
+
+        if max_adcclk == 0 {
+            return Err(ClockError::InvalidPrescaler);
         }
 
+        let (adcpre_bits, adcpre) = match (pclk2 + max_adcclk - 1) / max_adcclk {
+            0...2 => (0b00, 2),
+            3...4 => (0b01, 4),
+            5...6 => (0b10, 6),
+            _ => (0b11, 8),
+        };
+
+        let adcclk = pclk2 / adcpre;
+
+        if adcclk > max_adcclk {
+            return Err(ClockError::AdcclkUnreachable);
+        }
+
+        // NOTE USB needs exactly 48 MHz, derived from PLL / 1 or PLL / 1.5; the HSI isn't
+        // accurate enough for USB full speed so the PLL must be fed by the HSE
+        let usb_pll = pllmul_bits.is_some() && self.hse.is_some();
+        let (usbpre_bits, usbclk_valid) = match sysclk {
+            48_000_000 if usb_pll => (true, true),
+            72_000_000 if usb_pll => (false, true),
+            _ => (false, false),
+        };
+
+        // adjust flash wait states
+        acr.set_latency(Latency::for_sysclk(sysclk));
+
         let rcc = unsafe { &*RCC::ptr() };
+
+        if self.hse.is_some() {
+            // NOTE HSEBYP can only be written while HSE is disabled
+            rcc.cr.modify(|_, w| w.hsebyp().bit(self.hse_bypass));
+            rcc.cr.modify(|_, w| w.hseon().enabled());
+
+            while rcc.cr.read().hserdy().bit_is_clear() {}
+        }
+
         if let Some(pllmul_bits) = pllmul_bits {
             // use PLL as source
 
-            rcc.cfgr.write(|w| unsafe { w.pllmul().bits(pllmul_bits) });
+            rcc.cfgr.write(|w| unsafe {
+                w.pllmul()
+                    .bits(pllmul_bits)
+                    .pllsrc()
+                    .bit(self.hse.is_some())
+                    .usbpre()
+                    .bit(usbpre_bits)
+            });
 
-            rcc.cr.write(|w| w.pllon().enabled());
+            rcc.cr.modify(|_, w| w.pllon().enabled());
 
             while rcc.cr.read().pllrdy().is_unlocked() {}
 
             rcc.cfgr.modify(|_, w| unsafe {
-                w.ppre2()
+                w.adcpre()
+                    .bits(adcpre_bits)
+                    .ppre2()
                     .bits(ppre2_bits)
                     .ppre1()
                     .bits(ppre1_bits)
@@ -226,11 +422,28 @@ impl CFGR {
                     .sw()
                     .pll()
             });
+        } else if self.hse.is_some() {
+            // use HSE as source
+
+            rcc.cfgr.write(|w| unsafe {
+                w.adcpre()
+                    .bits(adcpre_bits)
+                    .ppre2()
+                    .bits(ppre2_bits)
+                    .ppre1()
+                    .bits(ppre1_bits)
+                    .hpre()
+                    .bits(hpre_bits)
+                    .sw()
+                    .hse()
+            });
         } else {
             // use HSI as source
 
             rcc.cfgr.write(|w| unsafe {
-                w.ppre2()
+                w.adcpre()
+                    .bits(adcpre_bits)
+                    .ppre2()
                     .bits(ppre2_bits)
                     .ppre1()
                     .bits(ppre1_bits)
@@ -241,31 +454,64 @@ impl CFGR {
             });
         }
 
-        Clocks {
+        Ok(Clocks {
+            adcclk: Hertz(adcclk),
             hclk: Hertz(hclk),
+            hse: self.hse.map(Hertz),
             pclk1: Hertz(pclk1),
             pclk2: Hertz(pclk2),
             ppre1,
             ppre2,
+            pllclk: pllmul_bits.map(|_| Hertz(sysclk)),
             sysclk: Hertz(sysclk),
-        }
+            usbclk_valid,
+        })
     }
 }
 
+/// Clock configuration error
+#[derive(Debug)]
+pub enum ClockError {
+    /// The HSE frequency is outside of the supported range
+    HseOutOfRange,
+    /// The requested SYSCLK can't be produced exactly from the clock source, or exceeds 72 MHz
+    SysclkUnreachable,
+    /// A requested bus frequency is zero or higher than the frequency of its parent clock
+    InvalidPrescaler,
+    /// HCLK would exceed 72 MHz
+    HclkOverspeed,
+    /// PCLK1 would exceed 36 MHz
+    Pclk1Overspeed,
+    /// PCLK2 would exceed 72 MHz
+    Pclk2Overspeed,
+    /// The requested ADC clock is lower than PCLK2 / 8
+    AdcclkUnreachable,
+    #[doc(hidden)] _Extensible,
+}
+
 /// Frozen clock frequencies
 ///
 /// The existence of this value indicates that the clock configuration can no longer be changed
 #[derive(Clone, Copy)]
 pub struct Clocks {
+    adcclk: Hertz,
     hclk: Hertz,
+    hse: Option<Hertz>,
     pclk1: Hertz,
     pclk2: Hertz,
     ppre1: u8,
     ppre2: u8,
+    pllclk: Option<Hertz>,
     sysclk: Hertz,
+    usbclk_valid: bool,
 }
 
 impl Clocks {
+    /// Returns the frequency of the ADC clock
+    pub fn adcclk(&self) -> Hertz {
+        self.adcclk
+    }
+
     /// Returns the frequency of the AHB
     pub fn hclk(&self) -> Hertz {
         self.hclk
@@ -281,18 +527,101 @@ impl Clocks {
         self.pclk2
     }
 
-    pub(crate) fn ppre1(&self) -> u8 {
-        self.ppre1
+    /// Returns the frequency of the clock that drives the timers on the APB1 (TIM2 to TIM4)
+    ///
+    /// NOTE this is twice the APB1 frequency when the APB1 prescaler is not 1
+    pub fn pclk1_tim(&self) -> Hertz {
+        Hertz(self.pclk1.0 * if self.ppre1 == 1 { 1 } else { 2 })
+    }
+
+    /// Returns the frequency of the clock that drives the timers on the APB2 (TIM1)
+    ///
+    /// NOTE this is twice the APB2 frequency when the APB2 prescaler is not 1
+    pub fn pclk2_tim(&self) -> Hertz {
+        Hertz(self.pclk2.0 * if self.ppre2 == 1 { 1 } else { 2 })
+    }
+
+    /// Returns the frequency of the external oscillator, or `None` if it's not used
+    pub fn hse(&self) -> Option<Hertz> {
+        self.hse
     }
 
-    // TODO remove `allow`
-    #[allow(dead_code)]
-    pub(crate) fn ppre2(&self) -> u8 {
-        self.ppre2
+    /// Returns the frequency of the PLL output, or `None` if the PLL is not used
+    ///
+    /// NOTE the PLL, when used, always drives SYSCLK
+    pub fn pllclk(&self) -> Option<Hertz> {
+        self.pllclk
     }
 
     /// Returns the system (core) frequency
     pub fn sysclk(&self) -> Hertz {
         self.sysclk
     }
+
+    /// Returns `true` if the USB peripheral is clocked at the required 48 MHz, derived from the HSE
+    pub fn usbclk_valid(&self) -> bool {
+        self.usbclk_valid
+    }
+}
+
+/// Clock source routed to the MCO pin (PA8)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum McoSource {
+    /// System clock
+    Sysclk,
+    /// Internal 8 MHz RC oscillator
+    Hsi,
+    /// External oscillator
+    Hse,
+    /// PLL clock divided by 2
+    PllDiv2,
+}
+
+/// Microcontroller clock output
+pub struct Mco {
+    pin: PA8<Alternate<PushPull>>,
+}
+
+impl Mco {
+    /// Outputs the clock `source` on PA8
+    ///
+    /// NOTE the I/O pins can't toggle faster than 50 MHz
+    ///
+    /// # Panics
+    ///
+    /// If `source` is not running
+    pub fn new(pin: PA8<Alternate<PushPull>>, source: McoSource, _clocks: Clocks) -> Self {
+        // NOTE(unsafe) `Clocks` proves the clock configuration has been frozen so we are the only
+        // ones touching CFGR
+        let rcc = unsafe { &*RCC::ptr() };
+        let cr = rcc.cr.read();
+
+        let bits = match source {
+            McoSource::Sysclk => 0b100,
+            McoSource::Hsi => {
+                assert!(cr.hsirdy().bit_is_set());
+                0b101
+            }
+            McoSource::Hse => {
+                assert!(cr.hserdy().bit_is_set());
+                0b110
+            }
+            McoSource::PllDiv2 => {
+                assert!(cr.pllrdy().is_locked());
+                0b111
+            }
+        };
+
+        rcc.cfgr.modify(|_, w| unsafe { w.mco().bits(bits) });
+
+        Mco { pin }
+    }
+
+    /// Stops the clock output and releases the pin
+    pub fn release(self) -> PA8<Alternate<PushPull>> {
+        // NOTE(unsafe) see `new`
+        unsafe { (*RCC::ptr()).cfgr.modify(|_, w| w.mco().bits(0b000)) }
+
+        self.pin
+    }
 }
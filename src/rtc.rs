@@ -0,0 +1,238 @@
+//! Real time clock
+//!
+//! The RTC is a 32-bit counter in the backup domain; once configured it keeps counting across
+//! resets and, as long as VBAT is powered, across power loss as well.
+
+use stm32f103xx::{EXTI, RCC, RTC};
+
+use backup_domain::BackupDomain;
+
+/// LSE (external 32.768 kHz crystal) frequency, in Hz
+const LSE: u32 = 32_768;
+
+/// EXTI line the alarm event is connected to
+const ALARM_EXTI_LINE: u32 = 17;
+
+/// LSI frequency, in Hz
+///
+/// NOTE the LSI is not trimmed; the datasheet only guarantees 30 - 60 kHz
+const LSI: u32 = 40_000;
+
+/// RTC clock source
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockSource {
+    /// External 32.768 kHz crystal
+    Lse,
+    /// Internal ~40 kHz RC oscillator
+    Lsi,
+}
+
+/// Interrupt event
+pub enum Event {
+    /// The counter has been incremented
+    Second,
+    /// The counter has reached the alarm value
+    Alarm,
+    /// The counter has overflowed
+    Overflow,
+}
+
+/// Real time clock
+pub struct Rtc {
+    rtc: RTC,
+}
+
+impl Rtc {
+    /// Configures the RTC to count seconds using the given clock `source`
+    ///
+    /// If the RTC is already running from `source` (e.g. after a reset) it's left running and
+    /// only the prescaler is reprogrammed, so the counter is preserved.
+    ///
+    /// NOTE switching to a different clock source requires resetting the backup domain, which
+    /// also clears the backup data registers
+    pub fn new(rtc: RTC, backup_domain: &mut BackupDomain, source: ClockSource) -> Self {
+        let bdcr = backup_domain.bdcr();
+        let r = bdcr.read();
+
+        let selected = match source {
+            ClockSource::Lse => r.rtcsel().is_lse(),
+            ClockSource::Lsi => r.rtcsel().is_lsi(),
+        };
+
+        if !selected || r.rtcen().bit_is_clear() {
+            if !r.rtcsel().is_no_clk() && !selected {
+                // NOTE RTCSEL can only be changed after a backup domain reset
+                bdcr.modify(|_, w| w.bdrst().set_bit());
+                bdcr.modify(|_, w| w.bdrst().clear_bit());
+            }
+
+            match source {
+                ClockSource::Lse => {
+                    bdcr.modify(|_, w| w.lseon().set_bit());
+                    while bdcr.read().lserdy().bit_is_clear() {}
+
+                    bdcr.modify(|_, w| w.rtcsel().lse());
+                }
+                ClockSource::Lsi => {
+                    // NOTE(unsafe) LSION is not touched by any other abstraction
+                    let rcc = unsafe { &*RCC::ptr() };
+                    rcc.csr.modify(|_, w| w.lsion().set_bit());
+                    while rcc.csr.read().lsirdy().bit_is_clear() {}
+
+                    bdcr.modify(|_, w| w.rtcsel().lsi());
+                }
+            }
+
+            bdcr.modify(|_, w| w.rtcen().enabled());
+        }
+
+        let mut rtc = Rtc { rtc };
+
+        // wait until the APB1 interface is synchronized with the RTC
+        rtc.rtc.crl.modify(|_, w| w.rsf().clear_bit());
+        while rtc.rtc.crl.read().rsf().bit_is_clear() {}
+
+        let prescaler = match source {
+            ClockSource::Lse => LSE,
+            ClockSource::Lsi => LSI,
+        } - 1;
+
+        rtc.configure(|rtc| unsafe {
+            rtc.prlh.write(|w| w.prlh().bits((prescaler >> 16) as u8));
+            rtc.prll.write(|w| w.prll().bits(prescaler as u16));
+        });
+
+        rtc
+    }
+
+    /// Returns the current value of the counter
+    pub fn get_cnt(&self) -> u32 {
+        // NOTE the two halves are not read atomically; retry if the high half changed in between
+        loop {
+            let high = self.rtc.cnth.read().bits();
+            let low = self.rtc.cntl.read().bits();
+
+            if self.rtc.cnth.read().bits() == high {
+                return high << 16 | low;
+            }
+        }
+    }
+
+    /// Sets the counter to `cnt`
+    pub fn set_cnt(&mut self, cnt: u32) {
+        self.configure(|rtc| unsafe {
+            rtc.cnth.write(|w| w.cnth().bits((cnt >> 16) as u16));
+            rtc.cntl.write(|w| w.cntl().bits(cnt as u16));
+        });
+    }
+
+    /// Sets the alarm to fire when the counter reaches `alarm`
+    pub fn set_alarm(&mut self, alarm: u32) {
+        self.configure(|rtc| unsafe {
+            rtc.alrh.write(|w| w.alrh().bits((alarm >> 16) as u16));
+            rtc.alrl.write(|w| w.alrl().bits(alarm as u16));
+        });
+    }
+
+    /// Enables the alarm interrupt and routes the alarm event to EXTI line 17
+    ///
+    /// The EXTI line is required to wake the device from Stop mode; the `RTCALARM` interrupt is
+    /// triggered through it
+    pub fn listen_alarm(&mut self, exti: &mut EXTI) {
+        exti.rtsr
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << ALARM_EXTI_LINE)) });
+        exti.imr
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << ALARM_EXTI_LINE)) });
+
+        self.listen(Event::Alarm);
+    }
+
+    /// Disables the alarm interrupt and masks EXTI line 17
+    pub fn unlisten_alarm(&mut self, exti: &mut EXTI) {
+        self.unlisten(Event::Alarm);
+
+        exti.imr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << ALARM_EXTI_LINE)) });
+    }
+
+    /// Clears the alarm flag and the pending bit of EXTI line 17
+    pub fn clear_alarm_flag(&mut self) {
+        self.clear_pending(Event::Alarm);
+
+        // NOTE(unsafe) atomic write to a stateless register
+        unsafe { (*EXTI::ptr()).pr.write(|w| w.bits(1 << ALARM_EXTI_LINE)) }
+    }
+
+    /// Starts listening for an interrupt `event`
+    ///
+    /// NOTE use `listen_alarm` to wake the device from Stop mode with the alarm
+    pub fn listen(&mut self, event: Event) {
+        self.wait_write();
+
+        match event {
+            Event::Second => self.rtc.crh.modify(|_, w| w.secie().set_bit()),
+            Event::Alarm => self.rtc.crh.modify(|_, w| w.alrie().set_bit()),
+            Event::Overflow => self.rtc.crh.modify(|_, w| w.owie().set_bit()),
+        }
+    }
+
+    /// Stops listening for an interrupt `event`
+    pub fn unlisten(&mut self, event: Event) {
+        self.wait_write();
+
+        match event {
+            Event::Second => self.rtc.crh.modify(|_, w| w.secie().clear_bit()),
+            Event::Alarm => self.rtc.crh.modify(|_, w| w.alrie().clear_bit()),
+            Event::Overflow => self.rtc.crh.modify(|_, w| w.owie().clear_bit()),
+        }
+    }
+
+    /// Returns `true` if the flag of `event` is set
+    pub fn is_pending(&self, event: Event) -> bool {
+        let crl = self.rtc.crl.read();
+
+        match event {
+            Event::Second => crl.secf().bit_is_set(),
+            Event::Alarm => crl.alrf().bit_is_set(),
+            Event::Overflow => crl.owf().bit_is_set(),
+        }
+    }
+
+    /// Clears the flag of `event`
+    pub fn clear_pending(&mut self, event: Event) {
+        self.wait_write();
+
+        // NOTE these flags are cleared by writing 0; writing 1 to the others has no effect
+        match event {
+            Event::Second => self.rtc.crl.modify(|_, w| w.secf().clear_bit()),
+            Event::Alarm => self.rtc.crl.modify(|_, w| w.alrf().clear_bit()),
+            Event::Overflow => self.rtc.crl.modify(|_, w| w.owf().clear_bit()),
+        }
+    }
+
+    /// Releases the RTC peripheral
+    ///
+    /// NOTE the RTC keeps running
+    pub fn release(self) -> RTC {
+        self.rtc
+    }
+
+    /// Writes the PRL, CNT or ALR registers, which are only writable in configuration mode
+    fn configure<F>(&mut self, f: F)
+    where
+        F: FnOnce(&RTC),
+    {
+        self.wait_write();
+        self.rtc.crl.modify(|_, w| w.cnf().set_bit());
+
+        f(&self.rtc);
+
+        self.rtc.crl.modify(|_, w| w.cnf().clear_bit());
+        self.wait_write();
+    }
+
+    /// Waits until the previous write to the RTC registers has completed
+    fn wait_write(&self) {
+        while self.rtc.crl.read().rtoff().bit_is_clear() {}
+    }
+}
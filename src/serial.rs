@@ -1,3 +1,4 @@
+use core::fmt;
 use core::marker::{PhantomData, Unsize};
 use core::ptr;
 use core::sync::atomic::{self, Ordering};
@@ -8,11 +9,12 @@ use nb;
 use stm32f103xx::{USART1, USART2, USART3};
 
 use afio::MAPR;
-use dma::{CircBuffer, Static, Transfer, dma1, R, W};
-use gpio::gpioa::{PA10, PA2, PA3, PA9};
-use gpio::gpiob::{PB10, PB11, PB6, PB7};
-use gpio::{Alternate, Floating, Input, PushPull};
-use rcc::{APB1, APB2, Clocks};
+use bb;
+use dma::{CircBuffer, RingBuffer, Static, Transfer, dma1, R, W};
+use gpio::gpioa::{PA0, PA1, PA10, PA11, PA12, PA2, PA3, PA9};
+use gpio::gpiob::{PB10, PB11, PB13, PB14, PB6, PB7};
+use gpio::{Alternate, Floating, Input, OpenDrain, PushPull};
+use rcc::{APB1, APB2, Clocks, RccPeripheral};
 use time::Bps;
 
 /// Interrupt event
@@ -21,6 +23,18 @@ pub enum Event {
     Rxne,
     /// New data can be sent
     Txe,
+    /// The line has gone idle after receiving data
+    Idle,
+}
+
+impl Event {
+    fn cr1_bit(&self) -> u8 {
+        match *self {
+            Event::Rxne => 5,
+            Event::Txe => 7,
+            Event::Idle => 4,
+        }
+    }
 }
 
 /// Serial error
@@ -37,8 +51,117 @@ pub enum Error {
     #[doc(hidden)] _Extensible,
 }
 
+/// Parity check
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Parity {
+    /// No parity bit
+    ParityNone,
+    /// Even parity
+    ParityEven,
+    /// Odd parity
+    ParityOdd,
+}
+
+/// Number of stop bits
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StopBits {
+    /// 1 stop bit
+    STOP1,
+    /// 0.5 stop bits
+    STOP0P5,
+    /// 2 stop bits
+    STOP2,
+    /// 1.5 stop bits
+    STOP1P5,
+}
+
+/// Number of data bits
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WordLength {
+    /// 8 data bits
+    DataBits8,
+    /// 9 data bits; use `Rx::read_u16` and `Tx::write_u16` to access the 9th bit
+    DataBits9,
+}
+
+/// Serial configuration
+///
+/// The default configuration is 115_200 bps, 8 data bits, no parity and 1 stop bit (8N1)
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// Baud rate
+    pub baudrate: Bps,
+    /// Number of data bits
+    pub wordlength: WordLength,
+    /// Parity check
+    pub parity: Parity,
+    /// Number of stop bits
+    pub stopbits: StopBits,
+}
+
+impl Config {
+    /// Sets the baud rate
+    pub fn baudrate(mut self, baudrate: Bps) -> Self {
+        self.baudrate = baudrate;
+        self
+    }
+
+    /// Sets the number of data bits
+    pub fn wordlength(mut self, wordlength: WordLength) -> Self {
+        self.wordlength = wordlength;
+        self
+    }
+
+    /// Disables the parity check
+    pub fn parity_none(mut self) -> Self {
+        self.parity = Parity::ParityNone;
+        self
+    }
+
+    /// Enables the even parity check
+    pub fn parity_even(mut self) -> Self {
+        self.parity = Parity::ParityEven;
+        self
+    }
+
+    /// Enables the odd parity check
+    pub fn parity_odd(mut self) -> Self {
+        self.parity = Parity::ParityOdd;
+        self
+    }
+
+    /// Sets the number of stop bits
+    pub fn stopbits(mut self, stopbits: StopBits) -> Self {
+        self.stopbits = stopbits;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            baudrate: Bps(115_200),
+            wordlength: WordLength::DataBits8,
+            parity: Parity::ParityNone,
+            stopbits: StopBits::STOP1,
+        }
+    }
+}
+
+impl From<Bps> for Config {
+    fn from(baudrate: Bps) -> Config {
+        Config::default().baudrate(baudrate)
+    }
+}
+
+/// Pins used by a USART: `(TX, RX)`, with hardware flow control `(TX, RX, CTS, RTS)` or, in
+/// single-wire half-duplex mode, just `TX` configured as open drain
 pub trait Pins<USART> {
     const REMAP: u8;
+    /// Whether the CTS and RTS pins are part of the set
+    const FLOW_CONTROL: bool = false;
+    /// Whether TX and RX share the TX pin
+    const HALF_DUPLEX: bool = false;
 }
 
 impl Pins<USART1> for (PA9<Alternate<PushPull>>, PA10<Input<Floating>>) {
@@ -53,14 +176,82 @@ impl Pins<USART2> for (PA2<Alternate<PushPull>>, PA3<Input<Floating>>) {
     const REMAP: u8 = 0;
 }
 
+// NOTE the remaining remaps use pins that are not available on the LQFP-48 package (see the TODO
+// in the `gpio` module)
 // impl Pins<USART2> for (PD5<Alternate<PushPull>>, PD6<Input<Floating>>) {
-//     const REMAP: u8 = 0;
+//     const REMAP: u8 = 1;
 // }
 
 impl Pins<USART3> for (PB10<Alternate<PushPull>>, PB11<Input<Floating>>) {
     const REMAP: u8 = 0;
 }
 
+impl Pins<USART1>
+    for (
+        PA9<Alternate<PushPull>>,
+        PA10<Input<Floating>>,
+        PA11<Input<Floating>>,
+        PA12<Alternate<PushPull>>,
+    ) {
+    const REMAP: u8 = 0;
+    const FLOW_CONTROL: bool = true;
+}
+
+impl Pins<USART1>
+    for (
+        PB6<Alternate<PushPull>>,
+        PB7<Input<Floating>>,
+        PA11<Input<Floating>>,
+        PA12<Alternate<PushPull>>,
+    ) {
+    const REMAP: u8 = 1;
+    const FLOW_CONTROL: bool = true;
+}
+
+impl Pins<USART2>
+    for (
+        PA2<Alternate<PushPull>>,
+        PA3<Input<Floating>>,
+        PA0<Input<Floating>>,
+        PA1<Alternate<PushPull>>,
+    ) {
+    const REMAP: u8 = 0;
+    const FLOW_CONTROL: bool = true;
+}
+
+impl Pins<USART3>
+    for (
+        PB10<Alternate<PushPull>>,
+        PB11<Input<Floating>>,
+        PB13<Input<Floating>>,
+        PB14<Alternate<PushPull>>,
+    ) {
+    const REMAP: u8 = 0;
+    const FLOW_CONTROL: bool = true;
+}
+
+// NOTE in half-duplex mode the receiver is internally connected to the TX pin so everything that
+// is transmitted is also received
+impl Pins<USART1> for PA9<Alternate<OpenDrain>> {
+    const REMAP: u8 = 0;
+    const HALF_DUPLEX: bool = true;
+}
+
+impl Pins<USART1> for PB6<Alternate<OpenDrain>> {
+    const REMAP: u8 = 1;
+    const HALF_DUPLEX: bool = true;
+}
+
+impl Pins<USART2> for PA2<Alternate<OpenDrain>> {
+    const REMAP: u8 = 0;
+    const HALF_DUPLEX: bool = true;
+}
+
+impl Pins<USART3> for PB10<Alternate<OpenDrain>> {
+    const REMAP: u8 = 0;
+    const HALF_DUPLEX: bool = true;
+}
+
 // impl Pins<USART3> for (PC10<Alternate<PushPull>>, PC11<Input<Floating>>) {
 //     const REMAP: u8 = 1;
 // }
@@ -89,75 +280,148 @@ macro_rules! hal {
     ($(
         $USARTX:ident: (
             $usartX:ident,
-            $usartXen:ident,
-            $usartXrst:ident,
             $usartX_remap:ident,
             $bit:ident,
             $closure:expr,
             $APB:ident,
+            $pclk:ident,
             rx: $rx_chan:path,
             tx: $tx_chan:path
         ),
     )+) => {
         $(
             impl<PINS> Serial<$USARTX, PINS> {
-                pub fn $usartX(
+                /// Configures a USART peripheral to provide serial communication
+                ///
+                /// `config` can be a `Config` or just a baud rate, in which case the frame format
+                /// is 8N1
+                pub fn $usartX<C>(
                     usart: $USARTX,
                     pins: PINS,
                     mapr: &mut MAPR,
-                    baud_rate: Bps,
+                    config: C,
                     clocks: Clocks,
                     apb: &mut $APB,
                 ) -> Self
                 where
+                    C: Into<Config>,
                     PINS: Pins<$USARTX>,
                 {
+                    let config = config.into();
+
                     // enable and reset $USARTX
-                    apb.enr().modify(|_, w| w.$usartXen().enabled());
-                    apb.rstr().modify(|_, w| w.$usartXrst().set_bit());
-                    apb.rstr().modify(|_, w| w.$usartXrst().clear_bit());
+                    $USARTX::enable(apb);
+                    $USARTX::reset(apb);
 
                     #[allow(unused_unsafe)]
-                    mapr.mapr()
-                        .modify(|_, w| unsafe{
-                            w.$usartX_remap().$bit(($closure)(PINS::REMAP))
-                        });
-
-                    // enable DMA transfers
-                    usart.cr3.write(|w| w.dmat().set_bit().dmar().set_bit());
-
-                    let brr = clocks.pclk2().0 / baud_rate.0;
+                    mapr.modify_mapr(|_, w| unsafe{
+                        w.$usartX_remap().$bit(($closure)(PINS::REMAP))
+                    });
+
+                    // enable DMA transfers and, depending on the pins, RTS/CTS flow control or
+                    // single-wire half-duplex mode
+                    usart.cr3.write(|w| {
+                        w.dmat()
+                            .set_bit()
+                            .dmar()
+                            .set_bit()
+                            .ctse()
+                            .bit(PINS::FLOW_CONTROL)
+                            .rtse()
+                            .bit(PINS::FLOW_CONTROL)
+                            .hdsel()
+                            .bit(PINS::HALF_DUPLEX)
+                    });
+
+                    let brr = clocks.$pclk().0 / config.baudrate.0;
                     assert!(brr >= 16, "impossible baud rate");
                     usart.brr.write(|w| unsafe { w.bits(brr) });
 
+                    let stop_bits = match config.stopbits {
+                        StopBits::STOP1 => 0b00,
+                        StopBits::STOP0P5 => 0b01,
+                        StopBits::STOP2 => 0b10,
+                        StopBits::STOP1P5 => 0b11,
+                    };
+                    usart.cr2.write(|w| unsafe { w.stop().bits(stop_bits) });
+
+                    // NOTE the parity bit takes the place of the MSB so the word length must be
+                    // extended to 9 bits to keep 8 data bits
+                    let (pce, ps) = match config.parity {
+                        Parity::ParityNone => (false, false),
+                        Parity::ParityEven => (true, false),
+                        Parity::ParityOdd => (true, true),
+                    };
+
+                    let nine_data_bits = config.wordlength == WordLength::DataBits9;
+                    assert!(!(nine_data_bits && pce), "9 data bits plus parity is not supported");
+
                     // UE: enable USART
+                    // M: word length
+                    // PCE: parity control enable
+                    // PS: parity selection
                     // RE: enable receiver
                     // TE: enable transceiver
-                    usart
-                        .cr1
-                        .write(|w| w.ue().set_bit().re().set_bit().te().set_bit());
+                    usart.cr1.write(|w| {
+                        w.ue()
+                            .set_bit()
+                            .m()
+                            .bit(pce || nine_data_bits)
+                            .pce()
+                            .bit(pce)
+                            .ps()
+                            .bit(ps)
+                            .re()
+                            .set_bit()
+                            .te()
+                            .set_bit()
+                    });
 
                     Serial { usart, pins }
                 }
 
+                /// Starts listening for an interrupt event
                 pub fn listen(&mut self, event: Event) {
                     match event {
                         Event::Rxne => self.usart.cr1.modify(|_, w| w.rxneie().set_bit()),
                         Event::Txe => self.usart.cr1.modify(|_, w| w.txeie().set_bit()),
+                        Event::Idle => self.usart.cr1.modify(|_, w| w.idleie().set_bit()),
                     }
                 }
 
+                /// Stops listening for an interrupt event
                 pub fn unlisten(&mut self, event: Event) {
                     match event {
                         Event::Rxne => self.usart.cr1.modify(|_, w| w.rxneie().clear_bit()),
                         Event::Txe => self.usart.cr1.modify(|_, w| w.txeie().clear_bit()),
+                        Event::Idle => self.usart.cr1.modify(|_, w| w.idleie().clear_bit()),
                     }
                 }
 
+                /// Changes the baud rate
+                ///
+                /// Waits for the frame being transmitted, if any, to complete and then briefly
+                /// disables the USART while BRR is updated; data that arrives meanwhile is lost
+                pub fn reconfigure_baud(&mut self, baudrate: Bps, clocks: Clocks) {
+                    let brr = clocks.$pclk().0 / baudrate.0;
+                    assert!(brr >= 16, "impossible baud rate");
+
+                    while self.usart.sr.read().tc().bit_is_clear() {}
+
+                    self.usart.cr1.modify(|_, w| w.ue().clear_bit());
+                    self.usart.brr.write(|w| unsafe { w.bits(brr) });
+                    self.usart.cr1.modify(|_, w| w.ue().set_bit());
+                }
+
+                /// Releases the USART peripheral and associated pins
                 pub fn release(self) -> ($USARTX, PINS) {
                     (self.usart, self.pins)
                 }
 
+                /// Splits the `Serial` abstraction into a transmitter and a receiver half
+                ///
+                /// Each half only touches the status flags and DR direction it owns, so the halves
+                /// can be moved into different tasks
                 pub fn split(self) -> (Tx<$USARTX>, Rx<$USARTX>) {
                     (
                         Tx {
@@ -174,22 +438,43 @@ macro_rules! hal {
                 type Error = Error;
 
                 fn read(&mut self) -> nb::Result<u8, Error> {
+                    self.read_u16().map(|word| word as u8)
+                }
+            }
+
+            impl Rx<$USARTX> {
+                /// Reads a whole word, including the 9th data bit when 9 data bits are used
+                pub fn read_u16(&mut self) -> nb::Result<u16, Error> {
                     // NOTE(unsafe) atomic read with no side effects
                     let sr = unsafe { (*$USARTX::ptr()).sr.read() };
 
-                    Err(if sr.pe().bit_is_set() {
-                        nb::Error::Other(Error::Parity)
+                    let error = if sr.pe().bit_is_set() {
+                        Some(Error::Parity)
                     } else if sr.fe().bit_is_set() {
-                        nb::Error::Other(Error::Framing)
+                        Some(Error::Framing)
                     } else if sr.ne().bit_is_set() {
-                        nb::Error::Other(Error::Noise)
+                        Some(Error::Noise)
                     } else if sr.ore().bit_is_set() {
-                        nb::Error::Other(Error::Overrun)
-                    } else if sr.rxne().bit_is_set() {
+                        Some(Error::Overrun)
+                    } else {
+                        None
+                    };
+
+                    if let Some(error) = error {
+                        // NOTE(read_volatile) reading DR after SR clears the error flags; the
+                        // received word is discarded
+                        unsafe {
+                            ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const u16);
+                        }
+
+                        return Err(nb::Error::Other(error));
+                    }
+
+                    Err(if sr.rxne().bit_is_set() {
                         // NOTE(read_volatile) see `write_volatile` below
                         return Ok(unsafe {
-                            ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const _)
-                        });
+                            ptr::read_volatile(&(*$USARTX::ptr()).dr as *const _ as *const u16)
+                        } & 0x1FF);
                     } else {
                         nb::Error::WouldBlock
                     })
@@ -197,6 +482,36 @@ macro_rules! hal {
             }
 
             impl Rx<$USARTX> {
+                /// Starts listening for an interrupt event
+                ///
+                /// NOTE this uses bit banding so it doesn't race with the other half
+                pub fn listen(&mut self, event: Event) {
+                    // NOTE(unsafe) atomic write through the bit band region
+                    unsafe { bb::set(&(*$USARTX::ptr()).cr1, event.cr1_bit()) }
+                }
+
+                /// Stops listening for an interrupt event
+                pub fn unlisten(&mut self, event: Event) {
+                    // NOTE(unsafe) atomic write through the bit band region
+                    unsafe { bb::clear(&(*$USARTX::ptr()).cr1, event.cr1_bit()) }
+                }
+
+                /// Returns `true` if an idle line has been detected
+                pub fn is_idle(&self) -> bool {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$USARTX::ptr()).sr.read().idle().bit_is_set() }
+                }
+
+                /// Clears the idle line flag
+                ///
+                /// NOTE this reads SR and then DR, so any data that's pending in DR is discarded
+                pub fn clear_idle_interrupt(&mut self) {
+                    unsafe {
+                        let _ = (*$USARTX::ptr()).sr.read();
+                        let _ = (*$USARTX::ptr()).dr.read();
+                    }
+                }
+
                 pub fn circ_read<B>(
                     self,
                     mut chan: $rx_chan,
@@ -247,6 +562,60 @@ macro_rules! hal {
                     CircBuffer::new(buffer, chan)
                 }
 
+                /// Continuously receives into `buffer`, wrapping around when it's full
+                ///
+                /// Use `RingBuffer::write_index` to find out how much data has been received
+                pub fn read_dma_circular<B>(
+                    self,
+                    mut chan: $rx_chan,
+                    buffer: &'static mut B,
+                ) -> RingBuffer<B, $rx_chan>
+                where
+                    B: Unsize<[u8]>,
+                {
+                    let len = {
+                        let buffer: &[u8] = buffer;
+                        chan.cmar().write(|w| unsafe {
+                            w.ma().bits(buffer.as_ptr() as usize as u32)
+                        });
+                        chan.cndtr().write(|w| unsafe{
+                            w.ndt().bits(u16(buffer.len()).unwrap())
+                        });
+                        chan.cpar().write(|w| unsafe {
+                            w.pa().bits(&(*$USARTX::ptr()).dr as *const _ as usize as u32)
+                        });
+
+                        // NOTE(compiler_fence) operations on `buffer` should not be reordered after
+                        // the next statement, which starts the DMA transfer
+                        atomic::compiler_fence(Ordering::SeqCst);
+
+                        chan.ccr().modify(|_, w| {
+                            w.mem2mem()
+                                .clear_bit()
+                                .pl()
+                                .medium()
+                                .msize()
+                                .bit8()
+                                .psize()
+                                .bit8()
+                                .minc()
+                                .set_bit()
+                                .pinc()
+                                .clear_bit()
+                                .circ()
+                                .set_bit()
+                                .dir()
+                                .clear_bit()
+                                .en()
+                                .set_bit()
+                        });
+
+                        buffer.len()
+                    };
+
+                    RingBuffer::new(buffer, chan, len)
+                }
+
                 pub fn read_exact<B>(
                     self,
                     mut chan: $rx_chan,
@@ -351,6 +720,49 @@ macro_rules! hal {
                 }
             }
 
+            impl Tx<$USARTX> {
+                /// Sends a break frame (a full frame of low bits followed by a stop bit) once the
+                /// current frame, if any, has been sent
+                ///
+                /// NOTE this blocks until the break has been transmitted
+                pub fn send_break(&mut self) {
+                    // NOTE(unsafe) atomic write through the bit band region
+                    unsafe { bb::set(&(*$USARTX::ptr()).cr1, 0) }
+
+                    // NOTE(unsafe) atomic read with no side effects
+                    while unsafe { (*$USARTX::ptr()).cr1.read().sbk().bit_is_set() } {}
+                }
+
+                /// Writes a whole word, including the 9th data bit when 9 data bits are used
+                pub fn write_u16(&mut self, word: u16) -> nb::Result<(), !> {
+                    // NOTE(unsafe) atomic read with no side effects
+                    let sr = unsafe { (*$USARTX::ptr()).sr.read() };
+
+                    if sr.txe().bit_is_set() {
+                        // NOTE(unsafe) atomic write to stateless register
+                        unsafe {
+                            ptr::write_volatile(
+                                &(*$USARTX::ptr()).dr as *const _ as *mut u16,
+                                word & 0x1FF,
+                            )
+                        }
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+            }
+
+            impl fmt::Write for Tx<$USARTX> {
+                fn write_str(&mut self, s: &str) -> fmt::Result {
+                    for byte in s.bytes() {
+                        block!(hal::serial::Write::write(self, byte)).map_err(|_| fmt::Error)?;
+                    }
+
+                    Ok(())
+                }
+            }
+
             impl hal::serial::Write<u8> for Tx<$USARTX> {
                 type Error = !;
 
@@ -388,34 +800,31 @@ macro_rules! hal {
 hal! {
     USART1: (
         usart1,
-        usart1en,
-        usart1rst,
         usart1_remap,
         bit,
         |remap| remap == 1,
         APB2,
+        pclk2,
         rx: dma1::C5,
         tx: dma1::C4
     ),
     USART2: (
         usart2,
-        usart2en,
-        usart2rst,
         usart2_remap,
         bit,
         |remap| remap == 1,
         APB1,
+        pclk1,
         rx: dma1::C6,
         tx: dma1::C7
     ),
     USART3: (
         usart3,
-        usart3en,
-        usart3rst,
         usart3_remap,
         bits,
         |remap| remap,
         APB1,
+        pclk1,
         rx: dma1::C3,
         tx: dma1::C2
     ),
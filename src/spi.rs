@@ -1,25 +1,37 @@
+use core::cmp;
+use core::marker::{PhantomData, Unsize};
 use core::ptr;
+use core::sync::atomic::{self, Ordering};
 
+use cast::u16;
+use cortex_m::{asm, interrupt};
+use hal::blocking::delay::DelayUs;
+use hal::blocking::spi::{Transfer as BlockingTransfer, Write as BlockingWrite};
+use hal::digital::v2::OutputPin;
 use hal::spi::{Mode, Phase, Polarity};
 use hal;
+#[cfg(feature = "eh1")]
+use hal1;
 use nb;
-use stm32f103xx::{SPI1, SPI2};
+use stm32f103xx::{spi1, SPI1, SPI2};
 
 use afio::MAPR;
+use dma::{dma1, Static, Transfer, R, W};
 use gpio::gpioa::{PA5, PA6, PA7};
 use gpio::gpiob::{PB13, PB14, PB15, PB3, PB4, PB5};
 use gpio::{Alternate, Floating, Input, PushPull};
-use rcc::{APB1, APB2, Clocks};
+use rcc::{APB1, APB2, Clocks, RccPeripheral};
 use time::Hertz;
 
 /// SPI error
 #[derive(Debug)]
 pub enum Error {
-    /// Overrun occurred
+    /// Overrun occurred: a word was received before the previous one was read, so data was lost
     Overrun,
-    /// Mode fault occurred
+    /// Mode fault occurred: NSS was pulled low while in master mode; the peripheral is switched
+    /// back to master mode when the error is reported
     ModeFault,
-    /// CRC error
+    /// CRC error: the received CRC didn't match; the flag is cleared when the error is reported
     Crc,
     #[doc(hidden)] _Extensible,
 }
@@ -55,9 +67,72 @@ impl Pins<SPI2>
     const REMAP: bool = false;
 }
 
-pub struct Spi<SPI, PINS> {
+/// An SPI peripheral that's feeding a DMA write transfer
+pub struct TxDma<SPI, PINS> {
+    spi: Spi<SPI, PINS>,
+}
+
+/// An SPI peripheral that's serving a DMA full duplex transfer
+pub struct TxRxDma<SPI, PINS, TXCHANNEL> {
+    spi: Spi<SPI, PINS>,
+    tx: TXCHANNEL,
+}
+
+/// Pins for bidirectional (3-wire) mode: `(SCK, MOSI)`, where MOSI is the shared data line
+pub trait BidiPins<SPI> {
+    const REMAP: bool;
+}
+
+impl BidiPins<SPI1> for (PA5<Alternate<PushPull>>, PA7<Alternate<PushPull>>) {
+    const REMAP: bool = false;
+}
+
+impl BidiPins<SPI1> for (PB3<Alternate<PushPull>>, PB5<Alternate<PushPull>>) {
+    const REMAP: bool = true;
+}
+
+impl BidiPins<SPI2> for (PB13<Alternate<PushPull>>, PB15<Alternate<PushPull>>) {
+    const REMAP: bool = false;
+}
+
+/// SPI abstraction
+///
+/// `WORD` is the frame size: `u8` (the default) or `u16`
+pub struct Spi<SPI, PINS, WORD = u8> {
     spi: SPI,
     pins: PINS,
+    _word: PhantomData<WORD>,
+}
+
+/// SPI in bidirectional (3-wire) mode
+///
+/// A single data line is used for both directions so the bus is half duplex
+pub struct SpiBidi<SPI, PINS> {
+    spi: SPI,
+    pins: PINS,
+    // core clock cycles per SCK period
+    sck_cycles: u32,
+}
+
+/// Returns the BR bits for the smallest divider that doesn't exceed `freq`
+///
+/// # Panics
+///
+/// If `freq` is lower than `pclk / 256`
+fn prescaler(pclk: u32, freq: u32) -> u8 {
+    // NOTE round the divider up so the actual frequency never exceeds `freq`
+    match (pclk + freq - 1) / freq {
+        0 => unreachable!(),
+        1...2 => 0b000,
+        3...4 => 0b001,
+        5...8 => 0b010,
+        9...16 => 0b011,
+        17...32 => 0b100,
+        33...64 => 0b101,
+        65...128 => 0b110,
+        129...256 => 0b111,
+        _ => panic!("SPI frequency too low"),
+    }
 }
 
 impl<PINS> Spi<SPI1, PINS> {
@@ -74,7 +149,7 @@ impl<PINS> Spi<SPI1, PINS> {
         F: Into<Hertz>,
         PINS: Pins<SPI1>,
     {
-        mapr.mapr().modify(|_, w| w.spi1_remap().bit(PINS::REMAP));
+        mapr.modify_mapr(|_, w| w.spi1_remap().bit(PINS::REMAP));
         Spi::_spi1(spi, pins, mode, freq.into(), clocks, apb)
     }
 }
@@ -96,8 +171,238 @@ impl<PINS> Spi<SPI2, PINS> {
     }
 }
 
+impl<PINS> SpiBidi<SPI1, PINS> {
+    pub fn spi1<F>(
+        spi: SPI1,
+        pins: PINS,
+        mapr: &mut MAPR,
+        mode: Mode,
+        freq: F,
+        clocks: Clocks,
+        apb: &mut APB2,
+    ) -> Self
+    where
+        F: Into<Hertz>,
+        PINS: BidiPins<SPI1>,
+    {
+        mapr.modify_mapr(|_, w| w.spi1_remap().bit(PINS::REMAP));
+        SpiBidi::_spi1(spi, pins, mode, freq.into(), clocks, apb)
+    }
+}
+
+impl<PINS> SpiBidi<SPI2, PINS> {
+    pub fn spi2<F>(
+        spi: SPI2,
+        pins: PINS,
+        mode: Mode,
+        freq: F,
+        clocks: Clocks,
+        apb: &mut APB1,
+    ) -> Self
+    where
+        F: Into<Hertz>,
+        PINS: BidiPins<SPI2>,
+    {
+        SpiBidi::_spi2(spi, pins, mode, freq.into(), clocks, apb)
+    }
+}
+
+/// A device on a shared SPI bus, selected through its own chip select (CS) pin
+///
+/// CS is asserted (driven low) before each `transfer` / `write` and deasserted once the bus has
+/// finished clocking the last word; `DELAY` provides the optional CS setup / hold time
+pub struct SpiDevice<SPI, CS, DELAY = NoDelay> {
+    spi: SPI,
+    cs: CS,
+    delay: DELAY,
+    cs_delay_us: u32,
+}
+
+/// No CS setup / hold time
+pub struct NoDelay;
+
+impl DelayUs<u32> for NoDelay {
+    fn delay_us(&mut self, _: u32) {}
+}
+
+impl<SPI, CS> SpiDevice<SPI, CS>
+where
+    CS: OutputPin,
+{
+    /// Wraps the bus `spi` and the chip select pin `cs` of a device
+    pub fn new(spi: SPI, cs: CS) -> Self {
+        SpiDevice::with_delay(spi, cs, NoDelay, 0)
+    }
+}
+
+impl<SPI, CS, DELAY> SpiDevice<SPI, CS, DELAY>
+where
+    CS: OutputPin,
+    DELAY: DelayUs<u32>,
+{
+    /// Like `new` but waits `cs_delay_us` microseconds after asserting CS and before deasserting
+    /// it
+    pub fn with_delay(spi: SPI, mut cs: CS, delay: DELAY, cs_delay_us: u32) -> Self {
+        cs.set_high().ok();
+
+        SpiDevice {
+            spi,
+            cs,
+            delay,
+            cs_delay_us,
+        }
+    }
+
+    /// Releases the bus, the chip select pin and the delay provider
+    pub fn free(self) -> (SPI, CS, DELAY) {
+        (self.spi, self.cs, self.delay)
+    }
+
+    fn select(&mut self) {
+        // NOTE(ok) the pins of this crate can't fail to change state
+        self.cs.set_low().ok();
+        self.delay.delay_us(self.cs_delay_us);
+    }
+
+    fn deselect(&mut self) {
+        self.delay.delay_us(self.cs_delay_us);
+        self.cs.set_high().ok();
+    }
+}
+
+impl<SPI, CS, DELAY> BlockingTransfer<u8> for SpiDevice<SPI, CS, DELAY>
+where
+    SPI: BlockingTransfer<u8>,
+    CS: OutputPin,
+    DELAY: DelayUs<u32>,
+{
+    type Error = SPI::Error;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], SPI::Error> {
+        self.select();
+        // NOTE the blocking implementations return after the last word has been received, i.e.
+        // after the last clock edge
+        let ret = self.spi.transfer(words);
+        self.deselect();
+
+        ret
+    }
+}
+
+impl<SPI, CS, DELAY> BlockingWrite<u8> for SpiDevice<SPI, CS, DELAY>
+where
+    SPI: BlockingWrite<u8>,
+    CS: OutputPin,
+    DELAY: DelayUs<u32>,
+{
+    type Error = SPI::Error;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), SPI::Error> {
+        self.select();
+        let ret = self.spi.write(words);
+        self.deselect();
+
+        ret
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl<SPI, CS, DELAY> hal1::spi::ErrorType for SpiDevice<SPI, CS, DELAY>
+where
+    SPI: hal1::spi::ErrorType,
+{
+    type Error = SPI::Error;
+}
+
+#[cfg(feature = "eh1")]
+impl<SPI, CS, DELAY, WORD> hal1::spi::SpiDevice<WORD> for SpiDevice<SPI, CS, DELAY>
+where
+    SPI: hal1::spi::SpiBus<WORD>,
+    CS: OutputPin,
+    DELAY: DelayUs<u32>,
+    WORD: Copy + 'static,
+{
+    /// NOTE `DelayNs` operations are rounded up to whole microseconds and are no-ops with
+    /// `NoDelay`
+    fn transaction(
+        &mut self,
+        operations: &mut [hal1::spi::Operation<WORD>],
+    ) -> Result<(), SPI::Error> {
+        use hal1::spi::Operation;
+
+        self.select();
+
+        let mut ret = Ok(());
+        for op in operations {
+            ret = match *op {
+                Operation::Read(ref mut words) => self.spi.read(words),
+                Operation::Write(words) => self.spi.write(words),
+                Operation::Transfer(ref mut read, write) => self.spi.transfer(read, write),
+                Operation::TransferInPlace(ref mut words) => self.spi.transfer_in_place(words),
+                Operation::DelayNs(ns) => {
+                    self.spi.flush().map(|_| self.delay.delay_us((ns + 999) / 1000))
+                }
+            };
+
+            if ret.is_err() {
+                break;
+            }
+        }
+
+        // NOTE CS must stay asserted until the last word has been clocked out
+        let ret = ret.and_then(|_| self.spi.flush());
+        self.deselect();
+
+        ret
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl hal1::spi::Error for Error {
+    fn kind(&self) -> hal1::spi::ErrorKind {
+        match *self {
+            Error::Overrun => hal1::spi::ErrorKind::Overrun,
+            Error::ModeFault => hal1::spi::ErrorKind::ModeFault,
+            _ => hal1::spi::ErrorKind::Other,
+        }
+    }
+}
+
+/// Returns the error flagged in `sr`, if any
+///
+/// OVR and MODF are cleared using the sequences documented in the reference manual (RM0008)
+fn check_errors(spi: &spi1::RegisterBlock, sr: &spi1::sr::R) -> Result<(), Error> {
+    if sr.ovr().bit_is_set() {
+        // NOTE(read) reading DR and then SR clears OVR; the word in DR is the last one received
+        // before the overrun and is discarded
+        spi.dr.read();
+        spi.sr.read();
+
+        Err(Error::Overrun)
+    } else if sr.modf().bit_is_set() {
+        // NOTE(modify) writing CR1 after reading SR clears MODF; the hardware has also cleared
+        // SPE and MSTR so both are set again
+        spi.cr1.modify(|_, w| w.mstr().set_bit().spe().set_bit());
+
+        Err(Error::ModeFault)
+    } else if sr.crcerr().bit_is_set() {
+        // NOTE(modify) CRCERR is cleared by writing zero to it; the other flags are read-only
+        spi.sr.modify(|_, w| w.crcerr().clear_bit());
+
+        Err(Error::Crc)
+    } else {
+        Ok(())
+    }
+}
+
 macro_rules! hal {
-    ($($SPIX:ident: ($spiX:ident, $spiXen:ident, $spiXrst:ident, $APB:ident),)+) => {
+    ($($SPIX:ident: (
+        $spiX:ident,
+        $APB:ident,
+        $pclk:ident,
+        rx: $rx_chan:path,
+        tx: $tx_chan:path
+    ),)+) => {
         $(
             impl<PINS> Spi<$SPIX, PINS> {
                 fn $spiX(
@@ -109,24 +414,13 @@ macro_rules! hal {
                     apb: &mut $APB,
                 ) -> Self {
                     // enable or reset $SPIX
-                    apb.enr().modify(|_, w| w.$spiXen().enabled());
-                    apb.rstr().modify(|_, w| w.$spiXrst().set_bit());
-                    apb.rstr().modify(|_, w| w.$spiXrst().clear_bit());
+                    $SPIX::enable(apb);
+                    $SPIX::reset(apb);
 
                     // disable SS output
                     spi.cr2.write(|w| w.ssoe().clear_bit());
 
-                    let br = match clocks.pclk2().0 / freq.0 {
-                        0 => unreachable!(),
-                        1...2 => 0b000,
-                        3...5 => 0b001,
-                        6...11 => 0b010,
-                        12...23 => 0b011,
-                        24...47 => 0b100,
-                        48...95 => 0b101,
-                        96...191 => 0b110,
-                        _ => 0b111,
-                    };
+                    let br = prescaler(clocks.$pclk().0, freq.0);
 
                     // mstr: master configuration
                     // lsbfirst: MSB first
@@ -160,9 +454,293 @@ macro_rules! hal {
                             .set_bit()
                     });
 
-                    Spi { spi, pins }
+                    Spi { spi, pins, _word: PhantomData }
+                }
+
+                /// Switches to 16-bit frames
+                pub fn frame_size_16bit(self) -> Spi<$SPIX, PINS, u16> {
+                    // NOTE DFF can only be changed while the SPI is disabled
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.dff().bit16().spe().set_bit());
+
+                    Spi { spi: self.spi, pins: self.pins, _word: PhantomData }
+                }
+            }
+
+            impl<PINS, WORD> Spi<$SPIX, PINS, WORD> {
+                /// Changes the SPI mode and clock frequency, e.g. to talk to a different device
+                /// on a shared bus
+                ///
+                /// Waits for the ongoing transfer, if any, to complete before disabling the SPI
+                ///
+                /// # Panics
+                ///
+                /// If `freq` can't be reached, i.e. it's lower than PCLK / 256
+                pub fn reconfigure<F>(&mut self, mode: Mode, freq: F, clocks: Clocks)
+                where
+                    F: Into<Hertz>,
+                {
+                    let br = prescaler(clocks.$pclk().0, freq.into().0);
+
+                    self.wait_idle();
+
+                    // NOTE CPOL, CPHA and BR can only be changed while the SPI is disabled
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| {
+                        w.cpha()
+                            .bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .cpol()
+                            .bit(mode.polarity == Polarity::IdleHigh)
+                            .br()
+                            .bits(br)
+                    });
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                }
+
+                /// Enables the hardware CRC calculation using `polynomial` and resets the CRC
+                ///
+                /// The CRC is computed over the words sent (`tx_crc`) and received (`rx_crc`)
+                /// from now on
+                pub fn enable_crc(&mut self, polynomial: u16) {
+                    self.wait_idle();
+
+                    // NOTE CRCEN can only be changed while the SPI is disabled
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit().crcen().clear_bit());
+                    self.spi.crcpr.write(|w| unsafe { w.crcpoly().bits(polynomial) });
+                    self.spi.cr1.modify(|_, w| w.crcen().set_bit());
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                }
+
+                /// Disables the hardware CRC calculation
+                pub fn disable_crc(&mut self) {
+                    self.wait_idle();
+
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.crcen().clear_bit().spe().set_bit());
+                }
+
+                /// Transmits the CRC right after the word that's currently being sent
+                ///
+                /// Call this right after sending the last data word. The word received while the
+                /// CRC is sent is the slave's CRC; it's checked by the hardware and a mismatch is
+                /// reported as `Error::Crc` by the next read
+                pub fn send_crc_next(&mut self) {
+                    self.spi.cr1.modify(|_, w| w.crcnext().set_bit());
+                }
+
+                /// Returns the CRC of the words sent so far
+                pub fn tx_crc(&self) -> u16 {
+                    self.spi.txcrcr.read().tx_crc().bits()
+                }
+
+                /// Returns the CRC of the words received so far
+                pub fn rx_crc(&self) -> u16 {
+                    self.spi.rxcrcr.read().rx_crc().bits()
+                }
+
+                /// Clears the CRC error flag
+                pub fn clear_crc_error(&mut self) {
+                    self.spi.sr.modify(|_, w| w.crcerr().clear_bit());
+                }
+
+                /// Waits until the ongoing transfer, if any, completes
+                fn wait_idle(&self) {
+                    while {
+                        let sr = self.spi.sr.read();
+                        sr.txe().bit_is_clear() || sr.bsy().bit_is_set()
+                    } {}
+                }
+            }
+
+            impl<PINS> Spi<$SPIX, PINS> {
+                /// Sends `buffer` using a DMA transfer; the received bytes are discarded
+                ///
+                /// Once the DMA transfer is done the SPI must be released with
+                /// [`TxDma.stop`](struct.TxDma.html#method.stop)
+                pub fn write_dma<A, B>(
+                    self,
+                    mut chan: $tx_chan,
+                    buffer: B,
+                ) -> Transfer<R, B, $tx_chan, TxDma<$SPIX, PINS>>
+                where
+                    A: Unsize<[u8]>,
+                    B: Static<A>,
+                {
+                    {
+                        let buffer: &[u8] = buffer.borrow();
+                        chan.cmar().write(|w| unsafe {
+                            w.ma().bits(buffer.as_ptr() as usize as u32)
+                        });
+                        chan.cndtr().write(|w| unsafe{
+                            w.ndt().bits(u16(buffer.len()).unwrap())
+                        });
+                        chan.cpar().write(|w| unsafe {
+                            w.pa().bits(&(*$SPIX::ptr()).dr as *const _ as usize as u32)
+                        });
+
+                        // NOTE(compiler_fence) operations on `buffer` should not be reordered after
+                        // the next statement, which starts the DMA transfer
+                        atomic::compiler_fence(Ordering::SeqCst);
+
+                        chan.ccr().modify(|_, w| {
+                            w.mem2mem()
+                                .clear_bit()
+                                .pl()
+                                .medium()
+                                .msize()
+                                .bit8()
+                                .psize()
+                                .bit8()
+                                .minc()
+                                .set_bit()
+                                .pinc()
+                                .clear_bit()
+                                .circ()
+                                .clear_bit()
+                                .dir()
+                                .set_bit()
+                                .en()
+                                .set_bit()
+                        });
+                    }
+
+                    self.spi.cr2.modify(|_, w| w.txdmaen().set_bit());
+
+                    Transfer::r(buffer, chan, TxDma { spi: self })
                 }
 
+                /// Exchanges the contents of `buffer` with the slave using two DMA channels
+                ///
+                /// Each byte of `buffer` is replaced with the byte received while it was being
+                /// sent. Once the DMA transfer is done the SPI and the TX channel must be released
+                /// with [`TxRxDma.stop`](struct.TxRxDma.html#method.stop)
+                pub fn transfer_dma<B>(
+                    self,
+                    mut rx: $rx_chan,
+                    mut tx: $tx_chan,
+                    buffer: &'static mut B,
+                ) -> Transfer<W, &'static mut B, $rx_chan, TxRxDma<$SPIX, PINS, $tx_chan>>
+                where
+                    B: Unsize<[u8]>,
+                {
+                    {
+                        let buffer: &[u8] = buffer;
+                        let ma = buffer.as_ptr() as usize as u32;
+                        let ndt = u16(buffer.len()).unwrap();
+                        let pa = unsafe { &(*$SPIX::ptr()).dr as *const _ as usize as u32 };
+
+                        rx.cmar().write(|w| unsafe { w.ma().bits(ma) });
+                        rx.cndtr().write(|w| unsafe { w.ndt().bits(ndt) });
+                        rx.cpar().write(|w| unsafe { w.pa().bits(pa) });
+
+                        tx.cmar().write(|w| unsafe { w.ma().bits(ma) });
+                        tx.cndtr().write(|w| unsafe { w.ndt().bits(ndt) });
+                        tx.cpar().write(|w| unsafe { w.pa().bits(pa) });
+
+                        // NOTE(compiler_fence) operations on `buffer` should not be reordered after
+                        // the next statements, which start the DMA transfer
+                        atomic::compiler_fence(Ordering::SeqCst);
+
+                        // NOTE RXDMAEN must be set before the TX requests start, otherwise the
+                        // first received byte could be missed
+                        self.spi.cr2.modify(|_, w| w.rxdmaen().set_bit());
+
+                        rx.ccr().modify(|_, w| {
+                            w.mem2mem()
+                                .clear_bit()
+                                .pl()
+                                .high()
+                                .msize()
+                                .bit8()
+                                .psize()
+                                .bit8()
+                                .minc()
+                                .set_bit()
+                                .pinc()
+                                .clear_bit()
+                                .circ()
+                                .clear_bit()
+                                .dir()
+                                .clear_bit()
+                                .en()
+                                .set_bit()
+                        });
+
+                        tx.ccr().modify(|_, w| {
+                            w.mem2mem()
+                                .clear_bit()
+                                .pl()
+                                .medium()
+                                .msize()
+                                .bit8()
+                                .psize()
+                                .bit8()
+                                .minc()
+                                .set_bit()
+                                .pinc()
+                                .clear_bit()
+                                .circ()
+                                .clear_bit()
+                                .dir()
+                                .set_bit()
+                                .en()
+                                .set_bit()
+                        });
+
+                        self.spi.cr2.modify(|_, w| w.txdmaen().set_bit());
+                    }
+
+                    Transfer::w(buffer, rx, TxRxDma { spi: self, tx })
+                }
+            }
+
+            impl<PINS> TxDma<$SPIX, PINS> {
+                /// Waits until the last byte has been shifted out and releases the SPI peripheral
+                pub fn stop(self) -> Spi<$SPIX, PINS> {
+                    let spi = self.spi;
+
+                    while spi.spi.sr.read().txe().bit_is_clear() {}
+                    while spi.spi.sr.read().bsy().bit_is_set() {}
+
+                    spi.spi.cr2.modify(|_, w| w.txdmaen().clear_bit());
+
+                    // NOTE the received bytes were never read; clear the overrun flag by reading
+                    // DR and then SR
+                    spi.spi.dr.read();
+                    spi.spi.sr.read();
+
+                    spi
+                }
+            }
+
+            impl<PINS> TxRxDma<$SPIX, PINS, $tx_chan> {
+                /// Releases the SPI peripheral and the TX channel
+                pub fn stop(mut self) -> (Spi<$SPIX, PINS>, $tx_chan) {
+                    // NOTE the RX transfer completes after the TX one so at this point the bus
+                    // is idle
+                    self.tx.stop();
+
+                    self.spi
+                        .spi
+                        .cr2
+                        .modify(|_, w| w.txdmaen().clear_bit().rxdmaen().clear_bit());
+
+                    (self.spi, self.tx)
+                }
+            }
+
+            impl<PINS> Spi<$SPIX, PINS, u16> {
+                /// Switches to 8-bit frames
+                pub fn frame_size_8bit(self) -> Spi<$SPIX, PINS, u8> {
+                    // NOTE DFF can only be changed while the SPI is disabled
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.dff().bit8().spe().set_bit());
+
+                    Spi { spi: self.spi, pins: self.pins, _word: PhantomData }
+                }
+            }
+
+            impl<PINS, WORD> Spi<$SPIX, PINS, WORD> {
                 pub fn free(self) -> ($SPIX, PINS) {
                     (self.spi, self.pins)
                 }
@@ -173,52 +751,295 @@ macro_rules! hal {
 
                 fn read(&mut self) -> nb::Result<u8, Error> {
                     let sr = self.spi.sr.read();
+                    check_errors(&self.spi, &sr)?;
 
-                    Err(if sr.ovr().bit_is_set() {
-                        nb::Error::Other(Error::Overrun)
-                    } else if sr.modf().bit_is_set() {
-                        nb::Error::Other(Error::ModeFault)
-                    } else if sr.crcerr().bit_is_set() {
-                        nb::Error::Other(Error::Crc)
-                    } else if sr.rxne().bit_is_set() {
+                    if sr.rxne().bit_is_set() {
                         // NOTE(read_volatile) read only 1 byte (the svd2rust API only allows
                         // reading a half-word)
-                        return Ok(unsafe {
-                            ptr::read_volatile(&self.spi.dr as *const _ as *const u8)
-                        });
+                        Ok(unsafe { ptr::read_volatile(&self.spi.dr as *const _ as *const u8) })
                     } else {
-                        nb::Error::WouldBlock
-                    })
+                        Err(nb::Error::WouldBlock)
+                    }
                 }
 
                 fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
                     let sr = self.spi.sr.read();
+                    check_errors(&self.spi, &sr)?;
 
-                    Err(if sr.ovr().bit_is_set() {
-                        nb::Error::Other(Error::Overrun)
-                    } else if sr.modf().bit_is_set() {
-                        nb::Error::Other(Error::ModeFault)
-                    } else if sr.crcerr().bit_is_set() {
-                        nb::Error::Other(Error::Crc)
-                    } else if sr.txe().bit_is_set() {
+                    if sr.txe().bit_is_set() {
                         // NOTE(write_volatile) see note above
                         unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut u8, byte) }
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+            }
+
+            #[cfg(feature = "eh1")]
+            impl<PINS, WORD> hal1::spi::ErrorType for Spi<$SPIX, PINS, WORD> {
+                type Error = Error;
+            }
+
+            #[cfg(feature = "eh1")]
+            impl<PINS, WORD> hal1::spi::SpiBus<WORD> for Spi<$SPIX, PINS, WORD>
+            where
+                Self: hal::spi::FullDuplex<WORD, Error = Error>,
+                WORD: Copy + Default + 'static,
+            {
+                fn read(&mut self, words: &mut [WORD]) -> Result<(), Error> {
+                    for word in words {
+                        block!(hal::spi::FullDuplex::send(self, WORD::default()))?;
+                        *word = block!(hal::spi::FullDuplex::read(self))?;
+                    }
+
+                    Ok(())
+                }
+
+                fn write(&mut self, words: &[WORD]) -> Result<(), Error> {
+                    for word in words {
+                        block!(hal::spi::FullDuplex::send(self, *word))?;
+                        block!(hal::spi::FullDuplex::read(self))?;
+                    }
+
+                    Ok(())
+                }
+
+                fn transfer(&mut self, read: &mut [WORD], write: &[WORD]) -> Result<(), Error> {
+                    // NOTE the shorter buffer is padded with / discards `WORD::default()` words
+                    for i in 0..cmp::max(read.len(), write.len()) {
+                        let word = write.get(i).cloned().unwrap_or_default();
+                        block!(hal::spi::FullDuplex::send(self, word))?;
+
+                        let word = block!(hal::spi::FullDuplex::read(self))?;
+                        if let Some(slot) = read.get_mut(i) {
+                            *slot = word;
+                        }
+                    }
+
+                    Ok(())
+                }
+
+                fn transfer_in_place(&mut self, words: &mut [WORD]) -> Result<(), Error> {
+                    for word in words {
+                        block!(hal::spi::FullDuplex::send(self, *word))?;
+                        *word = block!(hal::spi::FullDuplex::read(self))?;
+                    }
+
+                    Ok(())
+                }
+
+                fn flush(&mut self) -> Result<(), Error> {
+                    self.wait_idle();
+
+                    Ok(())
+                }
+            }
+
+            impl<PINS> SpiBidi<$SPIX, PINS> {
+                fn $spiX(
+                    spi: $SPIX,
+                    pins: PINS,
+                    mode: Mode,
+                    freq: Hertz,
+                    clocks: Clocks,
+                    apb: &mut $APB,
+                ) -> Self {
+                    // enable or reset $SPIX
+                    $SPIX::enable(apb);
+                    $SPIX::reset(apb);
+
+                    // disable SS output
+                    spi.cr2.write(|w| w.ssoe().clear_bit());
+
+                    let br = prescaler(clocks.$pclk().0, freq.0);
+                    let sck_cycles = clocks.hclk().0 / clocks.$pclk().0 * (2 << br);
+
+                    // bidimode: 1-line bidirectional
+                    // bidioe: start in transmit mode; in receive mode the clock runs continuously
+                    // the rest of the configuration is the same as the full duplex one
+                    spi.cr1.write(|w| {
+                        w.cpha()
+                            .bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .cpol()
+                            .bit(mode.polarity == Polarity::IdleHigh)
+                            .mstr()
+                            .set_bit()
+                            .br()
+                            .bits(br)
+                            .lsbfirst()
+                            .clear_bit()
+                            .ssm()
+                            .set_bit()
+                            .ssi()
+                            .set_bit()
+                            .rxonly()
+                            .clear_bit()
+                            .dff()
+                            .clear_bit()
+                            .bidimode()
+                            .set_bit()
+                            .bidioe()
+                            .set_bit()
+                            .spe()
+                            .set_bit()
+                    });
+
+                    SpiBidi { spi, pins, sck_cycles }
+                }
+
+                pub fn free(self) -> ($SPIX, PINS) {
+                    (self.spi, self.pins)
+                }
+
+                /// Reads `buffer.len()` bytes from the shared data line
+                ///
+                /// NOTE the master clocks the bytes in continuously; an interrupt handler that runs
+                /// for longer than the transfer of a byte makes this fail with `Error::Overrun`
+                pub fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+                    if buffer.is_empty() {
                         return Ok(());
+                    }
+
+                    // wait until the last write has gone out
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+
+                    let ret = self.recv(buffer);
+
+                    // back to transmit mode
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.bidioe().set_bit().spe().set_bit());
+
+                    ret
+                }
+
+                fn recv(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+                    let len = buffer.len();
+                    let (head, tail) = buffer.split_at_mut(len - cmp::min(len, 2));
+
+                    // NOTE the receive sequence is timing sensitive: SPE must be cleared between
+                    // the reception of the second to last byte and the start of the last one,
+                    // otherwise the clock keeps running. Only that turn-around runs with the
+                    // interrupts disabled
+                    if tail.len() == 1 {
+                        interrupt::free(|_| {
+                            self.start_receiving();
+                            self.stop_receiving();
+                        });
                     } else {
-                        nb::Error::WouldBlock
-                    })
+                        self.start_receiving();
+                    }
+
+                    for byte in head {
+                        *byte = block!(self.receive())?;
+                    }
+
+                    if tail.len() == 2 {
+                        tail[0] = interrupt::free(|_| {
+                            let byte = block!(self.receive());
+                            self.stop_receiving();
+                            byte
+                        })?;
+                    }
+
+                    tail[tail.len() - 1] = block!(self.receive())?;
+
+                    Ok(())
+                }
+
+                fn start_receiving(&mut self) {
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.bidioe().clear_bit().spe().set_bit());
+                }
+
+                fn receive(&mut self) -> nb::Result<u8, Error> {
+                    let sr = self.spi.sr.read();
+                    check_errors(&self.spi, &sr)?;
+
+                    if sr.rxne().bit_is_set() {
+                        // NOTE(read_volatile) see the FullDuplex implementation
+                        Ok(unsafe { ptr::read_volatile(&self.spi.dr as *const _ as *const u8) })
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                fn stop_receiving(&mut self) {
+                    // wait for (at least) one SCK period so the last byte starts to be clocked in
+                    for _ in 0..self.sck_cycles {
+                        asm::nop();
+                    }
+
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                }
+            }
+
+            impl<PINS> ::hal::blocking::spi::Write<u8> for SpiBidi<$SPIX, PINS> {
+                type Error = Error;
+
+                fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+                    for word in words {
+                        loop {
+                            let sr = self.spi.sr.read();
+                            check_errors(&self.spi, &sr)?;
+
+                            if sr.txe().bit_is_set() {
+                                // NOTE(write_volatile) see the FullDuplex implementation
+                                unsafe {
+                                    ptr::write_volatile(&self.spi.dr as *const _ as *mut u8, *word)
+                                }
+                                break;
+                            }
+                        }
+                    }
+
+                    // wait until the last word has been clocked out
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+
+                    Ok(())
+                }
+            }
+
+            impl<PINS> hal::spi::FullDuplex<u16> for Spi<$SPIX, PINS, u16> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u16, Error> {
+                    let sr = self.spi.sr.read();
+                    check_errors(&self.spi, &sr)?;
+
+                    if sr.rxne().bit_is_set() {
+                        Ok(self.spi.dr.read().dr().bits())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
                 }
 
+                fn send(&mut self, word: u16) -> nb::Result<(), Error> {
+                    let sr = self.spi.sr.read();
+                    check_errors(&self.spi, &sr)?;
+
+                    if sr.txe().bit_is_set() {
+                        self.spi.dr.write(|w| unsafe { w.dr().bits(word) });
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
             }
 
             impl<PINS> ::hal::blocking::spi::transfer::Default<u8> for Spi<$SPIX, PINS> {}
 
             impl<PINS> ::hal::blocking::spi::write::Default<u8> for Spi<$SPIX, PINS> {}
+
+            impl<PINS> ::hal::blocking::spi::transfer::Default<u16> for Spi<$SPIX, PINS, u16> {}
+
+            impl<PINS> ::hal::blocking::spi::write::Default<u16> for Spi<$SPIX, PINS, u16> {}
         )+
     }
 }
 
 hal! {
-    SPI1: (_spi1, spi1en, spi1rst, APB2),
-    SPI2: (_spi2, spi2en, spi2rst, APB1),
+    SPI1: (_spi1, APB2, pclk2, rx: dma1::C2, tx: dma1::C3),
+    SPI2: (_spi2, APB1, pclk1, rx: dma1::C4, tx: dma1::C5),
 }
@@ -1,25 +1,35 @@
 //! Time units
 
-use cortex_m::peripheral::DWT;
+use core::cmp;
+
+use cortex_m::peripheral::{DCB, DWT};
 
 use rcc::Clocks;
 
 /// Bits per second
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Bps(pub u32);
 
 /// Hertz
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Hertz(pub u32);
 
 /// KiloHertz
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct KiloHertz(pub u32);
 
 /// MegaHertz
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct MegaHertz(pub u32);
 
+/// MilliSeconds
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct MilliSeconds(pub u32);
+
+/// MicroSeconds
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct MicroSeconds(pub u32);
+
 /// Extension trait that adds convenience methods to the `u32` type
 pub trait U32Ext {
     /// Wrap in `Bps`
@@ -33,6 +43,12 @@ pub trait U32Ext {
 
     /// Wrap in `MegaHertz`
     fn mhz(self) -> MegaHertz;
+
+    /// Wrap in `MilliSeconds`
+    fn ms(self) -> MilliSeconds;
+
+    /// Wrap in `MicroSeconds`
+    fn us(self) -> MicroSeconds;
 }
 
 impl U32Ext for u32 {
@@ -51,27 +67,119 @@ impl U32Ext for u32 {
     fn mhz(self) -> MegaHertz {
         MegaHertz(self)
     }
+
+    fn ms(self) -> MilliSeconds {
+        MilliSeconds(self)
+    }
+
+    fn us(self) -> MicroSeconds {
+        MicroSeconds(self)
+    }
 }
 
-impl Into<Hertz> for KiloHertz {
-    fn into(self) -> Hertz {
-        Hertz(self.0 * 1_000)
+impl From<KiloHertz> for Hertz {
+    fn from(khz: KiloHertz) -> Self {
+        Hertz(khz.0 * 1_000)
     }
 }
 
-impl Into<Hertz> for MegaHertz {
-    fn into(self) -> Hertz {
-        Hertz(self.0 * 1_000_000)
+impl From<MegaHertz> for Hertz {
+    fn from(mhz: MegaHertz) -> Self {
+        Hertz(mhz.0 * 1_000_000)
     }
 }
 
-impl Into<KiloHertz> for MegaHertz {
-    fn into(self) -> KiloHertz {
-        KiloHertz(self.0 * 1_000)
+impl From<MegaHertz> for KiloHertz {
+    fn from(mhz: MegaHertz) -> Self {
+        KiloHertz(mhz.0 * 1_000)
     }
 }
 
+/// Frequency of a periodic event with the given period
+///
+/// NOTE the result is truncated to whole hertz so periods longer than one second map to `0.hz()`,
+/// and a zero period panics; the timers take a `Timeout`, which keeps the duration, instead
+impl From<MilliSeconds> for Hertz {
+    fn from(ms: MilliSeconds) -> Self {
+        Hertz(1_000 / ms.0)
+    }
+}
+
+/// Frequency of a periodic event with the given period
+///
+/// NOTE the result is truncated to whole hertz, see `From<MilliSeconds>`
+impl From<MicroSeconds> for Hertz {
+    fn from(us: MicroSeconds) -> Self {
+        Hertz(1_000_000 / us.0)
+    }
+}
+
+impl From<MilliSeconds> for MicroSeconds {
+    fn from(ms: MilliSeconds) -> Self {
+        MicroSeconds(ms.0 * 1_000)
+    }
+}
+
+/// Timeout of a count down timer, given either as a rate or as a duration
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Timeout {
+    /// The timer times out this many times per second
+    Frequency(Hertz),
+    /// The timer times out after this long
+    Duration(MicroSeconds),
+}
+
+impl Timeout {
+    /// Returns the number of cycles of the `clk` clock that make up the timeout
+    pub(crate) fn ticks(self, clk: Hertz) -> u64 {
+        match self {
+            Timeout::Frequency(freq) => {
+                assert!(freq.0 > 0);
+
+                u64::from(clk.0) / u64::from(freq.0)
+            }
+            Timeout::Duration(us) => u64::from(clk.0) * u64::from(us.0) / 1_000_000,
+        }
+    }
+}
+
+impl From<Hertz> for Timeout {
+    fn from(hz: Hertz) -> Self {
+        Timeout::Frequency(hz)
+    }
+}
+
+impl From<KiloHertz> for Timeout {
+    fn from(khz: KiloHertz) -> Self {
+        Timeout::Frequency(khz.into())
+    }
+}
+
+impl From<MegaHertz> for Timeout {
+    fn from(mhz: MegaHertz) -> Self {
+        Timeout::Frequency(mhz.into())
+    }
+}
+
+impl From<MilliSeconds> for Timeout {
+    fn from(ms: MilliSeconds) -> Self {
+        Timeout::Duration(ms.into())
+    }
+}
+
+impl From<MicroSeconds> for Timeout {
+    fn from(us: MicroSeconds) -> Self {
+        Timeout::Duration(us)
+    }
+}
+
+/// TRCENA bit of the Debug Exception and Monitor Control Register
+const DEMCR_TRCENA: u32 = 1 << 24;
+
 /// A monotonic nondecreasing timer
+///
+/// Counts core clock cycles using the DWT cycle counter (CYCCNT), which wraps around every
+/// 2^32 cycles (~60 seconds at 72 MHz)
 #[derive(Clone, Copy)]
 pub struct MonoTimer {
     frequency: Hertz,
@@ -79,7 +187,10 @@ pub struct MonoTimer {
 
 impl MonoTimer {
     /// Creates a new `Monotonic` timer
-    pub fn new(mut dwt: DWT, clocks: Clocks) -> Self {
+    pub fn new(mut dwt: DWT, _dcb: DCB, clocks: Clocks) -> Self {
+        // NOTE the DWT is only clocked when tracing is enabled, which is usually done by the
+        // debugger; without this CYCCNT stays at zero when running standalone
+        unsafe { _dcb.demcr.modify(|r| r | DEMCR_TRCENA) }
         dwt.enable_cycle_counter();
 
         // now the CYCCNT counter can't be stopped or resetted
@@ -101,6 +212,27 @@ impl MonoTimer {
             now: DWT::get_cycle_count(),
         }
     }
+
+    /// Busy waits for at least `cycles` core clock cycles
+    pub fn delay_cycles(&self, cycles: u32) {
+        let start = self.now();
+
+        while start.elapsed() < cycles {}
+    }
+
+    /// Busy waits for at least `us` microseconds
+    pub fn delay_us(&self, us: u32) {
+        // NOTE split the delay so each chunk fits in the 32-bit cycle counter
+        let cycles_per_us = self.frequency.0 / 1_000_000;
+        let max_us = u32::max_value() / cycles_per_us;
+
+        let mut us = us;
+        while us != 0 {
+            let chunk = cmp::min(us, max_us);
+            self.delay_cycles(chunk * cycles_per_us);
+            us -= chunk;
+        }
+    }
 }
 
 /// A measurement of a monotonically nondecreasing clock
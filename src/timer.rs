@@ -1,12 +1,20 @@
+use core::cmp;
+
 use cast::{u16, u32};
 use cortex_m::peripheral::SYST;
 use cortex_m::peripheral::syst::SystClkSource;
+use hal::blocking::delay::{DelayMs, DelayUs};
 use hal::timer::{CountDown, Periodic};
 use nb;
 use stm32f103xx::{TIM2, TIM3, TIM4};
+use void::Void;
 
-use rcc::{APB1, Clocks};
-use time::Hertz;
+use bb;
+use gpio::gpioa::{PA0, PA1, PA2, PA3, PA6, PA7};
+use gpio::gpiob::{PB0, PB1, PB6, PB7, PB8, PB9};
+use gpio::{Alternate, PushPull};
+use rcc::{APB1, Clocks, RccPeripheral};
+use time::{MicroSeconds, Timeout};
 
 /// Interrupt events
 pub enum Event {
@@ -14,15 +22,131 @@ pub enum Event {
     Update,
 }
 
+/// Trigger output (TRGO) of a timer operating as master
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MasterMode {
+    /// Emits a trigger when the counter is reset through the UG bit
+    Reset,
+    /// Emits a trigger when the counter is enabled; used to start several timers at once
+    Enable,
+    /// Emits a trigger on each update event (overflow); used to cascade timers
+    Update,
+}
+
+impl MasterMode {
+    fn bits(&self) -> u8 {
+        match *self {
+            MasterMode::Reset => 0b000,
+            MasterMode::Enable => 0b001,
+            MasterMode::Update => 0b010,
+        }
+    }
+}
+
+/// Reaction of a timer operating as slave to the trigger of its master
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlaveMode {
+    /// The counter is reset on each trigger
+    Reset,
+    /// The counter only runs while the trigger is high
+    Gated,
+    /// The counter is started (but not reset) by the trigger; `cancel` the timer beforehand
+    Trigger,
+    /// The counter is clocked by the trigger: it increments by one on each trigger
+    ExternalClock,
+}
+
+impl SlaveMode {
+    fn bits(&self) -> u8 {
+        match *self {
+            SlaveMode::Reset => 0b100,
+            SlaveMode::Gated => 0b101,
+            SlaveMode::Trigger => 0b110,
+            SlaveMode::ExternalClock => 0b111,
+        }
+    }
+}
+
+/// Timer whose trigger output is internally connected to the slave timer `TIM`
+pub trait InternalTrigger<TIM> {
+    /// Internal trigger (ITRx) input of `TIM` that the timer is connected to
+    const ITR: u8;
+}
+
+// NOTE see table 86 of the reference manual (RM0008)
+impl InternalTrigger<TIM2> for TIM3 {
+    const ITR: u8 = 2;
+}
+
+impl InternalTrigger<TIM2> for TIM4 {
+    const ITR: u8 = 3;
+}
+
+impl InternalTrigger<TIM3> for TIM2 {
+    const ITR: u8 = 1;
+}
+
+impl InternalTrigger<TIM3> for TIM4 {
+    const ITR: u8 = 3;
+}
+
+impl InternalTrigger<TIM4> for TIM2 {
+    const ITR: u8 = 1;
+}
+
+impl InternalTrigger<TIM4> for TIM3 {
+    const ITR: u8 = 2;
+}
+
 pub struct Timer<TIM> {
     tim: TIM,
     clocks: Clocks,
 }
 
+/// General purpose timer as a delay provider
+///
+/// Useful when the system timer (SysTick) is owned by a scheduler
+pub struct DelayTimer<TIM> {
+    tim: TIM,
+}
+
+/// General purpose timer that outputs a single pulse on one of its channels each time it's
+/// triggered
+pub struct OnePulse<TIM, PIN> {
+    tim: TIM,
+    pin: PIN,
+}
+
+/// Output pin of a timer channel
+///
+/// NOTE only the default (not remapped) pins are supported
+pub trait OnePulsePin<TIM> {
+    /// Channel index, starting at 0 for channel 1
+    const CHANNEL: u8;
+}
+
+macro_rules! one_pulse_pins {
+    ($($TIMX:ident: [$($PIN:ident: $channel:expr,)+],)+) => {
+        $(
+            $(
+                impl OnePulsePin<$TIMX> for $PIN<Alternate<PushPull>> {
+                    const CHANNEL: u8 = $channel;
+                }
+            )+
+        )+
+    }
+}
+
+one_pulse_pins! {
+    TIM2: [PA0: 0, PA1: 1, PA2: 2, PA3: 3,],
+    TIM3: [PA6: 0, PA7: 1, PB0: 2, PB1: 3,],
+    TIM4: [PB6: 0, PB7: 1, PB8: 2, PB9: 3,],
+}
+
 impl Timer<SYST> {
     pub fn syst<T>(mut syst: SYST, timeout: T, clocks: Clocks) -> Self
     where
-        T: Into<Hertz>,
+        T: Into<Timeout>,
     {
         syst.set_clock_source(SystClkSource::Core);
         let mut timer = Timer { tim: syst, clocks };
@@ -30,6 +154,11 @@ impl Timer<SYST> {
         timer
     }
 
+    /// Stops the count down; `start` restarts it
+    pub fn cancel(&mut self) {
+        self.tim.disable_counter();
+    }
+
     /// Starts listening for an `event`
     pub fn listen(&mut self, event: Event) {
         match event {
@@ -46,22 +175,22 @@ impl Timer<SYST> {
 }
 
 impl CountDown for Timer<SYST> {
-    type Time = Hertz;
+    type Time = Timeout;
 
     fn start<T>(&mut self, timeout: T)
     where
-        T: Into<Hertz>,
+        T: Into<Timeout>,
     {
-        let rvr = self.clocks.sysclk().0 / timeout.into().0 - 1;
+        let ticks = timeout.into().ticks(self.clocks.sysclk());
 
-        assert!(rvr < (1 << 24));
+        assert!(ticks > 0 && ticks <= (1 << 24));
 
-        self.tim.set_reload(rvr);
+        self.tim.set_reload(ticks as u32 - 1);
         self.tim.clear_current();
         self.tim.enable_counter();
     }
 
-    fn wait(&mut self) -> nb::Result<(), !> {
+    fn wait(&mut self) -> nb::Result<(), Void> {
         if self.tim.has_wrapped() {
             Ok(())
         } else {
@@ -73,17 +202,16 @@ impl CountDown for Timer<SYST> {
 impl Periodic for Timer<SYST> {}
 
 macro_rules! hal {
-    ($($TIMX:ident: ($timX:ident, $timXen:ident, $timXrst:ident),)+) => {
+    ($($TIMX:ident: ($timX:ident),)+) => {
         $(
             impl Timer<$TIMX> {
                 pub fn $timX<T>(tim: $TIMX, timeout: T, clocks: Clocks, apb1: &mut APB1) -> Self
                 where
-                    T: Into<Hertz>,
+                    T: Into<Timeout>,
                 {
                     // enable and reset peripheral to a clean slate state
-                    apb1.enr().modify(|_, w| w.$timXen().set_bit());
-                    apb1.rstr().modify(|_, w| w.$timXrst().set_bit());
-                    apb1.rstr().modify(|_, w| w.$timXrst().clear_bit());
+                    $TIMX::enable(apb1);
+                    $TIMX::reset(apb1);
 
                     let mut timer = Timer { clocks, tim };
                     timer.start(timeout);
@@ -91,6 +219,11 @@ macro_rules! hal {
                     timer
                 }
 
+                /// Stops the count down; `start` restarts it
+                pub fn cancel(&mut self) {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                }
+
                 /// Starts listening for an `event`
                 pub fn listen(&mut self, event: Event) {
                     match event {
@@ -104,24 +237,71 @@ macro_rules! hal {
                         Event::Update => self.tim.dier.write(|w| w.uie().clear_bit()),
                     }
                 }
+
+                /// Clears the update interrupt flag; call this from the interrupt handler
+                pub fn clear_update_interrupt_flag(&mut self) {
+                    self.tim.sr.modify(|_, w| w.uif().clear_bit());
+                }
+
+                /// Returns the current value of the counter
+                pub fn count(&self) -> u16 {
+                    self.tim.cnt.read().cnt().bits()
+                }
+
+                /// Makes the timer emit a trigger for its slave timers on `mode` events
+                pub fn set_master_mode(&mut self, mode: MasterMode) {
+                    self.tim.cr2.modify(|_, w| unsafe { w.mms().bits(mode.bits()) });
+                }
+
+                /// Makes the timer react to the trigger emitted by `master` according to `mode`
+                ///
+                /// NOTE in `ExternalClock` mode the prescaler and the auto-reload register are
+                /// reset so that the counter counts every trigger and wraps around at `0xFFFF`;
+                /// chaining two timers this way results in a 32-bit counter
+                pub fn set_slave_mode<M>(&mut self, _master: &Timer<M>, mode: SlaveMode)
+                where
+                    M: InternalTrigger<$TIMX>,
+                {
+                    if mode == SlaveMode::ExternalClock {
+                        self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+                        self.tim.psc.write(|w| w.psc().bits(0));
+                        self.tim.arr.write(|w| unsafe { w.bits(0xFFFF) });
+                        self.tim.egr.write(|w| w.ug().set_bit());
+                        self.tim.sr.modify(|_, w| w.uif().clear_bit());
+                    }
+
+                    self.tim
+                        .smcr
+                        .modify(|_, w| w.ts().bits(M::ITR).sms().bits(mode.bits()));
+
+                    if mode == SlaveMode::ExternalClock {
+                        self.tim.cr1.modify(|_, w| w.cen().set_bit());
+                    }
+                }
+
+                /// Stops reacting to the trigger of the master timer
+                pub fn clear_slave_mode(&mut self) {
+                    self.tim.smcr.modify(|_, w| w.sms().disabled());
+                }
             }
 
             impl CountDown for Timer<$TIMX> {
-                type Time = Hertz;
+                type Time = Timeout;
 
                 fn start<T>(&mut self, timeout: T)
                 where
-                    T: Into<Hertz>,
+                    T: Into<Timeout>,
                 {
                     // pause
                     self.tim.cr1.modify(|_, w| w.cen().clear_bit());
                     // restart counter
                     self.tim.cnt.reset();
 
-                    let frequency = timeout.into().0;
+                    let ticks = timeout.into().ticks(self.clocks.pclk1_tim());
 
-                    let ticks = self.clocks.pclk1().0 * if self.clocks.ppre1() == 1 { 1 } else { 2 }
-                    / frequency;
+                    // NOTE(1 << 32) PSC and ARR are both 16 bits wide
+                    assert!(ticks > 0 && ticks <= 1 << 32);
+                    let ticks = ticks as u32;
 
                     let psc = u16((ticks - 1) / (1 << 16)).unwrap();
                     self.tim.psc.write(|w| w.psc().bits(psc));
@@ -133,7 +313,7 @@ macro_rules! hal {
                     self.tim.cr1.modify(|_, w| w.cen().set_bit());
                 }
 
-                fn wait(&mut self) -> nb::Result<(), !> {
+                fn wait(&mut self) -> nb::Result<(), Void> {
                     if self.tim.sr.read().uif().bit_is_clear() {
                         Err(nb::Error::WouldBlock)
                     } else {
@@ -144,12 +324,172 @@ macro_rules! hal {
             }
 
             impl Periodic for Timer<$TIMX> {}
+
+            impl DelayTimer<$TIMX> {
+                /// Configures a general purpose timer as a delay provider
+                pub fn $timX(tim: $TIMX, clocks: Clocks, apb1: &mut APB1) -> Self {
+                    // enable and reset peripheral to a clean slate state
+                    $TIMX::enable(apb1);
+                    $TIMX::reset(apb1);
+
+                    // count microseconds
+                    let psc = u16(clocks.pclk1_tim().0 / 1_000_000 - 1).unwrap();
+                    tim.psc.write(|w| w.psc().bits(psc));
+
+                    DelayTimer { tim }
+                }
+
+                /// Releases the timer resource
+                pub fn free(self) -> $TIMX {
+                    self.tim
+                }
+            }
+
+            impl DelayUs<u32> for DelayTimer<$TIMX> {
+                fn delay_us(&mut self, us: u32) {
+                    let mut us = us;
+
+                    while us != 0 {
+                        // NOTE ARR is 16 bits wide
+                        let chunk = cmp::min(us, 0xFFFF);
+
+                        self.tim.arr.write(|w| unsafe { w.bits(chunk) });
+
+                        // load the prescaler and reset the counter; this also sets the update flag
+                        self.tim.egr.write(|w| w.ug().set_bit());
+                        self.tim.sr.modify(|_, w| w.uif().clear_bit());
+
+                        // in one pulse mode the counter stops by itself on the next update event
+                        self.tim.cr1.modify(|_, w| w.opm().set_bit().cen().set_bit());
+                        while self.tim.sr.read().uif().bit_is_clear() {}
+
+                        us -= chunk;
+                    }
+                }
+            }
+
+            impl DelayUs<u16> for DelayTimer<$TIMX> {
+                fn delay_us(&mut self, us: u16) {
+                    self.delay_us(u32(us))
+                }
+            }
+
+            impl DelayUs<u8> for DelayTimer<$TIMX> {
+                fn delay_us(&mut self, us: u8) {
+                    self.delay_us(u32(us))
+                }
+            }
+
+            impl DelayMs<u32> for DelayTimer<$TIMX> {
+                fn delay_ms(&mut self, ms: u32) {
+                    // NOTE split the delay to avoid overflowing the microsecond count
+                    for _ in 0..ms {
+                        self.delay_us(1_000u32);
+                    }
+                }
+            }
+
+            impl DelayMs<u16> for DelayTimer<$TIMX> {
+                fn delay_ms(&mut self, ms: u16) {
+                    self.delay_ms(u32(ms));
+                }
+            }
+
+            impl DelayMs<u8> for DelayTimer<$TIMX> {
+                fn delay_ms(&mut self, ms: u8) {
+                    self.delay_ms(u32(ms));
+                }
+            }
+
+            impl<PIN> OnePulse<$TIMX, PIN>
+            where
+                PIN: OnePulsePin<$TIMX>,
+            {
+                /// Configures a general purpose timer to output pulses of `width` on `pin`
+                ///
+                /// The pin stays low until `trigger` is called
+                pub fn $timX<T>(
+                    tim: $TIMX,
+                    pin: PIN,
+                    width: T,
+                    clocks: Clocks,
+                    apb1: &mut APB1,
+                ) -> Self
+                where
+                    T: Into<MicroSeconds>,
+                {
+                    // enable and reset peripheral to a clean slate state
+                    $TIMX::enable(apb1);
+                    $TIMX::reset(apb1);
+
+                    let ticks = u64::from(width.into().0) * u64::from(clocks.pclk1_tim().0)
+                        / 1_000_000;
+
+                    // NOTE one more tick is needed to start the pulse (CCR = 1)
+                    let psc = u16(ticks / (1 << 16)).unwrap();
+                    tim.psc.write(|w| w.psc().bits(psc));
+                    let ticks = cmp::max(ticks / (u64::from(psc) + 1), 1) as u32;
+                    tim.arr.write(|w| unsafe { w.bits(ticks) });
+
+                    // PWM mode 2: the output is active while CCR <= CNT <= ARR
+                    match PIN::CHANNEL {
+                        0 => {
+                            tim.ccmr1_output.modify(|_, w| w.oc1m().pwm2());
+                            tim.ccr1.write(|w| w.ccr1().bits(1));
+                        }
+                        1 => {
+                            tim.ccmr1_output.modify(|_, w| w.oc2m().pwm2());
+                            tim.ccr2.write(|w| w.ccr2().bits(1));
+                        }
+                        2 => {
+                            tim.ccmr2_output.modify(|_, w| w.oc3m().pwm2());
+                            tim.ccr3.write(|w| w.ccr3().bits(1));
+                        }
+                        _ => {
+                            tim.ccmr2_output.modify(|_, w| w.oc4m().pwm2());
+                            tim.ccr4.write(|w| w.ccr4().bits(1));
+                        }
+                    }
+
+                    // load the prescaler
+                    tim.egr.write(|w| w.ug().set_bit());
+                    tim.sr.modify(|_, w| w.uif().clear_bit());
+
+                    // in one pulse mode the counter stops by itself on the next update event
+                    tim.cr1.modify(|_, w| w.opm().set_bit());
+                    bb::set(&tim.ccer, 4 * PIN::CHANNEL);
+
+                    OnePulse { tim, pin }
+                }
+
+                /// Outputs a single pulse
+                ///
+                /// NOTE calling this while a pulse is being output has no effect
+                pub fn trigger(&mut self) {
+                    self.tim.cr1.modify(|_, w| w.cen().set_bit());
+                }
+
+                /// Waits until the pulse has been output
+                pub fn wait(&mut self) -> nb::Result<(), Void> {
+                    if self.tim.cr1.read().cen().bit_is_set() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        Ok(())
+                    }
+                }
+
+                /// Releases the timer and the pin
+                pub fn free(self) -> ($TIMX, PIN) {
+                    self.tim.ccer.reset();
+                    (self.tim, self.pin)
+                }
+            }
         )+
     }
 }
 
 hal! {
-    TIM2: (tim2, tim2en, tim2rst),
-    TIM3: (tim3, tim3en, tim3rst),
-    TIM4: (tim4, tim4en, tim3rst),
+    TIM2: (tim2),
+    TIM3: (tim3),
+    TIM4: (tim4),
 }
@@ -0,0 +1,195 @@
+//! Watchdogs
+
+use hal::watchdog::{Watchdog, WatchdogEnable};
+use stm32f103xx::{IWDG, WWDG};
+
+use rcc::{APB1, Clocks, RccPeripheral};
+use time::MilliSeconds;
+
+/// LSI frequency, in Hz, that clocks the independent watchdog
+///
+/// NOTE the LSI is not trimmed; the datasheet only guarantees 30 - 60 kHz
+const LSI: u32 = 40_000;
+
+/// Largest reload value (RLR is 12 bits wide)
+const MAX_RL: u32 = 0xFFF;
+
+/// Largest prescaler divider
+const MAX_PR_DIV: u32 = 256;
+
+const KEY_RELOAD: u16 = 0xAAAA;
+const KEY_START: u16 = 0xCCCC;
+const KEY_UNLOCK: u16 = 0x5555;
+
+/// Independent watchdog (IWDG)
+///
+/// Once started the watchdog can't be stopped; it resets the microcontroller unless it's fed
+/// before the timeout expires
+pub struct IndependentWatchdog {
+    iwdg: IWDG,
+}
+
+impl IndependentWatchdog {
+    /// Longest timeout, in milliseconds, that can be programmed (~26 seconds)
+    pub const MAX_TIMEOUT_MS: u32 = (MAX_RL + 1) * MAX_PR_DIV * 1_000 / LSI;
+
+    /// Wraps the IWDG peripheral
+    pub fn new(iwdg: IWDG) -> Self {
+        IndependentWatchdog { iwdg }
+    }
+
+    /// Starts the watchdog with a timeout of `timeout_ms` milliseconds
+    ///
+    /// The actual timeout depends on the LSI frequency, which can be off by as much as 50%
+    pub fn start(&mut self, timeout_ms: u32) {
+        assert!(timeout_ms <= Self::MAX_TIMEOUT_MS, "timeout too long");
+
+        // pick the smallest prescaler (best resolution) that can fit the timeout in RLR
+        let ticks = timeout_ms * (LSI / 1_000);
+        let mut pr = 0;
+        while pr < 6 && ticks > (MAX_RL + 1) * (4 << pr) {
+            pr += 1;
+        }
+        let rl = (ticks / (4 << pr)).max(1) - 1;
+
+        // NOTE starting the watchdog also starts the LSI
+        self.iwdg.kr.write(|w| unsafe { w.key().bits(KEY_START) });
+
+        // unlock PR and RLR
+        self.iwdg.kr.write(|w| unsafe { w.key().bits(KEY_UNLOCK) });
+        self.iwdg.pr.write(|w| unsafe { w.pr().bits(pr) });
+        self.iwdg.rlr.write(|w| unsafe { w.rl().bits(rl as u16) });
+
+        // wait until the new values have reached the LSI clock domain
+        while {
+            let sr = self.iwdg.sr.read();
+            sr.pvu().bit_is_set() || sr.rvu().bit_is_set()
+        } {}
+
+        self.feed();
+    }
+
+    /// Feeds the watchdog, restarting the timeout
+    pub fn feed(&mut self) {
+        self.iwdg.kr.write(|w| unsafe { w.key().bits(KEY_RELOAD) });
+    }
+}
+
+impl WatchdogEnable for IndependentWatchdog {
+    type Time = MilliSeconds;
+
+    fn start<T>(&mut self, period: T)
+    where
+        T: Into<MilliSeconds>,
+    {
+        IndependentWatchdog::start(self, period.into().0)
+    }
+}
+
+impl Watchdog for IndependentWatchdog {
+    fn feed(&mut self) {
+        IndependentWatchdog::feed(self)
+    }
+}
+
+/// Window watchdog (WWDG)
+///
+/// The watchdog resets the microcontroller when it's not fed before the timeout expires *and*
+/// when it's fed too early, before the window opens
+pub struct WindowWatchdog {
+    wwdg: WWDG,
+    clocks: Clocks,
+    // reload value of the counter
+    t: u8,
+    // counter value below which the watchdog can be fed
+    w: u8,
+    wdgtb: u8,
+}
+
+impl WindowWatchdog {
+    /// Wraps the WWDG peripheral
+    pub fn new(wwdg: WWDG, clocks: Clocks, apb1: &mut APB1) -> Self {
+        WWDG::enable(apb1);
+
+        WindowWatchdog {
+            wwdg,
+            clocks,
+            t: 0x7F,
+            w: 0x7F,
+            wdgtb: 0,
+        }
+    }
+
+    /// Starts the watchdog
+    ///
+    /// The watchdog must be fed between `min_feed_us` and `timeout_us` microseconds after it was
+    /// last fed (or started). Both values are rounded to the resolution of the counter; use
+    /// `window_min_us` and `window_max_us` to get the values that were actually programmed.
+    pub fn start(&mut self, min_feed_us: u32, timeout_us: u32) {
+        assert!(min_feed_us < timeout_us);
+
+        // pick the smallest timer base (best resolution) that fits the timeout in the 6-bit
+        // counter
+        let mut wdgtb = 0;
+        while wdgtb < 3 && u64::from(timeout_us) > 64 * self.tick_ns(wdgtb) / 1_000 {
+            wdgtb += 1;
+        }
+        let tick_ns = self.tick_ns(wdgtb);
+        assert!(u64::from(timeout_us) <= 64 * tick_ns / 1_000, "timeout too long");
+
+        // NOTE the reset happens when the counter goes from 0x40 to 0x3F
+        let ticks = (u64::from(timeout_us) * 1_000 / tick_ns).max(1) as u8;
+        let t = 0x3F + ticks;
+
+        // round the opening of the window up so the watchdog is never fed too early
+        let closed = ((u64::from(min_feed_us) * 1_000 + tick_ns - 1) / tick_ns) as u8;
+        let w = t - closed.min(ticks - 1);
+
+        self.t = t;
+        self.w = w;
+        self.wdgtb = wdgtb;
+
+        self.wwdg
+            .cfr
+            .modify(|_, w| unsafe { w.wdgtb().bits(wdgtb).w().bits(self.w) });
+        self.wwdg
+            .cr
+            .write(|w| unsafe { w.t().bits(t).wdga().set_bit() });
+    }
+
+    /// Feeds the watchdog, restarting the timeout
+    ///
+    /// NOTE feeding the watchdog before the window opens resets the microcontroller
+    pub fn feed(&mut self) {
+        let t = self.t;
+        self.wwdg
+            .cr
+            .write(|w| unsafe { w.t().bits(t).wdga().set_bit() });
+    }
+
+    /// Returns the time, in microseconds, it takes the window to open after feeding
+    pub fn window_min_us(&self) -> u32 {
+        (u64::from(self.t - self.w) * self.tick_ns(self.wdgtb) / 1_000) as u32
+    }
+
+    /// Returns the time, in microseconds, after feeding at which the watchdog resets the
+    /// microcontroller
+    pub fn window_max_us(&self) -> u32 {
+        (u64::from(self.t - 0x3F) * self.tick_ns(self.wdgtb) / 1_000) as u32
+    }
+
+    /// Enables the early wakeup interrupt, which fires one tick before the reset
+    pub fn listen_early_wakeup(&mut self) {
+        self.wwdg.cfr.modify(|_, w| w.ewi().set_bit());
+    }
+
+    /// Clears the early wakeup interrupt flag
+    pub fn clear_early_wakeup(&mut self) {
+        self.wwdg.sr.write(|w| w.ewi().clear_bit());
+    }
+
+    // period of the counter clock: PCLK1 / 4096 / 2^WDGTB
+    fn tick_ns(&self, wdgtb: u8) -> u64 {
+        (4096u64 << wdgtb) * 1_000_000_000 / u64::from(self.clocks.pclk1().0)
+    }
+}